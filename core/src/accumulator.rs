@@ -0,0 +1,303 @@
+use serde::{Deserialize, Serialize};
+
+use crate::header::decode_header;
+use crate::range::{block_counts, BlockSummary, ProvenBlock, RangeError};
+use crate::{keccak256, Registry};
+
+/**
+ * Running state of continuous indexing: the counts of each registry contract, in registry
+ * order, and the untracked logs over every block since the first, up to the last block.
+ **/
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct AccumulatorState {
+    pub registry_hash: [u8; 32],
+    pub first_block: u64,
+    pub last_block: u64,
+    pub last_block_hash: [u8; 32],
+    pub counts: Vec<u32>,
+    pub untracked_logs: u64,
+}
+
+/**
+ * The outputs of the previous step, `None` to start a new chain, and the block that extends
+ * it, verified against `accumulator_image_id` and `block_image_id` by the caller.
+ **/
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct AccumulatorInputs {
+    pub block_image_id: [u32; 8],
+    pub accumulator_image_id: [u32; 8],
+    pub previous: Option<AccumulatorOutputs>,
+    pub block: BlockSummary,
+    pub registry: Registry,
+}
+
+/**
+ * Inputs of the accumulator guest: the journals of the previous step and of the block's
+ * per-block proof. The guest verifies them against `accumulator_image_id` and
+ * `block_image_id` with `env::verify`, so neither is taken on trust.
+ **/
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct ProvenAccumulatorInputs {
+    pub block_image_id: [u32; 8],
+    pub accumulator_image_id: [u32; 8],
+    pub previous: Option<Vec<u8>>,
+    pub block: ProvenBlock,
+    pub registry: Registry,
+}
+
+/**
+ * Committed by the accumulator guest. `previous` is the commitment of the state it extended,
+ * zero for the first block of a chain, so that consecutive journals link up: the `previous`
+ * of each one is the `commitment` of the one before. Every step of a chain commits the same
+ * image IDs, a verifier checks they are the per-block and accumulator guests it trusts.
+ **/
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct AccumulatorOutputs {
+    pub block_image_id: [u32; 8],
+    pub accumulator_image_id: [u32; 8],
+    pub previous: [u8; 32],
+    pub commitment: [u8; 32],
+    pub state: AccumulatorState,
+}
+
+impl AccumulatorState {
+    /**
+     * Keccak of `registry hash || first block || last block || last block hash || counts ||
+     * untracked logs`, integers big endian.
+     **/
+    pub fn commitment(&self) -> [u8; 32] {
+        let mut bytes = Vec::with_capacity(2 * 32 + 3 * 8 + self.counts.len() * 4);
+        bytes.extend_from_slice(&self.registry_hash);
+        bytes.extend_from_slice(&self.first_block.to_be_bytes());
+        bytes.extend_from_slice(&self.last_block.to_be_bytes());
+        bytes.extend_from_slice(&self.last_block_hash);
+        for count in &self.counts {
+            bytes.extend_from_slice(&count.to_be_bytes());
+        }
+        bytes.extend_from_slice(&self.untracked_logs.to_be_bytes());
+
+        keccak256(&bytes)
+    }
+}
+
+/**
+ * Add a block to the previous state. The block must be the child of the last block of the
+ * state and be proven with the state's registry, and the previous step must have verified
+ * its inputs against the same image IDs. Like `range::aggregate`, the outputs are taken as
+ * given, the caller checks the proofs.
+ **/
+pub fn extend(inputs: &AccumulatorInputs) -> Result<AccumulatorOutputs, RangeError> {
+    let header = decode_header(&inputs.block.header)
+        .map_err(|error| RangeError::InvalidHeader { index: 0, error })?;
+    inputs
+        .registry
+        .validate()
+        .map_err(RangeError::InvalidRegistry)?;
+    let registry_hash = inputs.registry.hash();
+
+    if header.receipts_root != inputs.block.outputs.root {
+        return Err(RangeError::ReceiptsRootMismatch {
+            block: header.number,
+        });
+    }
+    if inputs.block.outputs.registry_hash != registry_hash {
+        return Err(RangeError::RegistryMismatch {
+            block: header.number,
+        });
+    }
+
+    let counts = block_counts(header.number, &inputs.block.outputs, &inputs.registry)?;
+    let untracked_logs = inputs.block.outputs.untracked_logs as u64;
+
+    let (previous, state) = match &inputs.previous {
+        None => (
+            [0u8; 32],
+            AccumulatorState {
+                registry_hash,
+                first_block: header.number,
+                last_block: header.number,
+                last_block_hash: header.hash,
+                counts,
+                untracked_logs,
+            },
+        ),
+        Some(previous) => {
+            if previous.block_image_id != inputs.block_image_id
+                || previous.accumulator_image_id != inputs.accumulator_image_id
+            {
+                return Err(RangeError::ImageIdMismatch {
+                    block: header.number,
+                });
+            }
+            let previous = &previous.state;
+            if previous.registry_hash != registry_hash {
+                return Err(RangeError::RegistryMismatch {
+                    block: header.number,
+                });
+            }
+            if header.number != previous.last_block + 1 {
+                return Err(RangeError::NotConsecutive {
+                    expected: previous.last_block + 1,
+                    found: header.number,
+                });
+            }
+            if header.parent_hash != previous.last_block_hash {
+                return Err(RangeError::ParentMismatch {
+                    block: header.number,
+                });
+            }
+
+            // A state with fewer counts than the registry would drop contracts in the zip.
+            if previous.counts.len() != counts.len() {
+                return Err(RangeError::CountsMismatch {
+                    block: header.number,
+                    expected: counts.len(),
+                    found: previous.counts.len(),
+                });
+            }
+            let overflow = || RangeError::CountOverflow {
+                block: header.number,
+            };
+            let counts = previous
+                .counts
+                .iter()
+                .zip(&counts)
+                .map(|(previous, count)| previous.checked_add(*count))
+                .collect::<Option<Vec<u32>>>()
+                .ok_or_else(overflow)?;
+            let untracked_logs = previous
+                .untracked_logs
+                .checked_add(untracked_logs)
+                .ok_or_else(overflow)?;
+
+            let state = AccumulatorState {
+                registry_hash,
+                first_block: previous.first_block,
+                last_block: header.number,
+                last_block_hash: header.hash,
+                counts,
+                untracked_logs,
+            };
+            (previous.commitment(), state)
+        }
+    };
+
+    Ok(AccumulatorOutputs {
+        block_image_id: inputs.block_image_id,
+        accumulator_image_id: inputs.accumulator_image_id,
+        previous,
+        commitment: state.commitment(),
+        state,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::header::encode_list;
+    use crate::Outputs;
+
+    fn registry() -> Registry {
+        Registry::new(vec![
+            ("0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2".to_string(), 2),
+            ("0xd6a347c998109ac87ccb323f78960c91bbe911cd".to_string(), 3),
+        ])
+        .unwrap()
+    }
+
+    fn block(parent_hash: [u8; 32], number: u8, product_tree_root: u128) -> BlockSummary {
+        let outputs = Outputs {
+            root: [number; 32],
+            product_tree_hash: [0; 32],
+            product_tree_root: product_tree_root.to_be_bytes(),
+            registry_hash: registry().hash(),
+            untracked_logs: 1,
+        };
+        let mut fields = vec![parent_hash.to_vec(); 5];
+        fields.extend([outputs.root.to_vec(), vec![0; 256], vec![], vec![number]]);
+        fields.extend([vec![], vec![], vec![], vec![], vec![], vec![0; 8]]);
+        BlockSummary {
+            header: encode_list(&fields),
+            outputs,
+        }
+    }
+
+    fn inputs(previous: Option<AccumulatorOutputs>, block: BlockSummary) -> AccumulatorInputs {
+        AccumulatorInputs {
+            block_image_id: [1; 8],
+            accumulator_image_id: [2; 8],
+            previous,
+            block,
+            registry: registry(),
+        }
+    }
+
+    #[test]
+    fn test_extend_chain() {
+        let first = block([0xff; 32], 1, 12);
+        let outputs = extend(&inputs(None, first.clone())).unwrap();
+        assert_eq!(outputs.previous, [0; 32]);
+        assert_eq!(outputs.state.counts, vec![2, 1]);
+        assert_eq!(outputs.commitment, outputs.state.commitment());
+        assert_eq!(
+            (outputs.block_image_id, outputs.accumulator_image_id),
+            ([1; 8], [2; 8])
+        );
+
+        let second = block(keccak256(&first.header), 2, 6);
+        let next = extend(&inputs(Some(outputs.clone()), second.clone())).unwrap();
+        assert_eq!(next.previous, outputs.commitment);
+        assert_eq!(next.state.counts, vec![3, 2]);
+        assert_eq!(next.state.untracked_logs, 2);
+        assert_eq!((next.state.first_block, next.state.last_block), (1, 2));
+
+        // Not the child of block 1.
+        let orphan = block([0xee; 32], 2, 6);
+        assert_eq!(
+            extend(&inputs(Some(outputs.clone()), orphan)),
+            Err(RangeError::ParentMismatch { block: 2 })
+        );
+
+        let skipped = block(keccak256(&first.header), 3, 6);
+        assert_eq!(
+            extend(&inputs(Some(outputs.clone()), skipped)),
+            Err(RangeError::NotConsecutive {
+                expected: 2,
+                found: 3
+            })
+        );
+
+        // A previous step that verified its inputs against other guests.
+        let mut foreign = inputs(Some(outputs), second);
+        foreign.accumulator_image_id = [7; 8];
+        assert_eq!(
+            extend(&foreign),
+            Err(RangeError::ImageIdMismatch { block: 2 })
+        );
+    }
+
+    #[test]
+    fn test_extend_checks_counts() {
+        let first = block([0xff; 32], 1, 12);
+        let outputs = extend(&inputs(None, first.clone())).unwrap();
+        let second = block(keccak256(&first.header), 2, 6);
+
+        let mut short = outputs.clone();
+        short.state.counts.pop();
+        assert_eq!(
+            extend(&inputs(Some(short), second.clone())),
+            Err(RangeError::CountsMismatch {
+                block: 2,
+                expected: 2,
+                found: 1
+            })
+        );
+
+        let mut full = outputs;
+        full.state.counts[1] = u32::MAX;
+        assert_eq!(
+            extend(&inputs(Some(full), second)),
+            Err(RangeError::CountOverflow { block: 2 })
+        );
+    }
+}
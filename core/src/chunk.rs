@@ -0,0 +1,822 @@
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::header::encode_length;
+use crate::registry::RegistryErrors;
+use crate::{build_product_tree, hash_branch, hash_leaf, keccak256, BinaryTree};
+use crate::{Inputs, Node, Outputs, Registry};
+
+/**
+ * A subtrie of the receipts trie and the nibbles of its path from the root.
+ **/
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Subtrie {
+    pub path: Vec<u8>,
+    pub node: Node,
+}
+
+/**
+ * Part of a block proven by the chunk guest: some subtries of the receipts trie and a slice
+ * of the log addresses. The slice starts at `log_offset` and its product subtree is padded
+ * with 1s to `log_width` leaves, a power of 2. Chunks past the last log have a width of 0.
+ **/
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct ChunkInputs {
+    pub subtries: Vec<Subtrie>,
+    pub log_addresses: Vec<String>,
+    pub log_offset: u32,
+    pub log_width: u32,
+    pub registry: Registry,
+}
+
+/**
+ * Committed by the chunk guest: the hash of each subtrie by path, and the commitment and
+ * value of the product subtree over the chunk's logs.
+ **/
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct ChunkOutputs {
+    pub subtries: Vec<(Vec<u8>, [u8; 32])>,
+    pub log_offset: u32,
+    pub log_width: u32,
+    pub product_tree_hash: [u8; 32],
+    pub product_tree_root: [u8; 16],
+    pub registry_hash: [u8; 32],
+    pub untracked_logs: u32,
+}
+
+/**
+ * Inputs of the combine guest: the top of the receipts trie, with `Node::Empty` where the
+ * subtries of the chunks go, and the journals of the chunk receipts, verified against
+ * `chunk_image_id`.
+ **/
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct CombineInputs {
+    pub chunk_image_id: [u32; 8],
+    pub top: Node,
+    pub chunks: Vec<Vec<u8>>,
+}
+
+/**
+ * Committed by the combine guest. `outputs` are those of a single proof of the block and come
+ * first, so the journal also decodes as plain `Outputs`. A verifier checks `chunk_image_id`
+ * is the chunk guest it trusts.
+ **/
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct ChunkedOutputs {
+    pub outputs: Outputs,
+    pub chunk_image_id: [u32; 8],
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum ChunkError {
+    NoChunks,
+    EmptyTrie,
+    InvalidLogWidth(u32),
+    TooManyLogs { chunk: u32 },
+    LogSliceMismatch { chunk: usize },
+    RegistryMismatch { chunk: usize },
+    DuplicateSubtrie(Vec<u8>),
+    MissingSubtrie(Vec<u8>),
+    UnusedSubtries(usize),
+    InvalidRegistry(RegistryErrors),
+    ProductOverflow,
+}
+
+impl Error for ChunkError {}
+
+impl fmt::Display for ChunkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ChunkError::NoChunks => write!(f, "no chunks to combine"),
+            ChunkError::EmptyTrie => write!(f, "empty receipts trie"),
+            ChunkError::InvalidLogWidth(width) => {
+                write!(f, "log width {} is not a power of 2", width)
+            }
+            ChunkError::TooManyLogs { chunk } => {
+                write!(f, "chunk at log {} has more logs than its width", chunk)
+            }
+            ChunkError::LogSliceMismatch { chunk } => {
+                write!(
+                    f,
+                    "log slice of chunk {} doesn't follow the previous one",
+                    chunk
+                )
+            }
+            ChunkError::RegistryMismatch { chunk } => {
+                write!(f, "chunk {} was proven with another registry", chunk)
+            }
+            ChunkError::DuplicateSubtrie(path) => {
+                write!(f, "subtrie at path {:x?} is in several chunks", path)
+            }
+            ChunkError::MissingSubtrie(path) => {
+                write!(f, "subtrie missing under path {:x?}", path)
+            }
+            ChunkError::UnusedSubtries(count) => {
+                write!(f, "{} subtries aren't part of the receipts trie", count)
+            }
+            ChunkError::InvalidRegistry(err) => write!(f, "{}", err),
+            ChunkError::ProductOverflow => write!(f, "product tree root overflows u128"),
+        }
+    }
+}
+
+/**
+ * Number of leaves, i.e. receipts, under a node.
+ **/
+pub fn leaf_count(node: &Node) -> usize {
+    match node {
+        Node::Branch { children, .. } => children.iter().map(|child| leaf_count(child)).sum(),
+        Node::Extension { child, .. } => leaf_count(child),
+        Node::Leaf(_) => 1,
+        Node::Empty => 0,
+    }
+}
+
+/**
+ * RLP encoding of an extension node: its nibbles, hex-prefix encoded, and the hash of its
+ * child. The child is a branch of receipts, never short enough to be inlined.
+ **/
+pub fn encode_extension(nibbles: &[u8], child: &[u8; 32]) -> Vec<u8> {
+    let (flag, rest) = match nibbles.len() % 2 {
+        1 => (0x10 | nibbles[0], &nibbles[1..]),
+        _ => (0, nibbles),
+    };
+    let mut path = vec![flag];
+    path.extend(rest.chunks(2).map(|pair| (pair[0] << 4) | pair[1]));
+
+    let mut payload = Vec::with_capacity(path.len() + 34);
+    // A single byte below 0x80 is its own encoding, the flag always is.
+    if path.len() > 1 {
+        encode_length(path.len(), 0x80, &mut payload);
+    }
+    payload.extend_from_slice(&path);
+    payload.push(0xa0);
+    payload.extend_from_slice(child);
+
+    let mut rlp = Vec::with_capacity(payload.len() + 1);
+    encode_length(payload.len(), 0xc0, &mut rlp);
+    rlp.extend_from_slice(&payload);
+    rlp
+}
+
+/**
+ * Hash of a node, the same as the per-block guest computes, except that the nodes at the
+ * paths of `subtries` take the given hash. `used` counts the subtries found.
+ **/
+fn hash_node(
+    node: &Node,
+    path: &mut Vec<u8>,
+    subtries: &HashMap<Vec<u8>, [u8; 32]>,
+    used: &mut usize,
+) -> Result<[u8; 32], ChunkError> {
+    if let Some(hash) = subtries.get(path) {
+        *used += 1;
+        return Ok(*hash);
+    }
+
+    match node {
+        Node::Branch {
+            children,
+            children_count,
+        } => {
+            let mut payload = Vec::with_capacity(16 * 33 + 1);
+            let mut count = 0;
+            for (nibble, child) in children.iter().enumerate() {
+                path.push(nibble as u8);
+                let is_subtrie = subtries.contains_key(path);
+                if is_subtrie || **child != Node::Empty {
+                    count += 1;
+                    payload.push(0xa0);
+                    payload.extend_from_slice(&hash_node(child, path, subtries, used)?);
+                } else {
+                    payload.push(0x80);
+                }
+                path.pop();
+            }
+            // Empty value.
+            payload.push(0x80);
+            // The top of a split trie keeps the child count of its branches.
+            if count != *children_count as usize {
+                return Err(ChunkError::MissingSubtrie(path.clone()));
+            }
+
+            let mut rlp = Vec::with_capacity(payload.len() + 3);
+            encode_length(payload.len(), 0xc0, &mut rlp);
+            rlp.extend_from_slice(&payload);
+            Ok(keccak256(&rlp))
+        }
+        Node::Extension { nibbles, child } => {
+            let depth = path.len();
+            path.extend_from_slice(nibbles);
+            if **child == Node::Empty && !subtries.contains_key(path) {
+                return Err(ChunkError::MissingSubtrie(path.clone()));
+            }
+            let child = hash_node(child, path, subtries, used)?;
+            path.truncate(depth);
+            Ok(keccak256(&encode_extension(nibbles, &child)))
+        }
+        Node::Leaf(leaf) => Ok(keccak256(leaf)),
+        Node::Empty => Err(ChunkError::EmptyTrie),
+    }
+}
+
+/**
+ * Hash of a trie, as committed by the per-block guest in `Outputs::root`.
+ **/
+pub fn node_hash(node: &Node) -> Result<[u8; 32], ChunkError> {
+    hash_node(node, &mut Vec::new(), &HashMap::new(), &mut 0)
+}
+
+/**
+ * Commitment of a product subtree of `width` leaves that are all 1, the padding of a tree.
+ **/
+fn ones_commitment(width: u32) -> [u8; 32] {
+    let mut hash = hash_leaf(1);
+    let mut width = width;
+    while width > 1 {
+        hash = hash_branch(&hash, &hash, 1);
+        width /= 2;
+    }
+    hash
+}
+
+/**
+ * Compute the outputs of a chunk. The product subtree is built like the per-block guest
+ * builds the whole tree, padded with 1s to the chunk's width.
+ **/
+pub fn chunk_outputs(inputs: &ChunkInputs) -> Result<ChunkOutputs, ChunkError> {
+    let subtries = inputs
+        .subtries
+        .iter()
+        .map(|subtrie| Ok((subtrie.path.clone(), node_hash(&subtrie.node)?)))
+        .collect::<Result<Vec<_>, ChunkError>>()?;
+
+    let width = inputs.log_width;
+    if (width != 0 && !width.is_power_of_two()) || !inputs.log_offset.is_multiple_of(width.max(1)) {
+        return Err(ChunkError::InvalidLogWidth(width));
+    }
+    if inputs.log_addresses.len() > width as usize {
+        return Err(ChunkError::TooManyLogs {
+            chunk: inputs.log_offset,
+        });
+    }
+
+    inputs
+        .registry
+        .validate()
+        .map_err(ChunkError::InvalidRegistry)?;
+    let contract_prime = inputs.registry.contract_primes();
+    let mut untracked_logs = 0;
+    let mut leaves = Vec::with_capacity(inputs.log_addresses.len().next_power_of_two());
+    for address in &inputs.log_addresses {
+        let prime = match contract_prime.get(address.as_str()) {
+            Some(prime) => *prime,
+            None => {
+                untracked_logs += 1;
+                1
+            }
+        };
+        leaves.push(Rc::new(RefCell::new(BinaryTree::Leaf { value: prime })));
+    }
+    let (product_tree_hash, product_tree_root) = if width == 0 {
+        ([0; 32], 1u128)
+    } else {
+        // The logs fill the leftmost subtree, every subtree right of it is all 1s. Only the
+        // subtree over the logs is built, the host picks the width and it may be large.
+        let mut subtree_width = leaves.len().next_power_of_two();
+        while leaves.len() < subtree_width {
+            leaves.push(Rc::new(RefCell::new(BinaryTree::Leaf { value: 1 })));
+        }
+        let tree = build_product_tree(leaves);
+        let tree = tree.borrow();
+        let (mut hash, value) = (tree.commitment(), tree.value());
+        let mut ones = ones_commitment(subtree_width as u32);
+        while subtree_width < width as usize {
+            hash = hash_branch(&hash, &ones, value);
+            ones = hash_branch(&ones, &ones, 1);
+            subtree_width *= 2;
+        }
+        (hash, value)
+    };
+
+    Ok(ChunkOutputs {
+        subtries,
+        log_offset: inputs.log_offset,
+        log_width: width,
+        product_tree_hash,
+        product_tree_root: product_tree_root.to_be_bytes(),
+        registry_hash: inputs.registry.hash(),
+        untracked_logs,
+    })
+}
+
+/**
+ * Combine the outputs of the chunks of a block into the outputs of a single proof of it.
+ * The subtries must fill the gaps of `top` exactly, and the chunks with logs come first,
+ * in order, with slices of the same width. Their product subtrees are the nodes of the
+ * block's product tree at that width, padded with subtrees of 1s to a power of 2.
+ **/
+pub fn combine(top: &Node, chunks: &[ChunkOutputs]) -> Result<Outputs, ChunkError> {
+    let first = chunks.first().ok_or(ChunkError::NoChunks)?;
+    let width = first.log_width;
+    if !width.is_power_of_two() {
+        return Err(ChunkError::InvalidLogWidth(width));
+    }
+
+    let mut subtries = HashMap::new();
+    let mut nodes = Vec::new();
+    let mut untracked_logs = 0;
+    for (index, chunk) in chunks.iter().enumerate() {
+        if chunk.registry_hash != first.registry_hash {
+            return Err(ChunkError::RegistryMismatch { chunk: index });
+        }
+        for (path, hash) in &chunk.subtries {
+            if subtries.insert(path.clone(), *hash).is_some() {
+                return Err(ChunkError::DuplicateSubtrie(path.clone()));
+            }
+        }
+
+        if chunk.log_width == 0 {
+            continue;
+        }
+        if chunk.log_width != width
+            || nodes.len() != index
+            || chunk.log_offset as u64 != index as u64 * width as u64
+        {
+            return Err(ChunkError::LogSliceMismatch { chunk: index });
+        }
+        nodes.push((
+            chunk.product_tree_hash,
+            u128::from_be_bytes(chunk.product_tree_root),
+        ));
+        untracked_logs += chunk.untracked_logs;
+    }
+
+    let mut used = 0;
+    let root = hash_node(top, &mut Vec::new(), &subtries, &mut used)?;
+    if used != subtries.len() {
+        return Err(ChunkError::UnusedSubtries(subtries.len() - used));
+    }
+
+    let padding = (ones_commitment(width), 1);
+    nodes.resize(nodes.len().next_power_of_two(), padding);
+    while nodes.len() > 1 {
+        nodes = nodes
+            .chunks(2)
+            .map(|pair| {
+                let value = pair[0]
+                    .1
+                    .checked_mul(pair[1].1)
+                    .ok_or(ChunkError::ProductOverflow)?;
+                Ok((hash_branch(&pair[0].0, &pair[1].0, value), value))
+            })
+            .collect::<Result<_, ChunkError>>()?;
+    }
+    let (product_tree_hash, product_tree_root) = nodes[0];
+
+    Ok(Outputs {
+        root,
+        product_tree_hash,
+        product_tree_root: product_tree_root.to_be_bytes(),
+        registry_hash: first.registry_hash,
+        untracked_logs,
+    })
+}
+
+/**
+ * Move the largest subtries of `node` with at most `max_leaves` leaves to `subtries`,
+ * leaving `Node::Empty` in their place.
+ **/
+fn split_node(
+    node: &Node,
+    path: &mut Vec<u8>,
+    max_leaves: usize,
+    subtries: &mut Vec<Subtrie>,
+) -> Node {
+    match node {
+        Node::Branch {
+            children,
+            children_count,
+        } if leaf_count(node) > max_leaves => {
+            let mut top: [Box<Node>; 16] = Default::default();
+            for (nibble, child) in children.iter().enumerate() {
+                path.push(nibble as u8);
+                *top[nibble] = split_node(child, path, max_leaves, subtries);
+                path.pop();
+            }
+            Node::Branch {
+                children: top,
+                children_count: *children_count,
+            }
+        }
+        Node::Extension { nibbles, child } if leaf_count(node) > max_leaves => {
+            let depth = path.len();
+            path.extend_from_slice(nibbles);
+            let child = split_node(child, path, max_leaves, subtries);
+            path.truncate(depth);
+            Node::Extension {
+                nibbles: nibbles.clone(),
+                child: Box::new(child),
+            }
+        }
+        Node::Empty => Node::Empty,
+        _ => {
+            subtries.push(Subtrie {
+                path: path.clone(),
+                node: node.clone(),
+            });
+            Node::Empty
+        }
+    }
+}
+
+/**
+ * Split the inputs of a block into about `chunk_count` chunks and the top of the trie above
+ * them. Subtries are grouped in trie order up to an even share of the receipts each, and the
+ * logs cut in slices of the smallest power of 2 that splits them in at most `chunk_count`.
+ **/
+pub fn split_inputs(inputs: &Inputs, chunk_count: usize) -> (Node, Vec<ChunkInputs>) {
+    let chunk_count = chunk_count.max(1);
+    let max_leaves = leaf_count(&inputs.root).div_ceil(chunk_count).max(1);
+
+    let mut subtries = Vec::new();
+    let top = split_node(&inputs.root, &mut Vec::new(), max_leaves, &mut subtries);
+    let mut groups: Vec<Vec<Subtrie>> = Vec::new();
+    let mut group_leaves = 0;
+    for subtrie in subtries {
+        let leaves = leaf_count(&subtrie.node);
+        match groups.last_mut() {
+            Some(group) if group_leaves + leaves <= max_leaves => group.push(subtrie),
+            _ => {
+                group_leaves = 0;
+                groups.push(vec![subtrie]);
+            }
+        }
+        group_leaves += leaves;
+    }
+
+    let logs = &inputs.log_addresses;
+    let width = logs.len().div_ceil(chunk_count).next_power_of_two();
+    let slices: Vec<&[String]> = if logs.is_empty() {
+        vec![&[]]
+    } else {
+        logs.chunks(width).collect()
+    };
+
+    let mut groups = groups.into_iter();
+    let chunks = (0..slices.len().max(groups.len()))
+        .map(|index| {
+            let slice = slices.get(index);
+            ChunkInputs {
+                subtries: groups.next().unwrap_or_default(),
+                log_addresses: slice.map_or_else(Vec::new, |slice| slice.to_vec()),
+                log_offset: slice.map_or(0, |_| (index * width) as u32),
+                log_width: slice.map_or(0, |_| width as u32),
+                registry: inputs.registry.clone(),
+            }
+        })
+        .collect();
+
+    (top, chunks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{map_leaves, JournalEncoding};
+
+    fn registry() -> Registry {
+        Registry::new(vec![
+            ("0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2".to_string(), 2),
+            ("0xd6a347c998109ac87ccb323f78960c91bbe911cd".to_string(), 3),
+        ])
+        .unwrap()
+    }
+
+    /**
+     * A trie of two levels of branches with `leaves` leaves.
+     **/
+    fn trie(leaves: usize) -> Node {
+        let mut root: [Box<Node>; 16] = Default::default();
+        for (nibble, child) in root.iter_mut().enumerate() {
+            let mut children: [Box<Node>; 16] = Default::default();
+            let mut count = 0;
+            for (index, leaf) in children.iter_mut().enumerate() {
+                if nibble * 16 + index < leaves {
+                    **leaf = Node::Leaf(vec![nibble as u8, index as u8, 0xaa]);
+                    count += 1;
+                }
+            }
+            if count > 0 {
+                **child = Node::Branch {
+                    children,
+                    children_count: count,
+                };
+            }
+        }
+        Node::Branch {
+            children_count: leaves.div_ceil(16) as u8,
+            children: root,
+        }
+    }
+
+    /**
+     * Outputs of a single proof of `inputs`, as the per-block guest computes them.
+     **/
+    fn single_outputs(inputs: &Inputs) -> Outputs {
+        let primes = inputs.registry.contract_primes();
+        let leaves = inputs
+            .log_addresses
+            .iter()
+            .map(|address| primes.get(address.as_str()).copied().unwrap_or(1))
+            .collect();
+        let tree = build_product_tree(map_leaves(leaves));
+        let tree = tree.borrow();
+        Outputs {
+            root: node_hash(&inputs.root).unwrap(),
+            product_tree_hash: tree.commitment(),
+            product_tree_root: tree.value().to_be_bytes(),
+            registry_hash: inputs.registry.hash(),
+            untracked_logs: inputs
+                .log_addresses
+                .iter()
+                .filter(|address| !primes.contains_key(address.as_str()))
+                .count() as u32,
+        }
+    }
+
+    fn inputs(leaves: usize, logs: usize) -> Inputs {
+        let addresses = [
+            "0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2",
+            "0xd6a347c998109ac87ccb323f78960c91bbe911cd",
+            "0x0000000000000000000000000000000000000001",
+        ];
+        Inputs {
+            root: trie(leaves),
+            log_addresses: (0..logs)
+                .map(|index| addresses[index % 7 % 3].to_string())
+                .collect(),
+            registry: registry(),
+            journal_encoding: JournalEncoding::Risc0,
+        }
+    }
+
+    /**
+     * Leaf node of a receipts trie: its remaining nibbles, hex-prefix encoded, and its value.
+     **/
+    fn encode_leaf(nibbles: &[u8], value: &[u8]) -> Vec<u8> {
+        let (flag, rest) = match nibbles.len() % 2 {
+            1 => (0x30 | nibbles[0], &nibbles[1..]),
+            _ => (0x20, nibbles),
+        };
+        let mut path = vec![flag];
+        path.extend(rest.chunks(2).map(|pair| (pair[0] << 4) | pair[1]));
+
+        let mut payload = Vec::new();
+        for item in [&path, value] {
+            if item.len() != 1 || item[0] >= 0x80 {
+                encode_length(item.len(), 0x80, &mut payload);
+            }
+            payload.extend_from_slice(item);
+        }
+        let mut rlp = Vec::new();
+        encode_length(payload.len(), 0xc0, &mut rlp);
+        rlp.extend_from_slice(&payload);
+        rlp
+    }
+
+    /**
+     * Trie of `entries`, nibbles of a key and a value, all keys sharing the first `depth`
+     * nibbles. None of the keys is a prefix of another, like RLP encoded indices.
+     **/
+    fn build_trie(entries: &[(Vec<u8>, Vec<u8>)], depth: usize) -> Node {
+        if let [(key, value)] = entries {
+            return Node::Leaf(encode_leaf(&key[depth..], value));
+        }
+        let first = &entries[0].0;
+        let shared = (depth..first.len())
+            .take_while(|&index| entries.iter().all(|(key, _)| key[index] == first[index]))
+            .count();
+        if shared > 0 {
+            return Node::Extension {
+                nibbles: first[depth..depth + shared].to_vec(),
+                child: Box::new(build_trie(entries, depth + shared)),
+            };
+        }
+
+        let mut children: [Box<Node>; 16] = Default::default();
+        let mut children_count = 0;
+        for (nibble, child) in children.iter_mut().enumerate() {
+            let group: Vec<_> = entries
+                .iter()
+                .filter(|(key, _)| key[depth] as usize == nibble)
+                .cloned()
+                .collect();
+            if !group.is_empty() {
+                **child = build_trie(&group, depth + 1);
+                children_count += 1;
+            }
+        }
+        Node::Branch {
+            children,
+            children_count,
+        }
+    }
+
+    /**
+     * Receipts trie of `count` receipts of 40 bytes, keyed by their RLP encoded index.
+     **/
+    fn receipts_trie(count: usize) -> Node {
+        let entries: Vec<_> = (0..count)
+            .map(|index| {
+                let mut key = Vec::new();
+                match index {
+                    0 => key.push(0x80),
+                    1..=0x7f => key.push(index as u8),
+                    _ => {
+                        let bytes = index.to_be_bytes();
+                        let skip = bytes.iter().take_while(|byte| **byte == 0).count();
+                        encode_length(bytes.len() - skip, 0x80, &mut key);
+                        key.extend_from_slice(&bytes[skip..]);
+                    }
+                }
+                let nibbles = key
+                    .iter()
+                    .flat_map(|byte| [byte >> 4, byte & 0xf])
+                    .collect();
+                (nibbles, vec![(index % 251) as u8; 40])
+            })
+            .collect();
+        build_trie(&entries, 0)
+    }
+
+    fn chunked_outputs(inputs: &Inputs, chunk_count: usize) -> Result<Outputs, ChunkError> {
+        let (top, chunks) = split_inputs(inputs, chunk_count);
+        let outputs = chunks
+            .iter()
+            .map(chunk_outputs)
+            .collect::<Result<Vec<_>, _>>()?;
+        combine(&top, &outputs)
+    }
+
+    #[test]
+    fn test_chunked_outputs_match_single() {
+        for (leaves, logs, chunk_count) in [
+            (200, 37, 4),
+            (200, 37, 1),
+            (3, 64, 8),
+            (40, 0, 3),
+            (1, 5, 2),
+            (256, 100, 16),
+        ] {
+            let inputs = inputs(leaves, logs);
+            assert_eq!(
+                chunked_outputs(&inputs, chunk_count).unwrap(),
+                single_outputs(&inputs),
+                "{} leaves, {} logs, {} chunks",
+                leaves,
+                logs,
+                chunk_count
+            );
+        }
+    }
+
+    #[test]
+    fn test_extension_nodes() {
+        // Roots of the same tries built by cita-trie. From 130 receipts on, the keys of
+        // 128 and over share nibbles and the trie has extension nodes.
+        for (count, root) in [
+            (
+                129,
+                "5ac99c0b49117972e0c4dfdce3a2f186766f106b33c631ba5aa61e94bddcbd92",
+            ),
+            (
+                130,
+                "726981f49c0adb04ad96beaa6bc7fd34ebde21192dec50c2729e6590cf87e366",
+            ),
+            (
+                300,
+                "ee35c5f95ede4571bcf78d587aeef7eb2e125791531240ba91cb10509d6c14a3",
+            ),
+        ] {
+            let mut inputs = inputs(0, 20);
+            inputs.root = receipts_trie(count);
+            let outputs = single_outputs(&inputs);
+            let root: Vec<u8> = (0..64)
+                .step_by(2)
+                .map(|index| u8::from_str_radix(&root[index..index + 2], 16).unwrap())
+                .collect();
+            assert_eq!(outputs.root.to_vec(), root);
+            for chunk_count in [1, 3, 16, 40] {
+                assert_eq!(chunked_outputs(&inputs, chunk_count).unwrap(), outputs);
+            }
+        }
+
+        // The subtrie under an extension is missing.
+        let inputs = Inputs {
+            root: receipts_trie(300),
+            ..inputs(0, 0)
+        };
+        let (top, chunks) = split_inputs(&inputs, 40);
+        let mut outputs: Vec<_> = chunks.iter().map(|c| chunk_outputs(c).unwrap()).collect();
+        outputs.pop();
+        assert!(matches!(
+            combine(&top, &outputs),
+            Err(ChunkError::MissingSubtrie(_))
+        ));
+    }
+
+    #[test]
+    fn test_wide_log_slice() {
+        let mut chunk = ChunkInputs {
+            subtries: vec![],
+            log_addresses: vec!["0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2".to_string(); 3],
+            log_offset: 0,
+            log_width: 64,
+            registry: registry(),
+        };
+        let mut leaves = vec![2; 3];
+        leaves.resize(64, 1);
+        let tree = build_product_tree(map_leaves(leaves));
+        let outputs = chunk_outputs(&chunk).unwrap();
+        assert_eq!(outputs.product_tree_hash, tree.borrow().commitment());
+        assert_eq!(outputs.product_tree_root, 8u128.to_be_bytes());
+
+        // The padding isn't built, the widest slice costs a few hashes per level.
+        chunk.log_width = 1 << 31;
+        assert_eq!(
+            chunk_outputs(&chunk).unwrap().product_tree_root,
+            8u128.to_be_bytes()
+        );
+    }
+
+    #[test]
+    fn test_split_inputs() {
+        let (top, chunks) = split_inputs(&inputs(200, 37), 4);
+        // 50 receipts a chunk at most and the subtries under the root have 16, so groups of 3.
+        assert_eq!(chunks.len(), 5);
+        for chunk in &chunks {
+            let receipts: usize = chunk.subtries.iter().map(|s| leaf_count(&s.node)).sum();
+            assert!(receipts <= 50);
+            assert!(chunk.subtries.iter().all(|s| s.path.len() == 1));
+        }
+        assert_eq!(leaf_count(&top), 0);
+        // 37 logs in slices of 16.
+        let widths: Vec<_> = chunks.iter().map(|c| c.log_width).collect();
+        assert_eq!(widths, vec![16, 16, 16, 0, 0]);
+        assert_eq!(chunks[2].log_addresses.len(), 5);
+    }
+
+    #[test]
+    fn test_combine_errors() {
+        let inputs = inputs(200, 37);
+        let (top, chunks) = split_inputs(&inputs, 4);
+        let outputs: Vec<_> = chunks.iter().map(|c| chunk_outputs(c).unwrap()).collect();
+
+        // A missing chunk leaves a gap in the trie.
+        assert_eq!(
+            combine(&top, &outputs[1..]),
+            Err(ChunkError::LogSliceMismatch { chunk: 0 })
+        );
+        let mut missing = outputs.clone();
+        missing.pop();
+        assert!(matches!(
+            combine(&top, &missing),
+            Err(ChunkError::MissingSubtrie(_))
+        ));
+
+        let mut swapped = outputs.clone();
+        swapped.swap(0, 1);
+        assert_eq!(
+            combine(&top, &swapped),
+            Err(ChunkError::LogSliceMismatch { chunk: 0 })
+        );
+
+        let mut duplicate = outputs.clone();
+        duplicate.push(outputs[3].clone());
+        assert!(matches!(
+            combine(&top, &duplicate),
+            Err(ChunkError::DuplicateSubtrie(_))
+        ));
+
+        // Subtree roots whose product wraps are refused rather than committed.
+        let mut overflowing = outputs.clone();
+        overflowing[0].product_tree_root = (1u128 << 64).to_be_bytes();
+        overflowing[1].product_tree_root = (1u128 << 64).to_be_bytes();
+        assert_eq!(
+            combine(&top, &overflowing),
+            Err(ChunkError::ProductOverflow)
+        );
+
+        let mut other_registry = outputs;
+        other_registry[2].registry_hash = [0; 32];
+        assert_eq!(
+            combine(&top, &other_registry),
+            Err(ChunkError::RegistryMismatch { chunk: 2 })
+        );
+    }
+}
@@ -0,0 +1,232 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::keccak256;
+
+/**
+ * Fields of an RLP encoded block header that the range guest checks. The hash is the keccak
+ * of the encoding, so a header can't claim another block's hash.
+ **/
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BlockHeader {
+    pub hash: [u8; 32],
+    pub parent_hash: [u8; 32],
+    pub receipts_root: [u8; 32],
+    pub number: u64,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum HeaderError {
+    InvalidRlp,
+    TooFewFields(usize),
+    InvalidField(&'static str),
+}
+
+impl Error for HeaderError {}
+
+impl fmt::Display for HeaderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HeaderError::InvalidRlp => write!(f, "invalid RLP"),
+            HeaderError::TooFewFields(count) => {
+                write!(f, "expected at least 15 header fields, found {}", count)
+            }
+            HeaderError::InvalidField(name) => write!(f, "invalid header field {}", name),
+        }
+    }
+}
+
+// Positions in the header list, unchanged by the fields forks append at the end.
+const PARENT_HASH: usize = 0;
+const RECEIPTS_ROOT: usize = 5;
+const NUMBER: usize = 8;
+const LEGACY_FIELD_COUNT: usize = 15;
+
+/**
+ * Decode the fields the range guest needs from an RLP encoded header.
+ **/
+pub fn decode_header(rlp: &[u8]) -> Result<BlockHeader, HeaderError> {
+    let fields = decode_list(rlp)?;
+    if fields.len() < LEGACY_FIELD_COUNT {
+        return Err(HeaderError::TooFewFields(fields.len()));
+    }
+
+    let hash32 = |index: usize, name: &'static str| -> Result<[u8; 32], HeaderError> {
+        fields[index]
+            .try_into()
+            .map_err(|_| HeaderError::InvalidField(name))
+    };
+    let number = fields[NUMBER];
+    if number.len() > 8 || number.first() == Some(&0) {
+        return Err(HeaderError::InvalidField("number"));
+    }
+
+    Ok(BlockHeader {
+        hash: keccak256(rlp),
+        parent_hash: hash32(PARENT_HASH, "parentHash")?,
+        receipts_root: hash32(RECEIPTS_ROOT, "receiptsRoot")?,
+        number: number
+            .iter()
+            .fold(0u64, |number, byte| number << 8 | *byte as u64),
+    })
+}
+
+/**
+ * RLP encode a list of byte strings, the shape of a block header.
+ **/
+pub fn encode_list(items: &[Vec<u8>]) -> Vec<u8> {
+    let mut payload = Vec::new();
+    for item in items {
+        match item.as_slice() {
+            [byte] if *byte < 0x80 => payload.push(*byte),
+            bytes => {
+                encode_length(bytes.len(), 0x80, &mut payload);
+                payload.extend_from_slice(bytes);
+            }
+        }
+    }
+
+    let mut out = Vec::with_capacity(payload.len() + 9);
+    encode_length(payload.len(), 0xc0, &mut out);
+    out.extend_from_slice(&payload);
+    out
+}
+
+pub(crate) fn encode_length(len: usize, offset: u8, out: &mut Vec<u8>) {
+    if len < 56 {
+        out.push(offset + len as u8);
+    } else {
+        let bytes = len.to_be_bytes();
+        let skip = bytes.iter().take_while(|byte| **byte == 0).count();
+        out.push(offset + 55 + (bytes.len() - skip) as u8);
+        out.extend_from_slice(&bytes[skip..]);
+    }
+}
+
+/**
+ * Decode an RLP list of byte strings, rejecting nested lists and trailing bytes.
+ **/
+pub fn decode_list(rlp: &[u8]) -> Result<Vec<&[u8]>, HeaderError> {
+    let (is_list, payload, rest) = decode_item(rlp)?;
+    if !is_list || !rest.is_empty() {
+        return Err(HeaderError::InvalidRlp);
+    }
+
+    let mut items = Vec::new();
+    let mut payload = payload;
+    while !payload.is_empty() {
+        let (is_list, item, rest) = decode_item(payload)?;
+        if is_list {
+            return Err(HeaderError::InvalidRlp);
+        }
+        items.push(item);
+        payload = rest;
+    }
+
+    Ok(items)
+}
+
+/**
+ * Split the first item off `bytes`: whether it's a list, its payload and the remaining bytes.
+ **/
+fn decode_item(bytes: &[u8]) -> Result<(bool, &[u8], &[u8]), HeaderError> {
+    let (&prefix, rest) = bytes.split_first().ok_or(HeaderError::InvalidRlp)?;
+    let (is_list, len, rest) = match prefix {
+        0x00..=0x7f => return Ok((false, &bytes[..1], rest)),
+        0x80..=0xb7 => (false, (prefix - 0x80) as usize, rest),
+        0xb8..=0xbf => {
+            let (len, rest) = decode_length(rest, (prefix - 0xb7) as usize)?;
+            (false, len, rest)
+        }
+        0xc0..=0xf7 => (true, (prefix - 0xc0) as usize, rest),
+        0xf8..=0xff => {
+            let (len, rest) = decode_length(rest, (prefix - 0xf7) as usize)?;
+            (true, len, rest)
+        }
+    };
+    if rest.len() < len {
+        return Err(HeaderError::InvalidRlp);
+    }
+
+    Ok((is_list, &rest[..len], &rest[len..]))
+}
+
+fn decode_length(bytes: &[u8], len_len: usize) -> Result<(usize, &[u8]), HeaderError> {
+    if bytes.len() < len_len || len_len > 8 || bytes[0] == 0 {
+        return Err(HeaderError::InvalidRlp);
+    }
+    let len = bytes[..len_len]
+        .iter()
+        .fold(0u64, |len, byte| len << 8 | *byte as u64);
+    if len < 56 {
+        return Err(HeaderError::InvalidRlp);
+    }
+
+    Ok((len as usize, &bytes[len_len..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(number: &[u8]) -> Vec<Vec<u8>> {
+        let mut fields = vec![vec![0x11; 32], vec![0x22; 32], vec![0x33; 20]];
+        fields.extend([vec![0x44; 32], vec![0x55; 32], vec![0x66; 32], vec![0; 256]]);
+        fields.extend([
+            vec![],
+            number.to_vec(),
+            vec![0x01, 0xc9, 0xc3, 0x80],
+            vec![0x7f],
+        ]);
+        fields.extend([
+            vec![0x64, 0xf1, 0xc2, 0xa3],
+            vec![],
+            vec![0x77; 32],
+            vec![0; 8],
+        ]);
+        fields
+    }
+
+    #[test]
+    fn test_decode_header() {
+        let rlp = encode_list(&fields(&[0x01, 0x12, 0xa8, 0x80]));
+        // Long list: 0xf9 and a 2 byte length.
+        assert_eq!(rlp[0], 0xf9);
+        assert_eq!(decode_list(&rlp).unwrap().len(), 15);
+
+        let header = decode_header(&rlp).unwrap();
+        assert_eq!(header.hash, keccak256(&rlp));
+        assert_eq!(header.parent_hash, [0x11; 32]);
+        assert_eq!(header.receipts_root, [0x66; 32]);
+        assert_eq!(header.number, 18_000_000);
+    }
+
+    #[test]
+    fn test_decode_header_errors() {
+        let rlp = encode_list(&fields(&[0x01]));
+        assert_eq!(
+            decode_header(&rlp[..rlp.len() - 1]),
+            Err(HeaderError::InvalidRlp)
+        );
+
+        let mut trailing = rlp.clone();
+        trailing.push(0x80);
+        assert_eq!(decode_header(&trailing), Err(HeaderError::InvalidRlp));
+
+        assert_eq!(
+            decode_header(&encode_list(&fields(&[0x01])[..14])),
+            Err(HeaderError::TooFewFields(14))
+        );
+        assert_eq!(
+            decode_header(&encode_list(&fields(&[0x00, 0x01]))),
+            Err(HeaderError::InvalidField("number"))
+        );
+
+        let mut short_root = fields(&[0x01]);
+        short_root[5].pop();
+        assert_eq!(
+            decode_header(&encode_list(&short_root)),
+            Err(HeaderError::InvalidField("receiptsRoot"))
+        );
+    }
+}
@@ -0,0 +1,103 @@
+use serde::{Deserialize, Serialize};
+
+use crate::Outputs;
+
+/**
+ * How the guest commits `Outputs` to the journal:
+ * - `Risc0`: risc0's word based serde, decoded with `risc0_zkvm::serde::from_slice`,
+ * - `Abi`: `abi.encode(bytes32 root, bytes32 productTreeHash, uint128 productTreeRoot,
+ *   bytes32 registryHash, uint32 untrackedLogs)`, decoded on chain with `abi.decode`.
+ **/
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub enum JournalEncoding {
+    #[default]
+    Risc0,
+    Abi,
+}
+
+/**
+ * Length of an ABI encoded journal: five static 32 byte words. A risc0 encoded journal is
+ * 113 words (452 bytes) long, so the two encodings are told apart by their length.
+ **/
+pub const ABI_JOURNAL_LEN: usize = 5 * 32;
+
+impl Outputs {
+    pub fn abi_encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(ABI_JOURNAL_LEN);
+        bytes.extend_from_slice(&self.root);
+        bytes.extend_from_slice(&self.product_tree_hash);
+        bytes.extend_from_slice(&[0u8; 16]);
+        bytes.extend_from_slice(&self.product_tree_root);
+        bytes.extend_from_slice(&self.registry_hash);
+        bytes.extend_from_slice(&[0u8; 28]);
+        bytes.extend_from_slice(&self.untracked_logs.to_be_bytes());
+        bytes
+    }
+
+    /**
+     * Decode an ABI encoded journal. Returns `None` if the length is wrong or the padding of
+     * the integers isn't zero, as `abi.decode` would revert.
+     **/
+    pub fn abi_decode(bytes: &[u8]) -> Option<Outputs> {
+        if bytes.len() != ABI_JOURNAL_LEN
+            || bytes[64..80].iter().any(|b| *b != 0)
+            || bytes[128..156].iter().any(|b| *b != 0)
+        {
+            return None;
+        }
+
+        Some(Outputs {
+            root: bytes[..32].try_into().ok()?,
+            product_tree_hash: bytes[32..64].try_into().ok()?,
+            product_tree_root: bytes[80..96].try_into().ok()?,
+            registry_hash: bytes[96..128].try_into().ok()?,
+            untracked_logs: u32::from_be_bytes(bytes[156..].try_into().ok()?),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outputs() -> Outputs {
+        let mut root = [0u8; 32];
+        let mut registry_hash = [0u8; 32];
+        for i in 0..32 {
+            root[i] = i as u8;
+            registry_hash[i] = 32 + i as u8;
+        }
+        Outputs {
+            root,
+            product_tree_hash: [0xaa; 32],
+            product_tree_root: (64u128 * 81 * 25).to_be_bytes(),
+            registry_hash,
+            untracked_logs: 3,
+        }
+    }
+
+    #[test]
+    fn test_abi_encode_vector() {
+        let expected = concat!(
+            "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f",
+            "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa",
+            "000000000000000000000000000000000000000000000000000000000001fa40",
+            "202122232425262728292a2b2c2d2e2f303132333435363738393a3b3c3d3e3f",
+            "0000000000000000000000000000000000000000000000000000000000000003",
+        );
+        let encoded = outputs().abi_encode();
+        assert_eq!(encoded.len(), ABI_JOURNAL_LEN);
+        let hex: String = encoded.iter().map(|b| format!("{:02x}", b)).collect();
+        assert_eq!(hex, expected);
+    }
+
+    #[test]
+    fn test_abi_decode() {
+        let mut encoded = outputs().abi_encode();
+        assert_eq!(Outputs::abi_decode(&encoded), Some(outputs()));
+
+        assert_eq!(Outputs::abi_decode(&encoded[1..]), None);
+        encoded[70] = 1;
+        assert_eq!(Outputs::abi_decode(&encoded), None);
+    }
+}
@@ -1,11 +1,26 @@
 use serde::{Deserialize, Serialize};
 use std::cell::RefCell;
+use std::ops::Deref;
 use std::rc::Rc;
+use tiny_keccak::{Hasher, Keccak};
+
+pub mod accumulator;
+pub mod chunk;
+pub mod header;
+pub mod journal;
+pub mod multiproof;
+pub mod range;
+pub mod registry;
+
+pub use journal::JournalEncoding;
+pub use registry::{Contract, Registry};
 
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
 pub struct Inputs {
     pub root: Node,
     pub log_addresses: Vec<String>,
+    pub registry: Registry,
+    pub journal_encoding: JournalEncoding,
 }
 
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
@@ -13,6 +28,10 @@ pub struct Outputs {
     pub root: [u8; 32],
     pub product_tree_hash: [u8; 32],
     pub product_tree_root: [u8; 16], // 16 bytes for u128
+    pub registry_hash: [u8; 32],
+    // Logs from contracts outside the registry. Counted instead of mapped to an "other" prime,
+    // which would overflow the u128 product tree root after a few dozen logs.
+    pub untracked_logs: u32,
 }
 
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize, Default)]
@@ -24,6 +43,11 @@ pub enum Node {
     Leaf(Vec<u8>),
     #[default]
     Empty,
+    /// Nibbles shared by every key under `child`, a branch. Last so older inputs still decode.
+    Extension {
+        nibbles: Vec<u8>,
+        child: Box<Node>,
+    },
 }
 
 pub enum BinaryTree {
@@ -61,6 +85,41 @@ impl BinaryTree {
             }
         }
     }
+
+    /**
+     * Keccak commitment of the tree, as committed by the guest in `Outputs::product_tree_hash`.
+     * Leaves hash their big endian value, branches hash `left || right || value`.
+     **/
+    pub fn commitment(&self) -> [u8; 32] {
+        match self {
+            BinaryTree::Leaf { value } => hash_leaf(*value),
+            BinaryTree::Branch { left, right, value } => hash_branch(
+                &left.borrow().deref().commitment(),
+                &right.borrow().deref().commitment(),
+                *value,
+            ),
+        }
+    }
+}
+
+pub fn keccak256(bytes: &[u8]) -> [u8; 32] {
+    let mut output = [0u8; 32];
+    let mut hasher = Keccak::v256();
+    hasher.update(bytes);
+    hasher.finalize(&mut output);
+    output
+}
+
+pub fn hash_leaf(value: u128) -> [u8; 32] {
+    keccak256(&value.to_be_bytes())
+}
+
+pub fn hash_branch(left: &[u8; 32], right: &[u8; 32], value: u128) -> [u8; 32] {
+    let mut bytes = [0u8; 2 * 32 + 16];
+    bytes[..32].copy_from_slice(left);
+    bytes[32..64].copy_from_slice(right);
+    bytes[64..].copy_from_slice(&value.to_be_bytes());
+    keccak256(&bytes)
 }
 
 /**
@@ -86,6 +145,8 @@ pub fn map_leaves(leaves: Vec<u128>) -> Vec<Rc<RefCell<BinaryTree>>> {
  * Build a product tree from a list of leaves.
  * Leaves len must be a power of 2. Complete with 1s if not (see map_leaves function).
  * The product tree is a binary tree where each node is the product of its children.
+ * Panics when a product overflows a u128: the root would wrap and every count read from it
+ * would be wrong, so the guest refuses to prove such a block.
  **/
 pub fn build_product_tree(leaves: Vec<Rc<RefCell<BinaryTree>>>) -> Rc<RefCell<BinaryTree>> {
     if leaves.len() == 1 {
@@ -97,7 +158,11 @@ pub fn build_product_tree(leaves: Vec<Rc<RefCell<BinaryTree>>>) -> Rc<RefCell<Bi
         let left = leaves[i].clone();
         let right = leaves[i + 1].clone();
 
-        let value = left.borrow().value() * right.borrow().value();
+        let value = left
+            .borrow()
+            .value()
+            .checked_mul(right.borrow().value())
+            .expect("product tree root overflows u128");
 
         let branch = BinaryTree::Branch { value, left, right };
 
@@ -107,17 +172,21 @@ pub fn build_product_tree(leaves: Vec<Rc<RefCell<BinaryTree>>>) -> Rc<RefCell<Bi
     build_product_tree(branches)
 }
 
+/// Largest count `factor_of_n` returns, 2^128 is the largest power of 2 a u128 holds.
+pub const MAX_FACTOR_COUNT: u8 = 128;
+
 /**
  * Calculate the number of times a number is divisible by a given factor.
  * This function allows you to query the product tree to determine
  * how many times an events associated prime happened in a block, given the product tree root from the block.
+ * A root of 0 is divisible by anything and gives 0, callers reject it as a root.
  **/
-pub fn factor_of_n(val: u128, n: u8) -> u8 {
+pub fn factor_of_n(val: u128, n: u128) -> u8 {
     let mut count = 0;
     let mut val = val;
-    while val % n as u128 == 0 {
+    while val != 0 && count < MAX_FACTOR_COUNT && val.is_multiple_of(n) {
         count += 1;
-        val /= n as u128;
+        val /= n;
     }
 
     count
@@ -0,0 +1,466 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::{hash_branch, hash_leaf};
+
+const FORMAT_VERSION: u8 = 1;
+const NODE_SIZE: usize = 32 + 16;
+
+/**
+ * A node of the product tree as seen by a verifier: its commitment and its value.
+ **/
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ProofNode {
+    pub hash: [u8; 32],
+    pub value: u128,
+}
+
+/**
+ * Inclusion proof for several leaves of the same product tree.
+ * Only the siblings the verifier cannot compute from the proven leaves are kept,
+ * ordered bottom-up and left to right, so paths sharing ancestors share their nodes.
+ **/
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MultiProof {
+    pub leaf_count: u32,
+    pub indices: Vec<u32>,
+    pub values: Vec<u128>,
+    pub siblings: Vec<ProofNode>,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum ProofError {
+    NoIndices,
+    IndexOutOfRange(usize),
+    InvalidLeafCount(u32),
+    ValuesMismatch,
+    MissingSibling,
+    UnusedSiblings(usize),
+    RootMismatch,
+    Malformed(&'static str),
+    UnsupportedVersion(u8),
+    ProductOverflow,
+}
+
+impl Error for ProofError {}
+
+impl fmt::Display for ProofError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProofError::NoIndices => write!(f, "proof error: no leaf indices"),
+            ProofError::IndexOutOfRange(idx) => {
+                write!(f, "proof error: leaf index {} out of range", idx)
+            }
+            ProofError::InvalidLeafCount(count) => {
+                write!(f, "proof error: leaf count {} is not a power of 2", count)
+            }
+            ProofError::ValuesMismatch => {
+                write!(f, "proof error: leaf values do not match indices")
+            }
+            ProofError::MissingSibling => write!(f, "proof error: missing sibling"),
+            ProofError::UnusedSiblings(count) => {
+                write!(f, "proof error: {} unused siblings", count)
+            }
+            ProofError::RootMismatch => write!(f, "proof error: root mismatch"),
+            ProofError::Malformed(reason) => write!(f, "proof error: malformed, {}", reason),
+            ProofError::UnsupportedVersion(version) => {
+                write!(f, "proof error: unsupported version {}", version)
+            }
+            ProofError::ProductOverflow => {
+                write!(f, "proof error: product of the nodes overflows u128")
+            }
+        }
+    }
+}
+
+impl MultiProof {
+    /**
+     * Generate a proof for the leaves at `indices`.
+     * `leaves` are the product tree leaves before padding, they are completed with 1s
+     * the same way the guest does (see map_leaves function).
+     **/
+    pub fn generate(leaves: &[u128], indices: &[usize]) -> Result<Self, ProofError> {
+        let leaf_count = leaves.len().next_power_of_two();
+        let mut indices = indices.to_vec();
+        indices.sort_unstable();
+        indices.dedup();
+
+        if indices.is_empty() {
+            return Err(ProofError::NoIndices);
+        }
+        if let Some(idx) = indices.iter().find(|idx| **idx >= leaf_count) {
+            return Err(ProofError::IndexOutOfRange(*idx));
+        }
+
+        let mut layer: Vec<ProofNode> = (0..leaf_count)
+            .map(|i| {
+                let value = leaves.get(i).copied().unwrap_or(1);
+                ProofNode {
+                    hash: hash_leaf(value),
+                    value,
+                }
+            })
+            .collect();
+
+        let values = indices.iter().map(|idx| layer[*idx].value).collect();
+        let mut siblings = Vec::new();
+        let mut known = indices.clone();
+
+        while layer.len() > 1 {
+            let mut i = 0;
+            while i < known.len() {
+                let idx = known[i];
+                let sibling = idx ^ 1;
+                if known.get(i + 1) == Some(&sibling) {
+                    i += 1;
+                } else {
+                    siblings.push(layer[sibling]);
+                }
+                i += 1;
+            }
+
+            known = known.iter().map(|idx| idx / 2).collect();
+            known.dedup();
+
+            layer = layer
+                .chunks(2)
+                .map(|pair| join(&pair[0], &pair[1]))
+                .collect::<Result<_, _>>()?;
+        }
+
+        Ok(MultiProof {
+            leaf_count: leaf_count as u32,
+            indices: indices.iter().map(|idx| *idx as u32).collect(),
+            values,
+            siblings,
+        })
+    }
+
+    /**
+     * Recompute the product tree root from the proven leaves and siblings and compare it
+     * with the `product_tree_hash` and `product_tree_root` committed in `Outputs`.
+     **/
+    pub fn verify(
+        &self,
+        product_tree_hash: &[u8; 32],
+        product_tree_root: u128,
+    ) -> Result<(), ProofError> {
+        let root = self.compute_root()?;
+        if &root.hash != product_tree_hash || root.value != product_tree_root {
+            return Err(ProofError::RootMismatch);
+        }
+
+        Ok(())
+    }
+
+    fn compute_root(&self) -> Result<ProofNode, ProofError> {
+        if !self.leaf_count.is_power_of_two() {
+            return Err(ProofError::InvalidLeafCount(self.leaf_count));
+        }
+        if self.indices.is_empty() {
+            return Err(ProofError::NoIndices);
+        }
+        if self.indices.len() != self.values.len() {
+            return Err(ProofError::ValuesMismatch);
+        }
+        if self.indices.windows(2).any(|w| w[0] >= w[1]) {
+            return Err(ProofError::Malformed("indices are not strictly increasing"));
+        }
+        if let Some(idx) = self.indices.iter().find(|idx| **idx >= self.leaf_count) {
+            return Err(ProofError::IndexOutOfRange(*idx as usize));
+        }
+
+        let mut known: Vec<(u32, ProofNode)> = self
+            .indices
+            .iter()
+            .zip(self.values.iter())
+            .map(|(idx, value)| {
+                let node = ProofNode {
+                    hash: hash_leaf(*value),
+                    value: *value,
+                };
+                (*idx, node)
+            })
+            .collect();
+        let mut siblings = self.siblings.iter();
+        let mut width = self.leaf_count;
+
+        while width > 1 {
+            let mut parents = Vec::with_capacity(known.len());
+            let mut i = 0;
+            while i < known.len() {
+                let (idx, node) = known[i];
+                let sibling = match known.get(i + 1) {
+                    Some((next, next_node)) if *next == idx ^ 1 => {
+                        i += 1;
+                        *next_node
+                    }
+                    _ => *siblings.next().ok_or(ProofError::MissingSibling)?,
+                };
+
+                let parent = if idx % 2 == 0 {
+                    join(&node, &sibling)?
+                } else {
+                    join(&sibling, &node)?
+                };
+                parents.push((idx / 2, parent));
+                i += 1;
+            }
+
+            known = parents;
+            width /= 2;
+        }
+
+        let unused = siblings.count();
+        if unused > 0 {
+            return Err(ProofError::UnusedSiblings(unused));
+        }
+
+        Ok(known[0].1)
+    }
+
+    /**
+     * Compact binary form:
+     * `version | leaf_count | index count | indices | values | sibling count | siblings`,
+     * integers are big endian, u32 for counts and indices, u128 for values.
+     **/
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(
+            1 + 4 + 4 + self.indices.len() * (4 + 16) + 4 + self.siblings.len() * NODE_SIZE,
+        );
+
+        bytes.push(FORMAT_VERSION);
+        bytes.extend_from_slice(&self.leaf_count.to_be_bytes());
+        bytes.extend_from_slice(&(self.indices.len() as u32).to_be_bytes());
+        for idx in &self.indices {
+            bytes.extend_from_slice(&idx.to_be_bytes());
+        }
+        for value in &self.values {
+            bytes.extend_from_slice(&value.to_be_bytes());
+        }
+        bytes.extend_from_slice(&(self.siblings.len() as u32).to_be_bytes());
+        for sibling in &self.siblings {
+            bytes.extend_from_slice(&sibling.hash);
+            bytes.extend_from_slice(&sibling.value.to_be_bytes());
+        }
+
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ProofError> {
+        let mut reader = Reader { bytes };
+
+        let version = reader.take::<1>()?[0];
+        if version != FORMAT_VERSION {
+            return Err(ProofError::UnsupportedVersion(version));
+        }
+
+        let leaf_count = u32::from_be_bytes(reader.take()?);
+        let index_count = u32::from_be_bytes(reader.take()?) as usize;
+        if index_count > leaf_count as usize {
+            return Err(ProofError::Malformed("more indices than leaves"));
+        }
+        // Counts come from the input, bound them by its length before allocating.
+        if index_count
+            .checked_mul(4 + 16)
+            .is_none_or(|len| len > reader.bytes.len())
+        {
+            return Err(ProofError::Malformed("more indices than bytes"));
+        }
+
+        let mut indices = Vec::with_capacity(index_count);
+        for _ in 0..index_count {
+            indices.push(u32::from_be_bytes(reader.take()?));
+        }
+        let mut values = Vec::with_capacity(index_count);
+        for _ in 0..index_count {
+            values.push(u128::from_be_bytes(reader.take()?));
+        }
+
+        let sibling_count = u32::from_be_bytes(reader.take()?) as usize;
+        if sibling_count.checked_mul(NODE_SIZE) != Some(reader.bytes.len()) {
+            return Err(ProofError::Malformed("unexpected sibling bytes length"));
+        }
+        let mut siblings = Vec::with_capacity(sibling_count);
+        for _ in 0..sibling_count {
+            let hash = reader.take()?;
+            let value = u128::from_be_bytes(reader.take()?);
+            siblings.push(ProofNode { hash, value });
+        }
+
+        Ok(MultiProof {
+            leaf_count,
+            indices,
+            values,
+            siblings,
+        })
+    }
+}
+
+/**
+ * Parent of two product tree nodes. A product over u128 is refused, as `build_product_tree`
+ * refuses to build such a tree.
+ **/
+fn join(left: &ProofNode, right: &ProofNode) -> Result<ProofNode, ProofError> {
+    let value = left
+        .value
+        .checked_mul(right.value)
+        .ok_or(ProofError::ProductOverflow)?;
+    Ok(ProofNode {
+        hash: hash_branch(&left.hash, &right.hash, value),
+        value,
+    })
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], ProofError> {
+        if self.bytes.len() < N {
+            return Err(ProofError::Malformed("unexpected end of input"));
+        }
+        let (head, tail) = self.bytes.split_at(N);
+        self.bytes = tail;
+
+        let mut out = [0u8; N];
+        out.copy_from_slice(head);
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{build_product_tree, map_leaves};
+
+    fn committed_root(leaves: &[u128]) -> ([u8; 32], u128) {
+        let tree = build_product_tree(map_leaves(leaves.to_vec()));
+        let tree = tree.borrow();
+        (tree.commitment(), tree.value())
+    }
+
+    #[test]
+    fn test_multiproof_verifies_against_commitment() {
+        let leaves = [2, 1, 3, 5, 1, 1, 2, 3, 5, 7, 1];
+        let (hash, root) = committed_root(&leaves);
+
+        for indices in [
+            vec![0],
+            vec![0, 1],
+            vec![2, 3, 9],
+            vec![10, 0, 5, 5],
+            vec![15],
+        ] {
+            let proof = MultiProof::generate(&leaves, &indices).unwrap();
+            assert_eq!(proof.verify(&hash, root), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_multiproof_shares_siblings() {
+        let leaves: Vec<u128> = (0..64).map(|i| if i % 3 == 0 { 2 } else { 1 }).collect();
+        let single = MultiProof::generate(&leaves, &[4]).unwrap();
+        assert_eq!(single.siblings.len(), 6);
+
+        let batch = MultiProof::generate(&leaves, &[4, 5, 6, 7]).unwrap();
+        assert_eq!(batch.siblings.len(), 4);
+
+        let all: Vec<usize> = (0..64).collect();
+        let full = MultiProof::generate(&leaves, &all).unwrap();
+        assert!(full.siblings.is_empty());
+    }
+
+    #[test]
+    fn test_multiproof_rejects_tampering() {
+        let leaves = [2, 3, 5, 7, 2, 3];
+        let (hash, root) = committed_root(&leaves);
+        let proof = MultiProof::generate(&leaves, &[1, 4]).unwrap();
+
+        let mut wrong_value = proof.clone();
+        wrong_value.values[0] = 5;
+        assert_eq!(
+            wrong_value.verify(&hash, root),
+            Err(ProofError::RootMismatch)
+        );
+
+        let mut missing = proof.clone();
+        missing.siblings.pop();
+        assert_eq!(missing.verify(&hash, root), Err(ProofError::MissingSibling));
+
+        let mut extra = proof;
+        extra.siblings.push(extra.siblings[0]);
+        assert_eq!(
+            extra.verify(&hash, root),
+            Err(ProofError::UnusedSiblings(1))
+        );
+    }
+
+    #[test]
+    fn test_multiproof_bytes_roundtrip() {
+        let leaves = [2, 3, 5, 1, 1, 7, 2];
+        let (hash, root) = committed_root(&leaves);
+        let proof = MultiProof::generate(&leaves, &[0, 3, 6]).unwrap();
+
+        let bytes = proof.to_bytes();
+        let decoded = MultiProof::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded, proof);
+        assert_eq!(decoded.verify(&hash, root), Ok(()));
+
+        assert!(MultiProof::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_multiproof_truncated_counts() {
+        // 9 bytes claiming u32::MAX leaves and indices.
+        let mut bytes = vec![FORMAT_VERSION];
+        bytes.extend_from_slice(&u32::MAX.to_be_bytes());
+        bytes.extend_from_slice(&u32::MAX.to_be_bytes());
+        assert_eq!(
+            MultiProof::from_bytes(&bytes),
+            Err(ProofError::Malformed("more indices than bytes"))
+        );
+
+        bytes.extend_from_slice(&[0; 4]);
+        bytes[5..9].copy_from_slice(&0u32.to_be_bytes());
+        bytes[9..13].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert_eq!(
+            MultiProof::from_bytes(&bytes),
+            Err(ProofError::Malformed("unexpected sibling bytes length"))
+        );
+    }
+
+    #[test]
+    fn test_multiproof_generate_errors() {
+        assert_eq!(
+            MultiProof::generate(&[2, 3], &[]),
+            Err(ProofError::NoIndices)
+        );
+        assert_eq!(
+            MultiProof::generate(&[2, 3, 5], &[4]),
+            Err(ProofError::IndexOutOfRange(4))
+        );
+        assert_eq!(
+            MultiProof::generate(&[u128::MAX, 2], &[0]),
+            Err(ProofError::ProductOverflow)
+        );
+    }
+
+    #[test]
+    fn test_multiproof_rejects_wrapped_product() {
+        // 2^127 * 2 wraps to 0, a tree `build_product_tree` refuses to build.
+        let sibling = 1u128 << 127;
+        let proof = MultiProof {
+            leaf_count: 2,
+            indices: vec![0],
+            values: vec![2],
+            siblings: vec![ProofNode {
+                hash: hash_leaf(sibling),
+                value: sibling,
+            }],
+        };
+        let wrapped = hash_branch(&hash_leaf(2), &hash_leaf(sibling), 0);
+        assert_eq!(proof.verify(&wrapped, 0), Err(ProofError::ProductOverflow));
+    }
+}
@@ -0,0 +1,514 @@
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+
+use crate::header::{decode_header, BlockHeader, HeaderError};
+use crate::registry::RegistryErrors;
+use crate::{factor_of_n, keccak256, Outputs, Registry};
+
+/**
+ * A proven block as input to a range aggregation: its RLP encoded header, from which the
+ * guest takes the number, hash, parent hash and receipts root, and the outputs committed
+ * by the per-block guest.
+ **/
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct BlockSummary {
+    pub header: Vec<u8>,
+    pub outputs: Outputs,
+}
+
+/**
+ * A chain of consecutive blocks, in order, all proven with `registry`.
+ **/
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct RangeInputs {
+    pub blocks: Vec<BlockSummary>,
+    pub registry: Registry,
+}
+
+/**
+ * Outputs of a range aggregation. `counts` are the logs of each registry contract over
+ * the whole range, in registry order, and are the values at the root of the range tree.
+ * The first and last block hashes anchor the range: a verifier that trusts either one
+ * trusts the whole chain of headers.
+ **/
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct RangeOutputs {
+    pub first_block: u64,
+    pub last_block: u64,
+    pub first_block_hash: [u8; 32],
+    pub last_block_hash: [u8; 32],
+    pub blocks_hash: [u8; 32],
+    pub range_tree_hash: [u8; 32],
+    pub registry_hash: [u8; 32],
+    pub counts: Vec<u32>,
+    pub untracked_logs: u64,
+}
+
+/**
+ * A block whose per-block proof the composition guest verifies itself: its RLP encoded header
+ * and the journal of its receipt, in either encoding.
+ **/
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct ProvenBlock {
+    pub header: Vec<u8>,
+    pub journal: Vec<u8>,
+}
+
+/**
+ * Inputs of the composition guest. The receipt of every block is verified against
+ * `block_image_id` before its outputs are aggregated, so nothing is taken on trust.
+ **/
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct ComposedRangeInputs {
+    pub block_image_id: [u32; 8],
+    pub blocks: Vec<ProvenBlock>,
+    pub registry: Registry,
+}
+
+/**
+ * Committed by the composition guest. A verifier checks `block_image_id` is the per-block
+ * guest it trusts, the guest verifies the block receipts against whichever ID it is given.
+ **/
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct ComposedRangeOutputs {
+    pub block_image_id: [u32; 8],
+    pub range: RangeOutputs,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum RangeError {
+    Empty,
+    InvalidHeader {
+        index: usize,
+        error: HeaderError,
+    },
+    NotConsecutive {
+        expected: u64,
+        found: u64,
+    },
+    ParentMismatch {
+        block: u64,
+    },
+    ReceiptsRootMismatch {
+        block: u64,
+    },
+    RegistryMismatch {
+        block: u64,
+    },
+    InvalidRegistry(RegistryErrors),
+    ZeroProductTreeRoot {
+        block: u64,
+    },
+    CountsMismatch {
+        block: u64,
+        expected: usize,
+        found: usize,
+    },
+    CountOverflow {
+        block: u64,
+    },
+    ImageIdMismatch {
+        block: u64,
+    },
+}
+
+impl Error for RangeError {}
+
+impl fmt::Display for RangeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RangeError::Empty => write!(f, "empty block range"),
+            RangeError::InvalidHeader { index, error } => {
+                write!(f, "invalid header at position {}: {}", index, error)
+            }
+            RangeError::NotConsecutive { expected, found } => write!(
+                f,
+                "blocks are not consecutive: expected block {}, found {}",
+                expected, found
+            ),
+            RangeError::ParentMismatch { block } => write!(
+                f,
+                "parent hash of block {} isn't the hash of the previous header",
+                block
+            ),
+            RangeError::ReceiptsRootMismatch { block } => write!(
+                f,
+                "receipts root of block {} doesn't match its header",
+                block
+            ),
+            RangeError::RegistryMismatch { block } => {
+                write!(f, "block {} was proven with a different registry", block)
+            }
+            RangeError::InvalidRegistry(err) => write!(f, "{}", err),
+            RangeError::ZeroProductTreeRoot { block } => {
+                write!(f, "product tree root of block {} is 0", block)
+            }
+            RangeError::CountsMismatch {
+                block,
+                expected,
+                found,
+            } => write!(
+                f,
+                "state before block {} has {} counts, the registry has {} contracts",
+                block, found, expected
+            ),
+            RangeError::CountOverflow { block } => {
+                write!(f, "counts overflow when adding block {}", block)
+            }
+            RangeError::ImageIdMismatch { block } => write!(
+                f,
+                "state before block {} was verified against other image IDs",
+                block
+            ),
+        }
+    }
+}
+
+/**
+ * Keccak of the concatenated `number || block hash || receipts root` of every block,
+ * in order, binding the range outputs to the blocks they were computed from.
+ **/
+pub fn blocks_hash(headers: &[BlockHeader]) -> [u8; 32] {
+    let mut bytes = Vec::with_capacity(headers.len() * (8 + 2 * 32));
+    for header in headers {
+        bytes.extend_from_slice(&header.number.to_be_bytes());
+        bytes.extend_from_slice(&header.hash);
+        bytes.extend_from_slice(&header.receipts_root);
+    }
+
+    keccak256(&bytes)
+}
+
+/**
+ * Count of each registry prime in a block's product tree root, in registry order.
+ * A product of primes is never 0, a root of 0 is refused rather than read as no logs.
+ **/
+pub fn block_counts(
+    block: u64,
+    outputs: &Outputs,
+    registry: &Registry,
+) -> Result<Vec<u32>, RangeError> {
+    let root = u128::from_be_bytes(outputs.product_tree_root);
+    if root == 0 {
+        return Err(RangeError::ZeroProductTreeRoot { block });
+    }
+
+    Ok(registry
+        .contracts
+        .iter()
+        .map(|contract| factor_of_n(root, contract.prime as u128) as u32)
+        .collect())
+}
+
+/**
+ * Leaf of the range tree: commits the block, its receipts root, its product tree
+ * and the counts derived from the product tree root.
+ **/
+pub fn hash_range_leaf(header: &BlockHeader, outputs: &Outputs, counts: &[u32]) -> [u8; 32] {
+    let mut bytes = Vec::with_capacity(8 + 3 * 32 + 16 + counts.len() * 4);
+    bytes.extend_from_slice(&header.number.to_be_bytes());
+    bytes.extend_from_slice(&header.hash);
+    bytes.extend_from_slice(&outputs.root);
+    bytes.extend_from_slice(&outputs.product_tree_hash);
+    bytes.extend_from_slice(&outputs.product_tree_root);
+    for count in counts {
+        bytes.extend_from_slice(&count.to_be_bytes());
+    }
+
+    keccak256(&bytes)
+}
+
+pub fn hash_range_branch(left: &[u8; 32], right: &[u8; 32], counts: &[u32]) -> [u8; 32] {
+    let mut bytes = Vec::with_capacity(2 * 32 + counts.len() * 4);
+    bytes.extend_from_slice(left);
+    bytes.extend_from_slice(right);
+    for count in counts {
+        bytes.extend_from_slice(&count.to_be_bytes());
+    }
+
+    keccak256(&bytes)
+}
+
+/**
+ * Build the range tree over blocks and return its commitment and root counts.
+ *
+ * The range tree is the product tree of the block product tree roots, kept in factored form:
+ * each node holds the exponent of every registry prime rather than the product itself, which
+ * would overflow a u128 after a handful of blocks. Multiplying two nodes adds their exponents.
+ * Leaves are padded to a power of 2 with empty leaves (zero counts, zero commitment), the
+ * equivalent of the 1s padding the block product trees.
+ **/
+pub fn range_tree(leaves: Vec<([u8; 32], Vec<u32>)>, width: usize) -> ([u8; 32], Vec<u32>) {
+    let mut level = leaves;
+    let next_p_2 = level.len().next_power_of_two();
+    level.resize(next_p_2, ([0u8; 32], vec![0; width]));
+
+    while level.len() > 1 {
+        level = level
+            .chunks(2)
+            .map(|pair| {
+                let counts: Vec<u32> = pair[0]
+                    .1
+                    .iter()
+                    .zip(&pair[1].1)
+                    .map(|(left, right)| left + right)
+                    .collect();
+                (hash_range_branch(&pair[0].0, &pair[1].0, &counts), counts)
+            })
+            .collect();
+    }
+
+    level.pop().unwrap_or(([0u8; 32], vec![0; width]))
+}
+
+/**
+ * Decode the headers of a range and check they form a chain: consecutive numbers, each
+ * parent hash the hash of the previous header, and each receipts root the one the block's
+ * outputs were proven for.
+ **/
+pub fn check_chain(blocks: &[BlockSummary]) -> Result<Vec<BlockHeader>, RangeError> {
+    let mut headers: Vec<BlockHeader> = Vec::with_capacity(blocks.len());
+    for (index, block) in blocks.iter().enumerate() {
+        let header = decode_header(&block.header)
+            .map_err(|error| RangeError::InvalidHeader { index, error })?;
+
+        if let Some(previous) = headers.last() {
+            if header.number != previous.number + 1 {
+                return Err(RangeError::NotConsecutive {
+                    expected: previous.number + 1,
+                    found: header.number,
+                });
+            }
+            if header.parent_hash != previous.hash {
+                return Err(RangeError::ParentMismatch {
+                    block: header.number,
+                });
+            }
+        }
+        if header.receipts_root != block.outputs.root {
+            return Err(RangeError::ReceiptsRootMismatch {
+                block: header.number,
+            });
+        }
+
+        headers.push(header);
+    }
+
+    Ok(headers)
+}
+
+/**
+ * Aggregate per-block outputs into range outputs. The headers must form a chain and the
+ * blocks be proven with `registry`. The block outputs themselves are taken as given:
+ * the caller checks the per-block proofs.
+ **/
+pub fn aggregate(inputs: &RangeInputs) -> Result<RangeOutputs, RangeError> {
+    if inputs.blocks.is_empty() {
+        return Err(RangeError::Empty);
+    }
+    inputs
+        .registry
+        .validate()
+        .map_err(RangeError::InvalidRegistry)?;
+
+    let headers = check_chain(&inputs.blocks)?;
+    let registry_hash = inputs.registry.hash();
+    let mut untracked_logs = 0u64;
+    let mut leaves = Vec::with_capacity(inputs.blocks.len());
+
+    for (header, block) in headers.iter().zip(&inputs.blocks) {
+        if block.outputs.registry_hash != registry_hash {
+            return Err(RangeError::RegistryMismatch {
+                block: header.number,
+            });
+        }
+
+        let counts = block_counts(header.number, &block.outputs, &inputs.registry)?;
+        leaves.push((hash_range_leaf(header, &block.outputs, &counts), counts));
+        untracked_logs += block.outputs.untracked_logs as u64;
+    }
+
+    let (range_tree_hash, counts) = range_tree(leaves, inputs.registry.contracts.len());
+    let (first, last) = (&headers[0], &headers[headers.len() - 1]);
+
+    Ok(RangeOutputs {
+        first_block: first.number,
+        last_block: last.number,
+        first_block_hash: first.hash,
+        last_block_hash: last.hash,
+        blocks_hash: blocks_hash(&headers),
+        range_tree_hash,
+        registry_hash,
+        counts,
+        untracked_logs,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::header::encode_list;
+    use crate::{build_product_tree, map_leaves, MAX_FACTOR_COUNT};
+
+    fn registry() -> Registry {
+        Registry::new(vec![
+            ("0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2".to_string(), 2),
+            ("0xd6a347c998109ac87ccb323f78960c91bbe911cd".to_string(), 3),
+            ("0x3feb4fea5132695542f8ede5076ac43296d17c6d".to_string(), 5),
+        ])
+        .unwrap()
+    }
+
+    fn header(parent_hash: [u8; 32], number: u64, receipts_root: [u8; 32]) -> Vec<u8> {
+        let number = number.to_be_bytes();
+        let skip = number.iter().take_while(|byte| **byte == 0).count();
+        let mut fields = vec![parent_hash.to_vec(), vec![0x22; 32], vec![0x33; 20]];
+        fields.extend([vec![0x44; 32], vec![0x55; 32], receipts_root.to_vec()]);
+        fields.extend([vec![0; 256], vec![], number[skip..].to_vec(), vec![0x01]]);
+        fields.extend([vec![], vec![], vec![], vec![0x77; 32], vec![0; 8]]);
+        encode_list(&fields)
+    }
+
+    /**
+     * Linked blocks from `first`, one per product tree root.
+     **/
+    fn chain(first: u64, roots: &[u128], registry: &Registry) -> Vec<BlockSummary> {
+        let mut parent_hash = [0xff; 32];
+        let mut blocks = Vec::new();
+        for (i, product_tree_root) in roots.iter().enumerate() {
+            let number = first + i as u64;
+            let outputs = Outputs {
+                root: [0x10 + number as u8; 32],
+                product_tree_hash: [0x20 + number as u8; 32],
+                product_tree_root: product_tree_root.to_be_bytes(),
+                registry_hash: registry.hash(),
+                untracked_logs: 1,
+            };
+            let header = header(parent_hash, number, outputs.root);
+            parent_hash = keccak256(&header);
+            blocks.push(BlockSummary { header, outputs });
+        }
+        blocks
+    }
+
+    #[test]
+    fn test_aggregate_counts() {
+        let registry = registry();
+        let inputs = RangeInputs {
+            blocks: chain(100, &[64 * 81 * 25, 2 * 5, 1], &registry),
+            registry: registry.clone(),
+        };
+
+        let outputs = aggregate(&inputs).unwrap();
+        // Registry order: 0x3feb.. (5), 0xc02a.. (2), 0xd6a3.. (3).
+        assert_eq!(outputs.counts, vec![3, 7, 4]);
+        assert_eq!(outputs.untracked_logs, 3);
+        assert_eq!((outputs.first_block, outputs.last_block), (100, 102));
+        assert_eq!(
+            outputs.first_block_hash,
+            keccak256(&inputs.blocks[0].header)
+        );
+        assert_eq!(outputs.last_block_hash, keccak256(&inputs.blocks[2].header));
+        assert_eq!(outputs.registry_hash, registry.hash());
+
+        // Four leaves once padded: the root is the branch of two branches.
+        let headers = check_chain(&inputs.blocks).unwrap();
+        let leaves: Vec<_> = headers
+            .iter()
+            .zip(&inputs.blocks)
+            .map(|(header, block)| {
+                let counts = block_counts(header.number, &block.outputs, &registry).unwrap();
+                (hash_range_leaf(header, &block.outputs, &counts), counts)
+            })
+            .collect();
+        let left = hash_range_branch(&leaves[0].0, &leaves[1].0, &[3, 7, 4]);
+        let right = hash_range_branch(&leaves[2].0, &[0u8; 32], &[0, 0, 0]);
+        assert_eq!(
+            outputs.range_tree_hash,
+            hash_range_branch(&left, &right, &[3, 7, 4])
+        );
+    }
+
+    #[test]
+    fn test_aggregate_errors() {
+        let registry = registry();
+        let mut inputs = RangeInputs {
+            blocks: vec![],
+            registry: registry.clone(),
+        };
+        assert_eq!(aggregate(&inputs), Err(RangeError::Empty));
+
+        let blocks = chain(1, &[2, 2, 2], &registry);
+        inputs.blocks = vec![blocks[0].clone(), blocks[2].clone()];
+        assert_eq!(
+            aggregate(&inputs),
+            Err(RangeError::NotConsecutive {
+                expected: 2,
+                found: 3
+            })
+        );
+
+        // Right number, but not the child of the first block.
+        inputs.blocks = vec![blocks[0].clone(), chain(2, &[2], &registry).remove(0)];
+        assert_eq!(
+            aggregate(&inputs),
+            Err(RangeError::ParentMismatch { block: 2 })
+        );
+
+        inputs.blocks = blocks.clone();
+        inputs.blocks[1].outputs.root[0] ^= 1;
+        assert_eq!(
+            aggregate(&inputs),
+            Err(RangeError::ReceiptsRootMismatch { block: 2 })
+        );
+
+        inputs.blocks = blocks.clone();
+        inputs.blocks[2].header.pop();
+        assert!(matches!(
+            aggregate(&inputs),
+            Err(RangeError::InvalidHeader { index: 2, .. })
+        ));
+
+        inputs.blocks = blocks;
+        inputs.blocks[1].outputs.registry_hash = Registry::default().hash();
+        assert_eq!(
+            aggregate(&inputs),
+            Err(RangeError::RegistryMismatch { block: 2 })
+        );
+    }
+
+    #[test]
+    fn test_zero_and_wrapped_roots() {
+        let registry = registry();
+        let mut inputs = RangeInputs {
+            blocks: chain(1, &[2, 2, 2], &registry),
+            registry: registry.clone(),
+        };
+        inputs.blocks[1].outputs.product_tree_root = [0; 16];
+        assert_eq!(
+            aggregate(&inputs),
+            Err(RangeError::ZeroProductTreeRoot { block: 2 })
+        );
+
+        // 128 logs of the prime 2 multiply to 2^128, which wraps to the zero root above.
+        // The guest refuses to build such a tree instead.
+        let wrapped = std::panic::catch_unwind(|| {
+            build_product_tree(map_leaves(vec![2; 128]))
+                .borrow()
+                .value()
+        });
+        assert!(wrapped.is_err());
+        assert_eq!(
+            build_product_tree(map_leaves(vec![2; 127]))
+                .borrow()
+                .value(),
+            1 << 127
+        );
+
+        // Counts stop at 128 whatever the factor, and a zero root has none.
+        assert_eq!(factor_of_n(1 << 127, 2), 127);
+        assert_eq!(factor_of_n(5, 1), MAX_FACTOR_COUNT);
+        assert_eq!(factor_of_n(0, 2), 0);
+    }
+}
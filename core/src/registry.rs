@@ -0,0 +1,555 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+use crate::keccak256;
+
+/**
+ * A tracked contract and the prime its logs map to in the product tree.
+ * Primes are u64 because the risc0 serializer has no u128 support.
+ **/
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Contract {
+    pub address: String,
+    pub prime: u64,
+}
+
+/**
+ * The set of contracts the guest tracks, passed in `Inputs` so that
+ * a single image ID serves every registry.
+ **/
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Registry {
+    pub contracts: Vec<Contract>,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum AddressError {
+    MissingPrefix,
+    InvalidLength(usize),
+    InvalidCharacter { character: char, position: usize },
+    InvalidChecksum { expected: String },
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum RegistryError {
+    InvalidAddress {
+        address: String,
+        error: AddressError,
+    },
+    NotPrime {
+        address: String,
+        prime: u64,
+    },
+    DuplicateAddress {
+        address: String,
+    },
+    DuplicatePrime {
+        prime: u64,
+        addresses: [String; 2],
+    },
+    /// Valid entries that aren't lowercase and sorted by address, as `Registry::new` leaves them.
+    NotCanonical,
+}
+
+/**
+ * Every problem found while validating a registry, in input order.
+ **/
+#[derive(Debug, Eq, PartialEq)]
+pub struct RegistryErrors(pub Vec<RegistryError>);
+
+impl Error for RegistryErrors {}
+
+impl fmt::Display for AddressError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AddressError::MissingPrefix => write!(f, "missing 0x prefix"),
+            AddressError::InvalidLength(len) => {
+                write!(f, "expected 40 hex digits, found {}", len)
+            }
+            AddressError::InvalidCharacter {
+                character,
+                position,
+            } => write!(
+                f,
+                "invalid hex character {:?} at position {}",
+                character, position
+            ),
+            AddressError::InvalidChecksum { expected } => {
+                write!(f, "invalid EIP-55 checksum, expected {}", expected)
+            }
+        }
+    }
+}
+
+impl fmt::Display for RegistryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RegistryError::InvalidAddress { address, error } => {
+                write!(f, "invalid address {}: {}", address, error)
+            }
+            RegistryError::NotPrime { address, prime } => {
+                write!(f, "value {} for {} is not a prime", prime, address)
+            }
+            RegistryError::DuplicateAddress { address } => {
+                write!(f, "address {} is registered more than once", address)
+            }
+            RegistryError::DuplicatePrime { prime, addresses } => write!(
+                f,
+                "prime {} is shared by {} and {}",
+                prime, addresses[0], addresses[1]
+            ),
+            RegistryError::NotCanonical => {
+                write!(f, "contracts are not lowercase and sorted by address")
+            }
+        }
+    }
+}
+
+impl fmt::Display for RegistryErrors {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid registry, {} error(s)", self.0.len())?;
+        for error in &self.0 {
+            write!(f, "\n  - {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+impl Registry {
+    /**
+     * Build a registry from (address, prime) pairs, checking that addresses are 0x prefixed
+     * 20 byte hex with a valid EIP-55 checksum when mixed case, that values are prime, and
+     * that no address or prime appears twice. All problems are reported at once.
+     *
+     * Addresses are lowercased to match the log addresses sent to the guest, and contracts
+     * are sorted by address so the hash doesn't depend on input order.
+     **/
+    pub fn new(entries: impl IntoIterator<Item = (String, u64)>) -> Result<Self, RegistryErrors> {
+        let mut errors = Vec::new();
+        let mut by_address: HashMap<String, u64> = HashMap::new();
+        let mut by_prime: HashMap<u64, String> = HashMap::new();
+        let mut contracts = Vec::new();
+
+        for (address, prime) in entries {
+            if let Err(error) = validate_address(&address) {
+                errors.push(RegistryError::InvalidAddress { address, error });
+                continue;
+            }
+            let address = address.to_lowercase();
+
+            if !is_prime(prime) {
+                errors.push(RegistryError::NotPrime {
+                    address: address.clone(),
+                    prime,
+                });
+            }
+            if by_address.insert(address.clone(), prime).is_some() {
+                errors.push(RegistryError::DuplicateAddress {
+                    address: address.clone(),
+                });
+                continue;
+            }
+            if let Some(other) = by_prime.get(&prime) {
+                errors.push(RegistryError::DuplicatePrime {
+                    prime,
+                    addresses: [other.clone(), address.clone()],
+                });
+            } else {
+                by_prime.insert(prime, address.clone());
+            }
+
+            contracts.push(Contract { address, prime });
+        }
+
+        if !errors.is_empty() {
+            return Err(RegistryErrors(errors));
+        }
+
+        contracts.sort_by(|a, b| a.address.cmp(&b.address));
+        Ok(Registry { contracts })
+    }
+
+    /**
+     * Check a registry received as is, like the guests' inputs, is one `Registry::new` builds:
+     * the same checks, and contracts already lowercase and sorted.
+     **/
+    pub fn validate(&self) -> Result<(), RegistryErrors> {
+        let rebuilt = Registry::new(
+            self.contracts
+                .iter()
+                .map(|contract| (contract.address.clone(), contract.prime)),
+        )?;
+        if rebuilt != *self {
+            return Err(RegistryErrors(vec![RegistryError::NotCanonical]));
+        }
+
+        Ok(())
+    }
+
+    /**
+     * Smallest prime not used by any registered contract.
+     **/
+    pub fn next_free_prime(&self) -> u64 {
+        (2..)
+            .filter(|n| is_prime(*n))
+            .find(|n| self.contracts.iter().all(|contract| contract.prime != *n))
+            .expect("ran out of u64 primes")
+    }
+
+    pub fn contract_primes(&self) -> HashMap<&str, u128> {
+        self.contracts
+            .iter()
+            .map(|contract| (contract.address.as_str(), contract.prime as u128))
+            .collect()
+    }
+
+    /**
+     * Keccak of the concatenated `address || prime` entries, in registry order, addresses as
+     * their 20 bytes so every entry has the same width. Committed by the guest in
+     * `Outputs::registry_hash`, for registries that passed `validate`.
+     **/
+    pub fn hash(&self) -> [u8; 32] {
+        let mut bytes = Vec::with_capacity(self.contracts.len() * (20 + 8));
+        for contract in &self.contracts {
+            bytes.extend_from_slice(&address_bytes(&contract.address));
+            bytes.extend_from_slice(&contract.prime.to_be_bytes());
+        }
+
+        keccak256(&bytes)
+    }
+}
+
+/**
+ * The 20 bytes of a valid address. Invalid digits decode as 0, `validate` rejects them first.
+ **/
+fn address_bytes(address: &str) -> [u8; 20] {
+    let digits = address.strip_prefix("0x").unwrap_or(address).as_bytes();
+    let nibble = |i: usize| {
+        digits
+            .get(i)
+            .and_then(|digit| (*digit as char).to_digit(16))
+            .unwrap_or(0) as u8
+    };
+
+    std::array::from_fn(|i| (nibble(2 * i) << 4) | nibble(2 * i + 1))
+}
+
+/**
+ * Check an address is `0x` followed by 40 hex digits. All lowercase and all uppercase
+ * addresses are accepted as is, mixed case ones must match their EIP-55 checksum.
+ **/
+pub fn validate_address(address: &str) -> Result<(), AddressError> {
+    let digits = address
+        .strip_prefix("0x")
+        .ok_or(AddressError::MissingPrefix)?;
+
+    if let Some((position, character)) = digits.char_indices().find(|(_, c)| !c.is_ascii_hexdigit())
+    {
+        return Err(AddressError::InvalidCharacter {
+            character,
+            position: position + 2,
+        });
+    }
+    if digits.len() != 40 {
+        return Err(AddressError::InvalidLength(digits.len()));
+    }
+
+    let has_lower = digits.chars().any(|c| c.is_ascii_lowercase());
+    let has_upper = digits.chars().any(|c| c.is_ascii_uppercase());
+    if has_lower && has_upper {
+        let expected = to_checksum_address(digits);
+        if expected[2..] != *digits {
+            return Err(AddressError::InvalidChecksum { expected });
+        }
+    }
+
+    Ok(())
+}
+
+/**
+ * EIP-55 mixed case encoding of 40 hex digits: a letter is uppercased when the
+ * matching nibble of the keccak of the lowercase digits is 8 or more.
+ **/
+pub fn to_checksum_address(digits: &str) -> String {
+    let lower = digits.to_lowercase();
+    let hash = keccak256(lower.as_bytes());
+
+    let mut checksummed = String::with_capacity(42);
+    checksummed.push_str("0x");
+    for (i, c) in lower.chars().enumerate() {
+        let nibble = if i % 2 == 0 {
+            hash[i / 2] >> 4
+        } else {
+            hash[i / 2] & 0x0f
+        };
+        if nibble >= 8 {
+            checksummed.push(c.to_ascii_uppercase());
+        } else {
+            checksummed.push(c);
+        }
+    }
+
+    checksummed
+}
+
+/**
+ * Deterministic Miller-Rabin, these bases are enough for every u64.
+ **/
+pub fn is_prime(n: u64) -> bool {
+    const BASES: [u64; 12] = [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37];
+
+    if n < 2 {
+        return false;
+    }
+    for p in BASES {
+        if n.is_multiple_of(p) {
+            return n == p;
+        }
+    }
+
+    let mut d = n - 1;
+    let mut s = 0;
+    while d.is_multiple_of(2) {
+        d /= 2;
+        s += 1;
+    }
+
+    'witness: for a in BASES {
+        let mut x = pow_mod(a, d, n);
+        if x == 1 || x == n - 1 {
+            continue;
+        }
+        for _ in 1..s {
+            x = mul_mod(x, x, n);
+            if x == n - 1 {
+                continue 'witness;
+            }
+        }
+        return false;
+    }
+
+    true
+}
+
+fn mul_mod(a: u64, b: u64, m: u64) -> u64 {
+    (a as u128 * b as u128 % m as u128) as u64
+}
+
+fn pow_mod(mut base: u64, mut exp: u64, m: u64) -> u64 {
+    let mut result = 1;
+    base %= m;
+    while exp > 0 {
+        if exp & 1 == 1 {
+            result = mul_mod(result, base, m);
+        }
+        base = mul_mod(base, base, m);
+        exp >>= 1;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(address: &str, prime: u64) -> (String, u64) {
+        (address.to_string(), prime)
+    }
+
+    #[test]
+    fn test_checksum_addresses() {
+        // See: https://eips.ethereum.org/EIPS/eip-55
+        for address in [
+            "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed",
+            "0xfB6916095ca1df60bB79Ce92cE3Ea74c37c5d359",
+            "0xdbF03B407c01E7cD3CBea99509d93f8DDDC8C6FB",
+            "0xD1220A0cf47c7B9Be7A2E6BA89F429762e7b9aDb",
+        ] {
+            assert_eq!(validate_address(address), Ok(()));
+            assert_eq!(to_checksum_address(&address[2..]), address);
+        }
+
+        assert_eq!(
+            validate_address("0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAeD"),
+            Err(AddressError::InvalidChecksum {
+                expected: "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed".to_string()
+            })
+        );
+        assert_eq!(
+            validate_address("0x5AAEB6053F3E94C9B9A09F33669435E7EF1BEAED"),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_invalid_addresses() {
+        assert_eq!(
+            validate_address("0x7a250d5630b4cf539739df2c5dacb4x659f2488d"),
+            Err(AddressError::InvalidCharacter {
+                character: 'x',
+                position: 32
+            })
+        );
+        assert_eq!(
+            validate_address("7a250d5630b4cf539739df2c5dacb4c659f2488d"),
+            Err(AddressError::MissingPrefix)
+        );
+        assert_eq!(
+            validate_address("0x7a250d5630b4cf539739df2c5dacb4c659f248"),
+            Err(AddressError::InvalidLength(38))
+        );
+    }
+
+    #[test]
+    fn test_is_prime() {
+        let primes: Vec<u64> = (0..50).filter(|n| is_prime(*n)).collect();
+        assert_eq!(
+            primes,
+            vec![2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47]
+        );
+        assert!(is_prime(18446744073709551557));
+        assert!(!is_prime(3215031751));
+        assert!(!is_prime(u64::MAX));
+    }
+
+    #[test]
+    fn test_registry_reports_every_error() {
+        let errors = Registry::new(vec![
+            entry("0x4ce5df9033ead87976255a8695592bca3e8cb5cb", 2),
+            entry("0x7a250d5630b4cf539739df2c5dacb4x659f2488d", 3),
+            entry("0xf64e49c1d1d2b1cfa570b1da6481dc8dc95cd093", 1),
+            entry("0x076a3e1500f3110d8f4445d396a3d7ca6d0ca269", 2),
+            entry("0x4CE5DF9033EAD87976255A8695592BCA3E8CB5CB", 7),
+        ])
+        .unwrap_err();
+
+        assert_eq!(
+            errors.0,
+            vec![
+                RegistryError::InvalidAddress {
+                    address: "0x7a250d5630b4cf539739df2c5dacb4x659f2488d".to_string(),
+                    error: AddressError::InvalidCharacter {
+                        character: 'x',
+                        position: 32
+                    },
+                },
+                RegistryError::NotPrime {
+                    address: "0xf64e49c1d1d2b1cfa570b1da6481dc8dc95cd093".to_string(),
+                    prime: 1,
+                },
+                RegistryError::DuplicatePrime {
+                    prime: 2,
+                    addresses: [
+                        "0x4ce5df9033ead87976255a8695592bca3e8cb5cb".to_string(),
+                        "0x076a3e1500f3110d8f4445d396a3d7ca6d0ca269".to_string(),
+                    ],
+                },
+                RegistryError::DuplicateAddress {
+                    address: "0x4ce5df9033ead87976255a8695592bca3e8cb5cb".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_next_free_prime() {
+        let mut registry = Registry::default();
+        assert_eq!(registry.next_free_prime(), 2);
+
+        registry = Registry::new(vec![
+            entry("0x4ce5df9033ead87976255a8695592bca3e8cb5cb", 2),
+            entry("0xf64e49c1d1d2b1cfa570b1da6481dc8dc95cd093", 5),
+        ])
+        .unwrap();
+        assert_eq!(registry.next_free_prime(), 3);
+    }
+
+    #[test]
+    fn test_registry_hash_ignores_order() {
+        let a = Registry::new(vec![
+            entry("0x4ce5df9033ead87976255a8695592bca3e8cb5cb", 2),
+            entry("0xF64E49C1D1D2B1CFA570B1DA6481DC8DC95CD093", 3),
+        ])
+        .unwrap();
+        let b = Registry::new(vec![
+            entry("0xf64e49c1d1d2b1cfa570b1da6481dc8dc95cd093", 3),
+            entry("0x4ce5df9033ead87976255a8695592bca3e8cb5cb", 2),
+        ])
+        .unwrap();
+
+        assert_eq!(a, b);
+        assert_eq!(a.hash(), b.hash());
+    }
+
+    #[test]
+    fn test_registry_hash_entries_are_fixed_width() {
+        // Hashing the address strings as they are, the first registry's bytes
+        // `a || p || b || q` read as a single longer address followed by `q`.
+        let a = "0x4ce5df9033ead87976255a8695592bca3e8cb5cb";
+        let b = "0xf64e49c1d1d2b1cfa570b1da6481dc8dc95cd093";
+        let p = u64::from_be_bytes(*b"00000000");
+        let two = Registry {
+            contracts: vec![
+                Contract {
+                    address: a.to_string(),
+                    prime: p,
+                },
+                Contract {
+                    address: b.to_string(),
+                    prime: 3,
+                },
+            ],
+        };
+        let one = Registry {
+            contracts: vec![Contract {
+                address: format!("{}00000000{}", a, b),
+                prime: 3,
+            }],
+        };
+
+        let string_hash = |registry: &Registry| {
+            let mut bytes = Vec::new();
+            for contract in &registry.contracts {
+                bytes.extend_from_slice(contract.address.as_bytes());
+                bytes.extend_from_slice(&contract.prime.to_be_bytes());
+            }
+            keccak256(&bytes)
+        };
+        assert_eq!(string_hash(&two), string_hash(&one));
+        assert_ne!(two.hash(), one.hash());
+        assert!(one.validate().is_err());
+    }
+
+    #[test]
+    fn test_registry_validate() {
+        let registry = Registry::new(vec![
+            entry("0x4ce5df9033ead87976255a8695592bca3e8cb5cb", 2),
+            entry("0xf64e49c1d1d2b1cfa570b1da6481dc8dc95cd093", 3),
+        ])
+        .unwrap();
+        assert_eq!(registry.validate(), Ok(()));
+
+        let mut unsorted = registry.clone();
+        unsorted.contracts.reverse();
+        assert_eq!(
+            unsorted.validate(),
+            Err(RegistryErrors(vec![RegistryError::NotCanonical]))
+        );
+
+        let mut duplicated = registry.clone();
+        duplicated.contracts.push(duplicated.contracts[1].clone());
+        assert!(duplicated.validate().is_err());
+
+        let mut not_prime = registry;
+        not_prime.contracts[0].prime = 4;
+        assert_eq!(
+            not_prime.validate(),
+            Err(RegistryErrors(vec![RegistryError::NotPrime {
+                address: "0x4ce5df9033ead87976255a8695592bca3e8cb5cb".to_string(),
+                prime: 4,
+            }]))
+        );
+    }
+}
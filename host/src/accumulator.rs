@@ -0,0 +1,374 @@
+use methods::{ACCUMULATOR_ELF, ACCUMULATOR_ID, RECEIPTS_ROOT_AND_PRODUCT_TREE_ID};
+use risc0_zkvm::serde::from_slice;
+use risc0_zkvm::Receipt as ProofReceipt;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use trie_core::accumulator::{
+    extend, AccumulatorInputs, AccumulatorOutputs, AccumulatorState, ProvenAccumulatorInputs,
+};
+use trie_core::Registry;
+use verifier::artifacts::{
+    image_id_hex, journal_path, load_receipt, to_hex, write_receipt, SavedReceipt,
+};
+use verifier::VerifyError;
+
+use crate::backend::shutdown_token;
+use crate::error::HostError;
+use crate::prover::{execute_elf, executor_env, prove_composition};
+use crate::range::{proven_block, RangeBlock};
+
+const ACCUMULATOR_JOURNAL_VERSION: u32 = 2;
+
+/**
+ * Human readable view of an `AccumulatorState`.
+ **/
+#[derive(Debug, Deserialize, Serialize)]
+pub struct AccumulatorStateJson {
+    pub registry_hash: String,
+    pub first_block: u64,
+    pub last_block: u64,
+    pub last_block_hash: String,
+    pub counts: Vec<u32>,
+    pub untracked_logs: u64,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct AccumulatorJournalFile {
+    pub version: u32,
+    pub image_id: String,
+    pub journal: String,
+    pub previous: String,
+    pub commitment: String,
+    pub state: AccumulatorStateJson,
+}
+
+impl From<&AccumulatorState> for AccumulatorStateJson {
+    fn from(state: &AccumulatorState) -> Self {
+        AccumulatorStateJson {
+            registry_hash: to_hex(&state.registry_hash),
+            first_block: state.first_block,
+            last_block: state.last_block,
+            last_block_hash: to_hex(&state.last_block_hash),
+            counts: state.counts.clone(),
+            untracked_logs: state.untracked_logs,
+        }
+    }
+}
+
+/**
+ * Decode the journal of the accumulator guest and check the block and the previous step
+ * were verified against the per-block and accumulator guests.
+ **/
+pub fn decode_accumulator_journal(journal: &[u8]) -> Result<AccumulatorOutputs, HostError> {
+    let outputs: AccumulatorOutputs =
+        from_slice(journal).map_err(|err| VerifyError::InvalidJournal(err.to_string()))?;
+    for (expected, found) in [
+        (RECEIPTS_ROOT_AND_PRODUCT_TREE_ID, outputs.block_image_id),
+        (ACCUMULATOR_ID, outputs.accumulator_image_id),
+    ] {
+        if found != expected {
+            return Err(VerifyError::ImageIdMismatch {
+                expected: image_id_hex(&expected),
+                found: image_id_hex(&found),
+            }
+            .into());
+        }
+    }
+
+    Ok(outputs)
+}
+
+/**
+ * Chain of accumulator states on disk. Each step is named after the block it added:
+ * `<block>.receipt.bin`, absent for steps run with the executor only, and
+ * `<block>.receipt.journal.json`, from which the next step takes its previous state.
+ **/
+pub struct AccumulatorChain {
+    dir: PathBuf,
+}
+
+impl AccumulatorChain {
+    pub fn open(dir: &Path) -> Result<Self, HostError> {
+        std::fs::create_dir_all(dir)?;
+        Ok(AccumulatorChain {
+            dir: dir.to_path_buf(),
+        })
+    }
+
+    pub fn receipt_path(&self, block: u64) -> PathBuf {
+        self.dir.join(format!("{}.receipt.bin", block))
+    }
+
+    /**
+     * Block numbers of the steps, in order.
+     **/
+    pub fn steps(&self) -> Result<Vec<u64>, HostError> {
+        let mut steps = Vec::new();
+        for entry in std::fs::read_dir(&self.dir)? {
+            let name = entry?.file_name();
+            let block = name
+                .to_str()
+                .and_then(|name| name.strip_suffix(".receipt.journal.json"))
+                .and_then(|block| block.parse().ok());
+            if let Some(block) = block {
+                steps.push(block);
+            }
+        }
+        steps.sort_unstable();
+
+        Ok(steps)
+    }
+
+    pub fn load_step(&self, block: u64) -> Result<AccumulatorOutputs, HostError> {
+        decode_accumulator_journal(&self.step_journal(block)?)
+    }
+
+    fn step_journal(&self, block: u64) -> Result<Vec<u8>, HostError> {
+        let path = journal_path(&self.receipt_path(block));
+        let file: AccumulatorJournalFile = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        if file.version != ACCUMULATOR_JOURNAL_VERSION {
+            return Err(HostError::Artifact(format!(
+                "unsupported journal file version {}",
+                file.version
+            )));
+        }
+        hex::decode(file.journal.trim_start_matches("0x"))
+            .map_err(|err| HostError::Artifact(err.to_string()))
+    }
+
+    /**
+     * Outputs of the last step, `None` for an empty chain.
+     **/
+    pub fn head(&self) -> Result<Option<AccumulatorOutputs>, HostError> {
+        match self.steps()?.last() {
+            Some(block) => Ok(Some(self.load_step(*block)?)),
+            None => Ok(None),
+        }
+    }
+
+    /**
+     * Add a block to the last state of the chain, starting the chain if it's empty. The block
+     * needs a receipt of the per-block guest: the accumulator guest verifies it, and the
+     * receipt of the previous step, with `env::verify`. The step is run on the host first, so
+     * a block that doesn't extend the chain is refused before the guest runs.
+     **/
+    pub fn extend(
+        &self,
+        block: &RangeBlock,
+        registry: Registry,
+        execute_only: bool,
+    ) -> Result<AccumulatorOutputs, HostError> {
+        let (summary, proven, receipt) = proven_block(block)?;
+        let head = self.steps()?.last().copied();
+        let previous = head.map(|head| self.step_journal(head)).transpose()?;
+        extend(&AccumulatorInputs {
+            block_image_id: RECEIPTS_ROOT_AND_PRODUCT_TREE_ID,
+            accumulator_image_id: ACCUMULATOR_ID,
+            previous: previous
+                .as_deref()
+                .map(decode_accumulator_journal)
+                .transpose()?,
+            block: summary,
+            registry: registry.clone(),
+        })?;
+
+        let inputs = ProvenAccumulatorInputs {
+            block_image_id: RECEIPTS_ROOT_AND_PRODUCT_TREE_ID,
+            accumulator_image_id: ACCUMULATOR_ID,
+            previous,
+            block: proven,
+            registry,
+        };
+        let (journal, outputs) = if execute_only {
+            // The executor records the `env::verify` calls without checking them.
+            let session = execute_elf(executor_env(&inputs)?.0, ACCUMULATOR_ELF)?;
+            let outputs = decode_accumulator_journal(&session.journal.bytes)?;
+            (session.journal.bytes, outputs)
+        } else {
+            let mut assumptions = vec![receipt];
+            if let (Some(head), Some(journal)) = (head, &inputs.previous) {
+                assumptions.push(self.step_receipt(head, journal)?);
+            }
+            let receipt = prove_composition(
+                &inputs,
+                &assumptions,
+                ACCUMULATOR_ELF,
+                ACCUMULATOR_ID,
+                shutdown_token(),
+            )?;
+            receipt
+                .verify(ACCUMULATOR_ID)
+                .map_err(|err| VerifyError::InvalidProof(err.to_string()))?;
+            let outputs = decode_accumulator_journal(&receipt.journal.bytes)?;
+            write_receipt(
+                &self.receipt_path(outputs.state.last_block),
+                &receipt,
+                &ACCUMULATOR_ID,
+            )?;
+            (receipt.journal.bytes, outputs)
+        };
+        self.save_step(&journal, &outputs)?;
+
+        Ok(outputs)
+    }
+
+    /**
+     * Verified receipt of a step. Its journal must be the one in the step's journal file,
+     * which the next step extends.
+     **/
+    fn step_receipt(&self, block: u64, journal: &[u8]) -> Result<ProofReceipt, HostError> {
+        let path = self.receipt_path(block);
+        if !path.exists() {
+            return Err(HostError::Artifact(format!(
+                "step {} has no receipt, it was run with the executor only",
+                block
+            )));
+        }
+        let saved = load_receipt(&path)?;
+        verify_step(&saved)?;
+        if saved.receipt.journal.bytes != journal {
+            return Err(HostError::JournalMismatch(journal_path(&path)));
+        }
+
+        Ok(saved.receipt)
+    }
+
+    fn save_step(&self, journal: &[u8], outputs: &AccumulatorOutputs) -> Result<(), HostError> {
+        let file = AccumulatorJournalFile {
+            version: ACCUMULATOR_JOURNAL_VERSION,
+            image_id: image_id_hex(&ACCUMULATOR_ID),
+            journal: to_hex(journal),
+            previous: to_hex(&outputs.previous),
+            commitment: to_hex(&outputs.commitment),
+            state: (&outputs.state).into(),
+        };
+        let path = journal_path(&self.receipt_path(outputs.state.last_block));
+        std::fs::write(path, serde_json::to_string_pretty(&file)?)?;
+
+        Ok(())
+    }
+
+    /**
+     * Verify the receipt of every step and that each step extends the one before, returning
+     * the outputs of the last step. Steps run with the executor only have no receipt and fail.
+     **/
+    pub fn verify(&self) -> Result<Option<AccumulatorOutputs>, HostError> {
+        let mut head: Option<AccumulatorOutputs> = None;
+        for block in self.steps()? {
+            let journal = self.step_journal(block)?;
+            self.step_receipt(block, &journal)?;
+            let outputs = decode_accumulator_journal(&journal)?;
+
+            let previous = head.as_ref().map_or([0u8; 32], |head| head.commitment);
+            if outputs.previous != previous {
+                return Err(HostError::BrokenChain(block));
+            }
+            head = Some(outputs);
+        }
+
+        Ok(head)
+    }
+}
+
+pub fn verify_step(saved: &SavedReceipt) -> Result<AccumulatorOutputs, HostError> {
+    if saved.image_id != ACCUMULATOR_ID {
+        return Err(VerifyError::ImageIdMismatch {
+            expected: image_id_hex(&ACCUMULATOR_ID),
+            found: image_id_hex(&saved.image_id),
+        }
+        .into());
+    }
+    saved
+        .receipt
+        .verify(ACCUMULATOR_ID)
+        .map_err(|err| VerifyError::InvalidProof(err.to_string()))?;
+
+    decode_accumulator_journal(&saved.receipt.journal.bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use risc0_zkvm::serde::to_vec;
+
+    fn outputs(previous: [u8; 32], last_block: u64) -> AccumulatorOutputs {
+        let state = AccumulatorState {
+            registry_hash: [0x11; 32],
+            first_block: 9,
+            last_block,
+            last_block_hash: [last_block as u8; 32],
+            counts: vec![1, 2],
+            untracked_logs: 3,
+        };
+        AccumulatorOutputs {
+            block_image_id: RECEIPTS_ROOT_AND_PRODUCT_TREE_ID,
+            accumulator_image_id: ACCUMULATOR_ID,
+            previous,
+            commitment: state.commitment(),
+            state,
+        }
+    }
+
+    fn journal(outputs: &AccumulatorOutputs) -> Vec<u8> {
+        to_vec(outputs)
+            .unwrap()
+            .iter()
+            .flat_map(|word| word.to_le_bytes())
+            .collect()
+    }
+
+    #[test]
+    fn test_chain_head() {
+        let dir = std::env::temp_dir().join(format!("accumulator-{}", std::process::id()));
+        let chain = AccumulatorChain::open(&dir).unwrap();
+        assert_eq!(chain.head().unwrap(), None);
+
+        let mut previous = [0u8; 32];
+        // Numeric order, not file name order.
+        for block in [9, 10, 11] {
+            let outputs = outputs(previous, block);
+            chain.save_step(&journal(&outputs), &outputs).unwrap();
+            previous = outputs.commitment;
+        }
+
+        assert_eq!(chain.steps().unwrap(), vec![9, 10, 11]);
+        let head = chain.head().unwrap().unwrap();
+        assert_eq!(head.state.last_block, 11);
+        assert_eq!(head.commitment, previous);
+
+        // Executor only steps have no receipt to verify.
+        assert!(matches!(chain.verify(), Err(HostError::Artifact(_))));
+
+        // The guest can't verify a journal file, the block needs a receipt.
+        let block = RangeBlock {
+            header: dir.join("12.header.json"),
+            receipt: None,
+            journal: Some(dir.join("12.journal.json")),
+        };
+        assert!(matches!(
+            chain.extend(&block, Registry::default(), true),
+            Err(HostError::InvalidInput(_))
+        ));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_decode_checks_image_ids() {
+        let valid = outputs([0; 32], 9);
+        assert_eq!(decode_accumulator_journal(&journal(&valid)).unwrap(), valid);
+
+        // Blocks verified against another guest, or a previous step of another accumulator.
+        let mut foreign_block = valid.clone();
+        foreign_block.block_image_id = [7; 8];
+        let mut foreign_step = valid;
+        foreign_step.accumulator_image_id = [7; 8];
+        for outputs in [foreign_block, foreign_step] {
+            assert!(matches!(
+                decode_accumulator_journal(&journal(&outputs)),
+                Err(HostError::Verify(VerifyError::ImageIdMismatch { .. }))
+            ));
+        }
+    }
+}
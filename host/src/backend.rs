@@ -0,0 +1,548 @@
+use risc0_zkvm::{
+    ExecutorEnv, ExternalProver, InnerAssumptionReceipt, Prover, ProverOpts,
+    Receipt as ProofReceipt,
+};
+use serde::{Deserialize, Serialize};
+use std::io::Read;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+
+use crate::error::HostError;
+use crate::prover::executor_limits;
+use verifier::artifacts::image_id_hex;
+
+const API_KEY_HEADER: &str = "x-api-key";
+const VERSION_HEADER: &str = "x-risc0-version";
+
+static BACKEND: OnceLock<Box<dyn ProverBackend>> = OnceLock::new();
+static SHUTDOWN: OnceLock<CancelToken> = OnceLock::new();
+
+/**
+ * Cancels the proofs it is given to. Clones share the flag, and a token made with `child`
+ * is also cancelled with its parent, so cancelling a parent stops every proof under it.
+ **/
+#[derive(Clone, Debug)]
+pub struct CancelToken {
+    // The token's own flag last, those of its ancestors before it.
+    flags: Vec<Arc<AtomicBool>>,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        CancelToken {
+            flags: vec![Arc::new(AtomicBool::new(false))],
+        }
+    }
+
+    pub fn child(&self) -> Self {
+        let mut flags = self.flags.clone();
+        flags.push(Arc::new(AtomicBool::new(false)));
+        CancelToken { flags }
+    }
+
+    pub fn cancel(&self) {
+        if let Some(flag) = self.flags.last() {
+            flag.store(true, Ordering::SeqCst);
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.flags.iter().any(|flag| flag.load(Ordering::SeqCst))
+    }
+}
+
+impl Default for CancelToken {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/**
+ * The token of the whole process, cancelled on SIGINT. Proofs get a child of it.
+ **/
+pub fn shutdown_token() -> &'static CancelToken {
+    SHUTDOWN.get_or_init(CancelToken::new)
+}
+
+/**
+ * A guest to prove: its ELF and image ID, the serialized inputs and the receipts of the
+ * `env::verify` calls it makes. `succinct` asks for a succinct receipt, and for the
+ * assumptions to be resolved by recursion. Cancelling `cancel` stops this proof only.
+ **/
+pub struct ProofRequest<'a> {
+    pub elf: &'a [u8],
+    pub image_id: [u32; 8],
+    pub input: &'a [u32],
+    pub assumptions: &'a [ProofReceipt],
+    pub succinct: bool,
+    pub cancel: &'a CancelToken,
+}
+
+/**
+ * Where proofs are produced. The receipt returned is not verified, callers do it as a
+ * separate step. A cancelled request returns `HostError::ProofCancelled`: Bonsai sessions
+ * are stopped, local proofs only check the token before starting, `r0vm` gets the SIGINT
+ * of the terminal itself.
+ **/
+pub trait ProverBackend: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn prove(&self, request: &ProofRequest) -> Result<ProofReceipt, HostError>;
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BackendKind {
+    #[default]
+    Local,
+    DevMode,
+    Bonsai,
+}
+
+impl FromStr for BackendKind {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "local" => Ok(BackendKind::Local),
+            "dev-mode" => Ok(BackendKind::DevMode),
+            "bonsai" => Ok(BackendKind::Bonsai),
+            _ => Err(format!(
+                "unknown prover {}, expected local, dev-mode or bonsai",
+                value
+            )),
+        }
+    }
+}
+
+/**
+ * Bonsai settings. The URL and API key fall back to `BONSAI_API_URL` and `BONSAI_API_KEY`,
+ * which only matter once the Bonsai backend is selected. Failed requests are retried
+ * `retries` times, waiting `retry_delay_ms` then twice as long each time. A proof that takes
+ * more than `proof_timeout_secs` is stopped.
+ **/
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BonsaiConfig {
+    pub url: Option<String>,
+    pub api_key: Option<String>,
+    pub request_timeout_secs: u64,
+    pub proof_timeout_secs: u64,
+    pub poll_interval_ms: u64,
+    pub retries: u32,
+    pub retry_delay_ms: u64,
+}
+
+impl Default for BonsaiConfig {
+    fn default() -> Self {
+        BonsaiConfig {
+            url: None,
+            api_key: None,
+            request_timeout_secs: 60,
+            proof_timeout_secs: 4 * 3600,
+            poll_interval_ms: 2000,
+            retries: 3,
+            retry_delay_ms: 1000,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProverConfig {
+    pub backend: BackendKind,
+    pub bonsai: BonsaiConfig,
+}
+
+/**
+ * Build the backend a config selects.
+ **/
+pub fn backend_from_config(config: &ProverConfig) -> Result<Box<dyn ProverBackend>, HostError> {
+    Ok(match config.backend {
+        BackendKind::Local => Box::new(LocalBackend::new()),
+        BackendKind::DevMode => Box::new(DevModeBackend::new()),
+        BackendKind::Bonsai => Box::new(BonsaiBackend::new(&config.bonsai)?),
+    })
+}
+
+/**
+ * Set the backend of every proof of the process, once, before the first one. Proofs use the
+ * local backend otherwise, whatever the environment says.
+ **/
+pub fn set_prover_backend(backend: Box<dyn ProverBackend>) -> Result<(), HostError> {
+    BACKEND
+        .set(backend)
+        .map_err(|_| HostError::InvalidBackend("the prover backend is already set".to_string()))
+}
+
+pub fn prover_backend() -> &'static dyn ProverBackend {
+    BACKEND
+        .get_or_init(|| Box::new(LocalBackend::new()))
+        .as_ref()
+}
+
+/**
+ * Path of the `r0vm` server local proofs run in: `RISC0_SERVER_PATH`, or `r0vm` from the
+ * `PATH` as installed by rzup.
+ **/
+fn r0vm_path() -> PathBuf {
+    std::env::var_os("RISC0_SERVER_PATH")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("r0vm"))
+}
+
+fn prove_external(
+    name: &str,
+    request: &ProofRequest,
+    dev_mode: bool,
+) -> Result<ProofReceipt, HostError> {
+    if request.cancel.is_cancelled() {
+        return Err(HostError::ProofCancelled(image_id_hex(&request.image_id)));
+    }
+    let prover = ExternalProver::new(name, r0vm_path());
+    let opts = if request.succinct {
+        ProverOpts::succinct()
+    } else {
+        ProverOpts::default()
+    }
+    .with_dev_mode(dev_mode);
+
+    let mut builder = ExecutorEnv::builder();
+    executor_limits().apply(&mut builder)?;
+    builder.write_slice(request.input);
+    for receipt in request.assumptions {
+        builder.add_assumption(prover.compress(&opts, receipt)?);
+    }
+
+    Ok(prover
+        .prove_with_opts(builder.build()?, request.elf, &opts)?
+        .receipt)
+}
+
+/**
+ * Prove on this machine, in an `r0vm` process.
+ **/
+pub struct LocalBackend;
+
+impl LocalBackend {
+    pub fn new() -> Self {
+        LocalBackend
+    }
+}
+
+impl Default for LocalBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ProverBackend for LocalBackend {
+    fn name(&self) -> &'static str {
+        "local"
+    }
+
+    fn prove(&self, request: &ProofRequest) -> Result<ProofReceipt, HostError> {
+        prove_external(self.name(), request, false)
+    }
+}
+
+/**
+ * Execute without proving and return fake receipts, which only verify with `RISC0_DEV_MODE`
+ * set.
+ **/
+pub struct DevModeBackend;
+
+impl DevModeBackend {
+    pub fn new() -> Self {
+        DevModeBackend
+    }
+}
+
+impl Default for DevModeBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ProverBackend for DevModeBackend {
+    fn name(&self) -> &'static str {
+        "dev-mode"
+    }
+
+    fn prove(&self, request: &ProofRequest) -> Result<ProofReceipt, HostError> {
+        prove_external(self.name(), request, true)
+    }
+}
+
+#[derive(Deserialize)]
+struct UploadRes {
+    url: String,
+    uuid: String,
+}
+
+#[derive(Deserialize)]
+struct ImgUploadRes {
+    url: String,
+}
+
+#[derive(Serialize)]
+struct ProofReq {
+    img: String,
+    input: String,
+    assumptions: Vec<String>,
+    execute_only: bool,
+    exec_cycle_limit: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct CreateSessRes {
+    uuid: String,
+}
+
+#[derive(Deserialize)]
+struct SessionStatusRes {
+    status: String,
+    receipt_url: Option<String>,
+    error_msg: Option<String>,
+}
+
+/**
+ * Prove on Bonsai over its REST API: upload the image, the inputs and the assumptions,
+ * start a session and poll it until the receipt can be downloaded.
+ **/
+pub struct BonsaiBackend {
+    url: String,
+    api_key: String,
+    agent: ureq::Agent,
+    config: BonsaiConfig,
+}
+
+impl BonsaiBackend {
+    pub fn new(config: &BonsaiConfig) -> Result<Self, HostError> {
+        let setting = |value: &Option<String>, var: &str| {
+            value
+                .clone()
+                .or_else(|| std::env::var(var).ok())
+                .ok_or_else(|| {
+                    HostError::InvalidBackend(format!(
+                        "the bonsai prover needs {} or a config",
+                        var
+                    ))
+                })
+        };
+
+        Ok(BonsaiBackend {
+            url: setting(&config.url, "BONSAI_API_URL")?
+                .trim_end_matches('/')
+                .to_string(),
+            api_key: setting(&config.api_key, "BONSAI_API_KEY")?,
+            agent: ureq::AgentBuilder::new()
+                .timeout(Duration::from_secs(config.request_timeout_secs))
+                .build(),
+            config: config.clone(),
+        })
+    }
+
+    fn request(&self, method: &str, url: &str) -> ureq::Request {
+        self.agent
+            .request(method, url)
+            .set(API_KEY_HEADER, &self.api_key)
+            .set(VERSION_HEADER, risc0_zkvm::VERSION)
+    }
+
+    /**
+     * Send a request, retrying transport errors, rate limits and server errors. Only for
+     * GET and PUT requests, which can be sent twice.
+     **/
+    fn send(
+        &self,
+        send: impl Fn() -> Result<ureq::Response, Box<ureq::Error>>,
+    ) -> Result<ureq::Response, HostError> {
+        let mut delay = Duration::from_millis(self.config.retry_delay_ms);
+        let mut attempt = 0;
+        loop {
+            let result = send();
+            let retryable = match &result {
+                Ok(_) => false,
+                Err(err) => match err.as_ref() {
+                    ureq::Error::Status(status, _) => *status == 429 || *status >= 500,
+                    ureq::Error::Transport(_) => true,
+                },
+            };
+            if !retryable || attempt == self.config.retries {
+                return result.map_err(HostError::Http);
+            }
+            attempt += 1;
+            tracing::warn!(attempt, "bonsai request failed, retrying");
+            std::thread::sleep(delay);
+            delay *= 2;
+        }
+    }
+
+    fn get(&self, route: &str) -> Result<ureq::Response, HostError> {
+        let url = format!("{}/{}", self.url, route);
+        self.send(|| self.request("GET", &url).call().map_err(Box::new))
+    }
+
+    fn upload(&self, url: &str, bytes: &[u8]) -> Result<(), HostError> {
+        self.send(|| self.request("PUT", url).send_bytes(bytes).map_err(Box::new))?;
+
+        Ok(())
+    }
+
+    fn upload_image(&self, image_id: &str, elf: &[u8]) -> Result<(), HostError> {
+        let response = self.get(&format!("images/upload/{}", image_id))?;
+        // The image was uploaded before.
+        if response.status() == 204 {
+            return Ok(());
+        }
+
+        let upload: ImgUploadRes = response.into_json()?;
+        self.upload(&upload.url, elf)
+    }
+
+    fn upload_data(&self, route: &str, bytes: &[u8]) -> Result<String, HostError> {
+        let upload: UploadRes = self.get(&format!("{}/upload", route))?.into_json()?;
+        self.upload(&upload.url, bytes)?;
+
+        Ok(upload.uuid)
+    }
+
+    /**
+     * Start a session. Not retried: a request that failed or timed out may still have
+     * started one, and a second would be another proof to pay for.
+     **/
+    fn create_session(&self, request: &ProofReq) -> Result<String, HostError> {
+        let url = format!("{}/sessions/create", self.url);
+        let session: CreateSessRes = self
+            .request("POST", &url)
+            .send_json(request)
+            .map_err(|err| HostError::Http(Box::new(err)))?
+            .into_json()?;
+
+        Ok(session.uuid)
+    }
+
+    fn stop(&self, session: &str) {
+        if let Err(err) = self.get(&format!("sessions/stop/{}", session)) {
+            tracing::warn!(session, "failed to stop the bonsai session: {}", err);
+        }
+    }
+
+    /**
+     * Poll the session until it succeeds, returning the receipt URL, or fails, times out or
+     * is cancelled, stopping it in the last two cases.
+     **/
+    fn wait(&self, session: &str, cancel: &CancelToken) -> Result<String, HostError> {
+        let start = Instant::now();
+        let timeout = Duration::from_secs(self.config.proof_timeout_secs);
+        loop {
+            if cancel.is_cancelled() {
+                self.stop(session);
+                return Err(HostError::ProofCancelled(session.to_string()));
+            }
+            if start.elapsed() > timeout {
+                self.stop(session);
+                return Err(HostError::ProofTimeout {
+                    session: session.to_string(),
+                    timeout,
+                });
+            }
+
+            let status: SessionStatusRes = self
+                .get(&format!("sessions/status/{}", session))?
+                .into_json()?;
+            match status.status.as_str() {
+                "RUNNING" => {
+                    std::thread::sleep(Duration::from_millis(self.config.poll_interval_ms))
+                }
+                "SUCCEEDED" => {
+                    return status.receipt_url.ok_or_else(|| {
+                        HostError::Bonsai(format!(
+                            "session {} succeeded without a receipt",
+                            session
+                        ))
+                    })
+                }
+                _ => {
+                    let error = status.error_msg.unwrap_or_default();
+                    // Bonsai reports the executor's error, like a local execution.
+                    if error.contains("Session limit exceeded") {
+                        return Err(HostError::SessionLimitExceeded(anyhow::anyhow!(error)));
+                    }
+                    return Err(HostError::Bonsai(format!(
+                        "session {} {}: {}",
+                        session, status.status, error
+                    )));
+                }
+            }
+        }
+    }
+}
+
+impl ProverBackend for BonsaiBackend {
+    fn name(&self) -> &'static str {
+        "bonsai"
+    }
+
+    fn prove(&self, request: &ProofRequest) -> Result<ProofReceipt, HostError> {
+        let mut assumptions = Vec::with_capacity(request.assumptions.len());
+        for receipt in request.assumptions {
+            let inner: InnerAssumptionReceipt = receipt.inner.clone().into();
+            if !matches!(inner, InnerAssumptionReceipt::Succinct(_)) {
+                return Err(HostError::Bonsai(
+                    "bonsai only resolves assumptions with succinct receipts, it can't \
+                     compress them like the local prover"
+                        .to_string(),
+                ));
+            }
+            assumptions.push(inner);
+        }
+
+        let image_id = image_id_hex(&request.image_id)
+            .trim_start_matches("0x")
+            .to_string();
+        let cancelled = || HostError::ProofCancelled(image_id_hex(&request.image_id));
+        if request.cancel.is_cancelled() {
+            return Err(cancelled());
+        }
+        self.upload_image(&image_id, request.elf)?;
+
+        let input: Vec<u8> = request
+            .input
+            .iter()
+            .flat_map(|word| word.to_le_bytes())
+            .collect();
+        let input = self.upload_data("inputs", &input)?;
+
+        let assumptions = assumptions
+            .iter()
+            .map(|inner| self.upload_data("receipts", &bincode::serialize(inner)?))
+            .collect::<Result<Vec<_>, HostError>>()?;
+
+        // Nothing to stop yet, a session is only started if still wanted.
+        if request.cancel.is_cancelled() {
+            return Err(cancelled());
+        }
+        let session_limit = executor_limits().session_limit;
+        let session = self.create_session(&ProofReq {
+            img: image_id,
+            input,
+            assumptions,
+            execute_only: false,
+            exec_cycle_limit: Some(session_limit).filter(|limit| *limit != 0),
+        })?;
+        tracing::info!(session, "bonsai session started");
+
+        let receipt_url = self.wait(&session, request.cancel)?;
+        let mut bytes = Vec::new();
+        self.send(|| self.request("GET", &receipt_url).call().map_err(Box::new))?
+            .into_reader()
+            .read_to_end(&mut bytes)?;
+
+        Ok(bincode::deserialize(&bytes)?)
+    }
+}
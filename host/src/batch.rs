@@ -0,0 +1,290 @@
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+
+use methods::RECEIPTS_ROOT_AND_PRODUCT_TREE_ID;
+use trie_core::chunk::node_hash;
+use trie_core::{Inputs, Outputs};
+use verifier::artifacts::{load_receipt, save_receipt, OutputsJson};
+
+use crate::backend::shutdown_token;
+use crate::error::HostError;
+use crate::prover::{prove_inputs, read_inputs, verify_saved};
+
+/**
+ * Blocks to prove: `name` identifies the block in outputs, usually its number, and names its
+ * files in the output directory, `inputs` is a file written by `build-inputs`, relative to
+ * the manifest.
+ **/
+#[derive(Debug, Deserialize)]
+pub struct Manifest {
+    pub blocks: Vec<ManifestBlock>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ManifestBlock {
+    pub name: String,
+    pub inputs: PathBuf,
+}
+
+#[derive(Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlockStatus {
+    Proved,
+    Skipped,
+    Failed,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct BlockResult {
+    pub name: String,
+    pub status: BlockStatus,
+    pub receipt: PathBuf,
+    pub elapsed_secs: f64,
+    pub outputs: Option<OutputsJson>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Summary {
+    pub proved: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    pub elapsed_secs: f64,
+    pub blocks: Vec<BlockResult>,
+}
+
+/**
+ * Read the blocks to prove from a manifest (TOML, or JSON by extension),
+ * or from a directory where every `.bin` file is the inputs of the block named after it.
+ **/
+pub fn load_blocks(path: &Path) -> Result<Vec<ManifestBlock>, HostError> {
+    if path.is_dir() {
+        let mut blocks = Vec::new();
+        for entry in std::fs::read_dir(path)? {
+            let inputs = entry?.path();
+            if inputs.extension().and_then(|ext| ext.to_str()) != Some("bin") {
+                continue;
+            }
+            if let Some(name) = inputs.file_stem().and_then(|stem| stem.to_str()) {
+                blocks.push(ManifestBlock {
+                    name: name.to_string(),
+                    inputs: inputs.clone(),
+                });
+            }
+        }
+        blocks.sort_by(|a, b| a.name.cmp(&b.name));
+        return Ok(blocks);
+    }
+
+    let content = std::fs::read_to_string(path)?;
+    let manifest: Manifest = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("json") => serde_json::from_str(&content)?,
+        _ => toml::from_str(&content)?,
+    };
+
+    let base = path.parent().unwrap_or_else(|| Path::new("."));
+    manifest
+        .blocks
+        .into_iter()
+        .map(|block| {
+            check_name(&block.name)?;
+            Ok(ManifestBlock {
+                inputs: base.join(&block.inputs),
+                name: block.name,
+            })
+        })
+        .collect()
+}
+
+/**
+ * Block names become file names in the output directory, they can't leave it.
+ **/
+fn check_name(name: &str) -> Result<(), HostError> {
+    if name.is_empty() || name.contains(['/', '\\']) || name.contains("..") {
+        return Err(HostError::InvalidInput(format!(
+            "invalid block name {:?}, names can't be empty or contain path separators or ..",
+            name
+        )));
+    }
+
+    Ok(())
+}
+
+/**
+ * Prove every block with `jobs` workers, writing `<name>.receipt.bin` and its journal in
+ * `output_dir`, then `summary.json`. Blocks whose receipt already exists and verifies are
+ * skipped, so an interrupted batch resumes where it stopped.
+ **/
+pub fn run(blocks: &Path, output_dir: &Path, jobs: usize) -> Result<Summary, HostError> {
+    let blocks = load_blocks(blocks)?;
+    std::fs::create_dir_all(output_dir)?;
+
+    let time = Instant::now();
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(Vec::with_capacity(blocks.len()));
+
+    std::thread::scope(|scope| {
+        for _ in 0..jobs.max(1) {
+            scope.spawn(|| loop {
+                let idx = next.fetch_add(1, Ordering::SeqCst);
+                let Some(block) = blocks.get(idx) else {
+                    break;
+                };
+
+                let result = prove_block(block, output_dir);
+                tracing::info!(
+                    block = %result.name,
+                    status = ?result.status,
+                    error = result.error.as_deref(),
+                    "{}/{}",
+                    idx + 1,
+                    blocks.len()
+                );
+                results
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner())
+                    .push((idx, result));
+            });
+        }
+    });
+
+    let mut results = results
+        .into_inner()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    results.sort_by_key(|(idx, _)| *idx);
+    let blocks: Vec<BlockResult> = results.into_iter().map(|(_, result)| result).collect();
+
+    let count = |status: BlockStatus| blocks.iter().filter(|b| b.status == status).count();
+    let summary = Summary {
+        proved: count(BlockStatus::Proved),
+        skipped: count(BlockStatus::Skipped),
+        failed: count(BlockStatus::Failed),
+        elapsed_secs: time.elapsed().as_secs_f64(),
+        blocks,
+    };
+    std::fs::write(
+        output_dir.join("summary.json"),
+        serde_json::to_string_pretty(&summary)?,
+    )?;
+
+    Ok(summary)
+}
+
+fn prove_block(block: &ManifestBlock, output_dir: &Path) -> BlockResult {
+    let time = Instant::now();
+    let receipt_path = output_dir.join(format!("{}.receipt.bin", block.name));
+
+    let (status, result) = match read_inputs(&block.inputs) {
+        Err(err) => (BlockStatus::Failed, Err(err)),
+        Ok(inputs) => match existing_outputs(&receipt_path, &inputs) {
+            Some(outputs) => (BlockStatus::Skipped, Ok(outputs)),
+            None => {
+                let result = prove_inputs(&inputs, shutdown_token()).and_then(|receipt| {
+                    save_receipt(&receipt_path, &receipt, &RECEIPTS_ROOT_AND_PRODUCT_TREE_ID)?;
+                    verify_saved(&load_receipt(&receipt_path)?)
+                });
+                (BlockStatus::Proved, result)
+            }
+        },
+    };
+
+    let (status, outputs, error) = match result {
+        Ok(outputs) => (status, Some(OutputsJson::from(&outputs)), None),
+        Err(err) => (BlockStatus::Failed, None, Some(err.to_string())),
+    };
+
+    BlockResult {
+        name: block.name.clone(),
+        status,
+        receipt: receipt_path,
+        elapsed_secs: time.elapsed().as_secs_f64(),
+        outputs,
+        error,
+    }
+}
+
+/**
+ * Outputs of a receipt left by a previous run, if it is complete, verifies and proves the
+ * current inputs. A receipt of an edited block or of another registry is proven again.
+ **/
+fn existing_outputs(receipt_path: &Path, inputs: &Inputs) -> Option<Outputs> {
+    if !receipt_path.exists() {
+        return None;
+    }
+    let outputs = load_receipt(receipt_path)
+        .ok()
+        .and_then(|saved| verify_saved(&saved).ok())?;
+    if !proves_inputs(&outputs, inputs) {
+        tracing::warn!(
+            receipt = %receipt_path.display(),
+            "receipt doesn't match the inputs, proving again"
+        );
+        return None;
+    }
+
+    Some(outputs)
+}
+
+/**
+ * Whether outputs commit to the receipts root and registry of `inputs`.
+ **/
+fn proves_inputs(outputs: &Outputs, inputs: &Inputs) -> bool {
+    node_hash(&inputs.root).is_ok_and(|root| root == outputs.root)
+        && outputs.registry_hash == inputs.registry.hash()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use trie_core::{JournalEncoding, Node, Registry};
+
+    #[test]
+    fn test_block_names() {
+        let dir = std::env::temp_dir().join(format!("batch-names-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let manifest = dir.join("blocks.toml");
+
+        std::fs::write(
+            &manifest,
+            "[[blocks]]\nname = \"18000000\"\ninputs = \"a.bin\"\n",
+        )
+        .unwrap();
+        let blocks = load_blocks(&manifest).unwrap();
+        assert_eq!(blocks[0].inputs, dir.join("a.bin"));
+
+        for name in ["../escape", "a/b", "a\\b", ".."] {
+            let content = format!("[[blocks]]\nname = {:?}\ninputs = \"a.bin\"\n", name);
+            std::fs::write(&manifest, content).unwrap();
+            assert!(load_blocks(&manifest).is_err(), "{}", name);
+        }
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_proves_inputs() {
+        let inputs = Inputs {
+            root: Node::Leaf(vec![0xc0]),
+            log_addresses: vec![],
+            registry: Registry::default(),
+            journal_encoding: JournalEncoding::Risc0,
+        };
+        let mut outputs = Outputs {
+            root: node_hash(&inputs.root).unwrap(),
+            product_tree_hash: [0; 32],
+            product_tree_root: 1u128.to_be_bytes(),
+            registry_hash: inputs.registry.hash(),
+            untracked_logs: 0,
+        };
+        assert!(proves_inputs(&outputs, &inputs));
+
+        outputs.registry_hash = [0; 32];
+        assert!(!proves_inputs(&outputs, &inputs));
+        outputs.registry_hash = inputs.registry.hash();
+        outputs.root = [0; 32];
+        assert!(!proves_inputs(&outputs, &inputs));
+    }
+}
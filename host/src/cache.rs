@@ -0,0 +1,211 @@
+use risc0_zkvm::Receipt as ProofReceipt;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use methods::RECEIPTS_ROOT_AND_PRODUCT_TREE_ID;
+use trie_core::{keccak256, Inputs, Outputs};
+use verifier::artifacts::{journal_path, load_receipt, save_receipt, SavedReceipt};
+
+use crate::backend::shutdown_token;
+use crate::error::HostError;
+use crate::prover::{prove_inputs, verify_saved};
+
+pub const DEFAULT_MAX_BYTES: u64 = 1 << 30;
+
+/**
+ * Directory of previous receipts, keyed by the hash of the serialized inputs and the image ID,
+ * so proving the same inputs with the same guest again is a lookup. Entries are re-verified
+ * on every hit. When the directory grows over `max_bytes`, the least recently used entries
+ * are evicted, the modification time of an entry's receipt file recording its last use.
+ **/
+pub struct ProofCache {
+    dir: PathBuf,
+    max_bytes: u64,
+}
+
+/**
+ * Result of `ProofCache::prove`: the receipt, its outputs, and whether it came from the cache.
+ **/
+pub struct CachedProof {
+    pub receipt: ProofReceipt,
+    pub outputs: Outputs,
+    pub hit: bool,
+}
+
+impl ProofCache {
+    pub fn new(dir: &Path, max_bytes: u64) -> Result<Self, HostError> {
+        std::fs::create_dir_all(dir)?;
+        Ok(ProofCache {
+            dir: dir.to_path_buf(),
+            max_bytes,
+        })
+    }
+
+    /**
+     * Cache key: keccak256 of the image ID words (little endian) followed by the bincode
+     * serialized inputs.
+     **/
+    pub fn key(inputs: &Inputs, image_id: &[u32; 8]) -> Result<String, HostError> {
+        let mut bytes: Vec<u8> = image_id
+            .iter()
+            .flat_map(|word| word.to_le_bytes())
+            .collect();
+        bytes.extend_from_slice(&bincode::serialize(inputs)?);
+
+        Ok(hex::encode(keccak256(&bytes)))
+    }
+
+    pub fn receipt_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.receipt.bin", key))
+    }
+
+    /**
+     * The cached receipt for `key`, if there is one and it still verifies. Entries that
+     * don't verify, e.g. written by another risc0 version, are removed.
+     **/
+    pub fn get(&self, key: &str) -> Option<(ProofReceipt, Outputs)> {
+        let path = self.receipt_path(key);
+        if !path.exists() {
+            return None;
+        }
+
+        let verified = load_receipt(&path).ok().and_then(|saved| {
+            let outputs = verify_saved(&saved).ok()?;
+            Some((saved.receipt, outputs))
+        });
+        match verified {
+            Some(entry) => {
+                // Setting the modification time marks the entry as used.
+                if let Ok(file) = File::options().write(true).open(&path) {
+                    let _ = file.set_modified(SystemTime::now());
+                }
+                Some(entry)
+            }
+            None => {
+                tracing::warn!(key, "cached receipt doesn't verify, removing it");
+                self.remove(key);
+                None
+            }
+        }
+    }
+
+    pub fn put(&self, key: &str, receipt: &ProofReceipt) -> Result<(), HostError> {
+        save_receipt(
+            &self.receipt_path(key),
+            receipt,
+            &RECEIPTS_ROOT_AND_PRODUCT_TREE_ID,
+        )?;
+        self.evict()
+    }
+
+    /**
+     * Return the cached receipt of `inputs`, or prove them, verify the receipt and cache it.
+     **/
+    pub fn prove(&self, inputs: &Inputs) -> Result<CachedProof, HostError> {
+        let key = Self::key(inputs, &RECEIPTS_ROOT_AND_PRODUCT_TREE_ID)?;
+        if let Some((receipt, outputs)) = self.get(&key) {
+            tracing::info!(key, "proof cache hit");
+            return Ok(CachedProof {
+                receipt,
+                outputs,
+                hit: true,
+            });
+        }
+
+        tracing::info!(key, "proof cache miss");
+        let saved = SavedReceipt {
+            image_id: RECEIPTS_ROOT_AND_PRODUCT_TREE_ID,
+            receipt: prove_inputs(inputs, shutdown_token())?,
+        };
+        let outputs = verify_saved(&saved)?;
+        self.put(&key, &saved.receipt)?;
+
+        Ok(CachedProof {
+            receipt: saved.receipt,
+            outputs,
+            hit: false,
+        })
+    }
+
+    /**
+     * Remove the least recently used entries until the cache fits in `max_bytes`.
+     **/
+    pub fn evict(&self) -> Result<(), HostError> {
+        let mut entries = Vec::new();
+        let mut total = 0;
+        for entry in std::fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            let Some(key) = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_suffix(".receipt.bin"))
+            else {
+                continue;
+            };
+
+            let metadata = std::fs::metadata(&path)?;
+            let size = metadata.len()
+                + std::fs::metadata(journal_path(&path)).map_or(0, |journal| journal.len());
+            total += size;
+            entries.push((metadata.modified()?, size, key.to_string()));
+        }
+
+        entries.sort();
+        for (_, size, key) in entries {
+            if total <= self.max_bytes {
+                break;
+            }
+            tracing::info!(key, size, "evicting cached receipt");
+            self.remove(&key);
+            total -= size;
+        }
+
+        Ok(())
+    }
+
+    fn remove(&self, key: &str) {
+        let path = self.receipt_path(key);
+        let _ = std::fs::remove_file(journal_path(&path));
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn entry(cache: &ProofCache, key: &str, size: usize, age_secs: u64) {
+        let path = cache.receipt_path(key);
+        std::fs::write(&path, vec![0u8; size]).unwrap();
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(SystemTime::now() - Duration::from_secs(age_secs))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_evict_oldest_first() {
+        let dir = std::env::temp_dir().join(format!("proof-cache-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let cache = ProofCache::new(&dir, 250).unwrap();
+
+        entry(&cache, "old", 100, 30);
+        entry(&cache, "recent", 100, 10);
+        entry(&cache, "new", 100, 0);
+        cache.evict().unwrap();
+
+        assert!(!cache.receipt_path("old").exists());
+        assert!(cache.receipt_path("recent").exists());
+        assert!(cache.receipt_path("new").exists());
+
+        // Not a receipt, the entry is dropped instead of being returned.
+        assert!(cache.get("new").is_none());
+        assert!(!cache.receipt_path("new").exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,160 @@
+use methods::{BLOCK_CHUNK_ELF, BLOCK_CHUNK_ID, CHUNK_COMBINE_ELF, CHUNK_COMBINE_ID};
+use risc0_zkvm::Receipt as ProofReceipt;
+
+use trie_core::chunk::{leaf_count, split_inputs, CombineInputs};
+use trie_core::{Inputs, JournalEncoding, Outputs};
+use verifier::artifacts::SavedReceipt;
+use verifier::{verify_chunked_receipt, VerifyError};
+
+use crate::backend::shutdown_token;
+use crate::error::HostError;
+use crate::prover::{guest_input, prove_composition, prove_elf};
+
+// Receipts and logs a chunk is sized for, well within a single execution.
+const CHUNK_RECEIPTS: usize = 256;
+const CHUNK_LOGS: usize = 1024;
+
+/**
+ * Number of chunks to split a block in, so that each has at most `CHUNK_RECEIPTS` receipts
+ * and `CHUNK_LOGS` logs, give or take the shape of the trie.
+ **/
+pub fn chunk_count(inputs: &Inputs) -> usize {
+    let receipts = leaf_count(&inputs.root).div_ceil(CHUNK_RECEIPTS);
+    let logs = inputs.log_addresses.len().div_ceil(CHUNK_LOGS);
+
+    receipts.max(logs).max(1)
+}
+
+/**
+ * Prove a block in chunks, each proving some subtries of the receipts trie and a slice of
+ * the logs, then combine them in a guest that verifies the chunk receipts. The outputs are
+ * those of a single proof, under the image ID of the combine guest.
+ **/
+pub fn prove_chunked(
+    inputs: &Inputs,
+    chunk_count: usize,
+) -> Result<(ProofReceipt, Outputs), HostError> {
+    if inputs.journal_encoding != JournalEncoding::Risc0 {
+        return Err(HostError::InvalidInput(
+            "chunked proofs only commit risc0 journals".to_string(),
+        ));
+    }
+
+    let (top, chunks) = split_inputs(inputs, chunk_count);
+    let mut receipts = Vec::with_capacity(chunks.len());
+    for (index, chunk) in chunks.iter().enumerate() {
+        let receipt = prove_elf(
+            &guest_input(chunk)?,
+            BLOCK_CHUNK_ELF,
+            BLOCK_CHUNK_ID,
+            shutdown_token(),
+        )?;
+        receipt
+            .verify(BLOCK_CHUNK_ID)
+            .map_err(|err| VerifyError::InvalidProof(err.to_string()))?;
+        tracing::info!(chunk = index, of = chunks.len(), "chunk proven");
+        receipts.push(receipt);
+    }
+
+    let combine_inputs = CombineInputs {
+        chunk_image_id: BLOCK_CHUNK_ID,
+        top,
+        chunks: receipts
+            .iter()
+            .map(|receipt| receipt.journal.bytes.clone())
+            .collect(),
+    };
+    let receipt = prove_composition(
+        &combine_inputs,
+        &receipts,
+        CHUNK_COMBINE_ELF,
+        CHUNK_COMBINE_ID,
+        shutdown_token(),
+    )?;
+    receipt
+        .verify(CHUNK_COMBINE_ID)
+        .map_err(|err| VerifyError::InvalidProof(err.to_string()))?;
+    let outputs = decode_chunked_journal(&receipt.journal.bytes)?;
+
+    Ok((receipt, outputs))
+}
+
+/**
+ * Decode the journal of the combine guest and check the chunks were verified against the
+ * chunk guest.
+ **/
+pub fn decode_chunked_journal(journal: &[u8]) -> Result<Outputs, HostError> {
+    Ok(verifier::decode_chunked_journal(journal, &BLOCK_CHUNK_ID)?)
+}
+
+pub fn verify_chunked(saved: &SavedReceipt) -> Result<Outputs, HostError> {
+    Ok(verify_chunked_receipt(
+        saved,
+        &CHUNK_COMBINE_ID,
+        &BLOCK_CHUNK_ID,
+    )?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use risc0_zkvm::serde::to_vec;
+    use trie_core::chunk::ChunkedOutputs;
+    use trie_core::{Node, Registry};
+    use verifier::artifacts::{decode_outputs, load_journal, save_journal};
+
+    #[test]
+    fn test_chunk_count() {
+        let mut inputs = Inputs {
+            root: Node::Leaf(vec![0xaa]),
+            log_addresses: vec![],
+            registry: Registry::new(vec![]).unwrap(),
+            journal_encoding: JournalEncoding::Risc0,
+        };
+        assert_eq!(chunk_count(&inputs), 1);
+
+        inputs.log_addresses = vec![String::new(); 2500];
+        assert_eq!(chunk_count(&inputs), 3);
+    }
+
+    #[test]
+    fn test_decode_chunked_journal() {
+        let outputs = Outputs {
+            root: [0x11; 32],
+            product_tree_hash: [0x22; 32],
+            product_tree_root: 6u128.to_be_bytes(),
+            registry_hash: [0x33; 32],
+            untracked_logs: 1,
+        };
+        let journal = |chunk_image_id| -> Vec<u8> {
+            to_vec(&ChunkedOutputs {
+                outputs: outputs.clone(),
+                chunk_image_id,
+            })
+            .unwrap()
+            .iter()
+            .flat_map(|word| word.to_le_bytes())
+            .collect()
+        };
+
+        assert_eq!(
+            decode_chunked_journal(&journal(BLOCK_CHUNK_ID)).unwrap(),
+            outputs
+        );
+        // The outputs of a combine receipt over any other chunk guest are refused, whether
+        // read as a combine journal or as plain outputs.
+        let foreign = journal([7; 8]);
+        assert!(matches!(
+            decode_chunked_journal(&foreign),
+            Err(HostError::Verify(VerifyError::ImageIdMismatch { .. }))
+        ));
+        assert!(decode_outputs(&foreign).is_err());
+        assert!(decode_outputs(&journal(BLOCK_CHUNK_ID)).is_err());
+
+        // Journal files aren't proofs, they still show the outputs.
+        let path = std::env::temp_dir().join(format!("chunked-{}.json", std::process::id()));
+        save_journal(&path, &foreign, &outputs, &CHUNK_COMBINE_ID).unwrap();
+        assert_eq!(load_journal(&path).unwrap().1, outputs);
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,533 @@
+use methods::{
+    CHUNK_COMBINE_ID, RECEIPTS_ROOT_AND_PRODUCT_TREE_ELF, RECEIPTS_ROOT_AND_PRODUCT_TREE_ID,
+};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use trie_core::journal::ABI_JOURNAL_LEN;
+use trie_core::{JournalEncoding, Registry};
+
+use host::accumulator::AccumulatorChain;
+use host::backend::shutdown_token;
+use host::cache::ProofCache;
+use host::chunk::{chunk_count, prove_chunked};
+use host::error::HostError;
+use host::input::{load_header, load_receipts};
+use host::prover::{
+    decode_journal, execute_env, executor_env, guest_input, inputs_from_trie, journal_digest,
+    prove_elf, read_inputs, verify_saved, write_inputs, ExecutionStats,
+};
+use host::query::{count_accumulated_logs, count_logs, count_range_logs};
+use host::range::{save_range_journal, RangeBlock, RangeJob};
+use host::registry::{load_registry, save_registry};
+use host::report::Report;
+use host::rpc::{BlockId, RpcClient};
+use host::server::{
+    Backend, Server, ServerConfig, DEFAULT_MAX_BODY_BYTES, DEFAULT_MAX_FINISHED_JOBS,
+};
+use host::trie::{build_from_receipts, check_receipts_root};
+use verifier::artifacts::{
+    image_id_hex, journal_path, load_journal, load_receipt, save_journal, save_receipt,
+    write_receipt,
+};
+use verifier::VerifyError;
+
+use crate::{AccumulatorAction, RegistryAction};
+
+/**
+ * Where `build-inputs` reads the block receipts from.
+ **/
+pub enum ReceiptsSource {
+    File {
+        receipts: PathBuf,
+        header: Option<PathBuf>,
+    },
+    Rpc {
+        url: String,
+        block: BlockId,
+    },
+}
+
+/**
+ * Build the guest inputs. When a header is known, from `--header` or the node, the receipts
+ * root is checked against it first so a wrong or incomplete receipts file is refused before
+ * any proving time is spent.
+ **/
+pub fn build_inputs(
+    source: ReceiptsSource,
+    registry: &Path,
+    output: &Path,
+    journal_encoding: JournalEncoding,
+    report: &mut Report,
+) -> Result<(), HostError> {
+    let (registry, receipts, header) = report.phase("load", || {
+        let registry = load_registry(registry)?;
+        let (receipts, header) = match source {
+            ReceiptsSource::File { receipts, header } => {
+                let header = header.as_deref().map(load_header).transpose()?;
+                (load_receipts(&receipts)?, header)
+            }
+            ReceiptsSource::Rpc { url, block } => {
+                let block = RpcClient::new(&url).fetch_block(&block)?;
+                tracing::info!(
+                    number = block.header.number,
+                    hash = ?block.header.hash,
+                    receipts = block.receipts.len(),
+                    "fetched block"
+                );
+                (block.receipts, Some(block.header))
+            }
+        };
+        Ok::<_, HostError>((registry, receipts, header))
+    })?;
+
+    let trie = report.phase("trie_build", || build_from_receipts(&receipts))?;
+    report.receipt_count = Some(trie.receipt_count);
+    report.log_count = Some(trie.log_addresses.len());
+
+    match header {
+        Some(header) => {
+            check_receipts_root(&header, &trie)?;
+            println!("Receipts root matches block {} header", header.number);
+        }
+        None => println!("No header given, receipts root not checked"),
+    }
+
+    let inputs = inputs_from_trie(trie, registry, journal_encoding);
+    let bytes = report.phase("serialize_inputs", || write_inputs(output, &inputs))?;
+    report.input_bytes = Some(bytes);
+    println!("Inputs written to {}", output.display());
+
+    Ok(())
+}
+
+/**
+ * Save a block's header and receipts as returned by the node, for `build-inputs` to use offline.
+ **/
+pub fn fetch(
+    url: &str,
+    block: &BlockId,
+    receipts_output: &Path,
+    header_output: &Path,
+) -> Result<(), HostError> {
+    let block = RpcClient::new(url).fetch_block(block)?;
+    std::fs::write(receipts_output, serde_json::to_string(&block.raw_receipts)?)?;
+    std::fs::write(
+        header_output,
+        serde_json::to_string_pretty(&block.raw_header)?,
+    )?;
+
+    println!(
+        "Block {} ({:?}): {} receipts written to {}, header to {}",
+        block.header.number,
+        block.header.hash,
+        block.receipts.len(),
+        receipts_output.display(),
+        header_output.display()
+    );
+
+    Ok(())
+}
+
+/**
+ * Run the guest with the executor only, no proof is generated and no network is needed.
+ **/
+pub fn execute(inputs: &Path, output: Option<&Path>, report: &mut Report) -> Result<(), HostError> {
+    let inputs = report.phase("load", || read_inputs(inputs))?;
+    let (env, input_bytes) = report.phase("serialize_inputs", || executor_env(&inputs))?;
+    report.input_bytes = Some(input_bytes);
+
+    let session = report.phase("execute", || execute_env(env))?;
+
+    let stats = ExecutionStats::from_session(&session)?;
+    println!("Segments: {}", stats.segments);
+    println!("Total cycles: {}", stats.total_cycles);
+    println!("User cycles: {}", stats.user_cycles);
+    println!("Journal: 0x{}", hex::encode(&session.journal.bytes));
+    report.execution = Some(stats);
+
+    let outputs = decode_journal(&session.journal.bytes)?;
+    println!("Outputs: {:?}", outputs);
+    report.outputs = Some((&outputs).into());
+    if let Some(output) = output {
+        save_journal(
+            output,
+            &session.journal.bytes,
+            &outputs,
+            &RECEIPTS_ROOT_AND_PRODUCT_TREE_ID,
+        )?;
+        println!("Journal written to {}", output.display());
+    }
+
+    Ok(())
+}
+
+/**
+ * In dev mode risc0 skips proving and produces fake receipts, which only verify in dev mode.
+ **/
+pub fn set_dev_mode() {
+    eprintln!("WARNING: dev mode enabled, receipts are not real proofs");
+    std::env::set_var("RISC0_DEV_MODE", "1");
+}
+
+/**
+ * Prove the inputs and write the receipt. With a cache directory, a receipt of the same inputs
+ * and guest from a previous run is reused after verifying it again. `chunked` proves the block
+ * in chunks, `chunks` of them or as many as its size calls for.
+ **/
+pub fn prove(
+    inputs: &Path,
+    output: &Path,
+    cache: Option<(PathBuf, u64)>,
+    chunked: bool,
+    chunks: Option<usize>,
+    report: &mut Report,
+) -> Result<(), HostError> {
+    let inputs = report.phase("load", || read_inputs(inputs))?;
+
+    let (receipt, outputs, image_id) = if chunked {
+        let chunks = chunks.unwrap_or_else(|| chunk_count(&inputs));
+        println!("Proving in {} chunks", chunks);
+        let (receipt, outputs) = report.phase("prove", || prove_chunked(&inputs, chunks))?;
+        (receipt, outputs, CHUNK_COMBINE_ID)
+    } else if let Some((dir, max_bytes)) = cache {
+        let cache = ProofCache::new(&dir, max_bytes)?;
+        let proof = report.phase("prove", || cache.prove(&inputs))?;
+        println!(
+            "Proof cache {} in {}",
+            if proof.hit { "hit" } else { "miss" },
+            dir.display()
+        );
+        (
+            proof.receipt,
+            proof.outputs,
+            RECEIPTS_ROOT_AND_PRODUCT_TREE_ID,
+        )
+    } else {
+        let input = report.phase("serialize_inputs", || guest_input(&inputs))?;
+        report.input_bytes = Some(input.len() * 4);
+
+        let receipt = report.phase("prove", || {
+            prove_elf(
+                &input,
+                RECEIPTS_ROOT_AND_PRODUCT_TREE_ELF,
+                RECEIPTS_ROOT_AND_PRODUCT_TREE_ID,
+                shutdown_token(),
+            )
+        })?;
+        report
+            .phase("verify", || {
+                receipt.verify(RECEIPTS_ROOT_AND_PRODUCT_TREE_ID)
+            })
+            .map_err(|err| VerifyError::InvalidProof(err.to_string()))?;
+        let outputs = decode_journal(&receipt.journal.bytes)?;
+        (receipt, outputs, RECEIPTS_ROOT_AND_PRODUCT_TREE_ID)
+    };
+
+    save_receipt(output, &receipt, &image_id)?;
+    println!(
+        "Receipt written to {}, journal to {}",
+        output.display(),
+        journal_path(output).display()
+    );
+
+    println!("Outputs: {:?}", outputs);
+    report.outputs = Some((&outputs).into());
+
+    Ok(())
+}
+
+pub fn verify(receipt: &Path, output: Option<&Path>, report: &mut Report) -> Result<(), HostError> {
+    let saved = report.phase("load", || load_receipt(receipt))?;
+    let outputs = report.phase("verify", || verify_saved(&saved))?;
+    println!("Receipt verified");
+    println!("Outputs: {:?}", outputs);
+    report.outputs = Some((&outputs).into());
+
+    let journal = journal_path(receipt);
+    if journal.exists() {
+        let (file, journal_outputs) = load_journal(&journal)?;
+        if journal_outputs != outputs || file.image_id != image_id_hex(&saved.image_id) {
+            return Err(HostError::JournalMismatch(journal));
+        }
+    }
+
+    if let Some(output) = output {
+        save_journal(
+            output,
+            &saved.receipt.journal.bytes,
+            &outputs,
+            &saved.image_id,
+        )?;
+        println!("Journal written to {}", output.display());
+    }
+
+    Ok(())
+}
+
+/**
+ * Print the journal digest and ABI encoded outputs of a verified receipt.
+ **/
+pub fn journal_receipt(receipt: &Path) -> Result<(), HostError> {
+    let saved = load_receipt(receipt)?;
+    let outputs = verify_saved(&saved)?;
+    print_journal(&saved.receipt.journal.bytes, &outputs);
+
+    Ok(())
+}
+
+/**
+ * Print the journal digest and ABI encoded outputs of a journal file, without verifying a proof.
+ **/
+pub fn journal_file(journal: &Path) -> Result<(), HostError> {
+    let (file, outputs) = load_journal(journal)?;
+    let journal = hex::decode(file.journal.trim_start_matches("0x"))
+        .map_err(|err| HostError::InvalidInput(err.to_string()))?;
+    print_journal(&journal, &outputs);
+
+    Ok(())
+}
+
+fn print_journal(journal: &[u8], outputs: &trie_core::Outputs) {
+    let encoding = if journal.len() == ABI_JOURNAL_LEN {
+        "abi"
+    } else {
+        "risc0"
+    };
+    println!("Journal encoding: {}", encoding);
+    println!("Journal digest: 0x{}", hex::encode(journal_digest(journal)));
+    println!(
+        "ABI encoded outputs: 0x{}",
+        hex::encode(outputs.abi_encode())
+    );
+}
+
+pub fn query(
+    receipt: &Path,
+    registry: &Path,
+    address: Option<&str>,
+    report: &mut Report,
+) -> Result<(), HostError> {
+    let (saved, registry) = report.phase("load", || {
+        Ok::<_, HostError>((load_receipt(receipt)?, load_registry(registry)?))
+    })?;
+    let outputs = report.phase("verify", || verify_saved(&saved))?;
+    report.outputs = Some((&outputs).into());
+
+    let counts = report.phase("query", || count_logs(&outputs, &registry, address))?;
+    for (contract, count) in counts {
+        println!("{} ({}): {}", contract.address, contract.prime, count);
+    }
+    if address.is_none() {
+        println!("untracked: {}", outputs.untracked_logs);
+    }
+
+    Ok(())
+}
+
+/**
+ * Aggregate the per-block proofs of a block range into counts over the range, verifying
+ * them in the guest with `recursive`. The receipt is written to `output` with its journal
+ * next to it, only the journal with `execute_only`.
+ **/
+pub fn range(
+    blocks: &Path,
+    registry: &Path,
+    output: &Path,
+    recursive: bool,
+    execute_only: bool,
+    address: Option<&str>,
+    report: &mut Report,
+) -> Result<(), HostError> {
+    let (job, registry) = report.phase("load", || {
+        let registry = load_registry(registry)?;
+        Ok::<_, HostError>((
+            RangeJob::load(blocks, registry.clone(), recursive)?,
+            registry,
+        ))
+    })?;
+
+    let journal = journal_path(output);
+    let image_id = job.image_id();
+    let outputs = if execute_only {
+        let (session, outputs) = report.phase("execute", || job.execute())?;
+        report.execution = Some(ExecutionStats::from_session(&session)?);
+        save_range_journal(&journal, &image_id, &session.journal.bytes, &outputs)?;
+        println!("Journal written to {}", journal.display());
+        outputs
+    } else {
+        let (receipt, outputs) = report.phase("prove", || job.prove())?;
+        write_receipt(output, &receipt, &image_id)?;
+        save_range_journal(&journal, &image_id, &receipt.journal.bytes, &outputs)?;
+        println!(
+            "Receipt written to {}, journal to {}",
+            output.display(),
+            journal.display()
+        );
+        outputs
+    };
+
+    println!(
+        "Blocks {} (0x{}) to {} (0x{})",
+        outputs.first_block,
+        hex::encode(outputs.first_block_hash),
+        outputs.last_block,
+        hex::encode(outputs.last_block_hash)
+    );
+    for (contract, count) in count_range_logs(&outputs, &registry, address)? {
+        println!("{} ({}): {}", contract.address, contract.prime, count);
+    }
+    if address.is_none() {
+        println!("untracked: {}", outputs.untracked_logs);
+    }
+
+    Ok(())
+}
+
+pub fn accumulator(
+    dir: &Path,
+    action: AccumulatorAction,
+    report: &mut Report,
+) -> Result<(), HostError> {
+    let chain = AccumulatorChain::open(dir)?;
+    match action {
+        AccumulatorAction::Extend {
+            header,
+            receipt,
+            registry,
+            execute_only,
+        } => {
+            let registry = report.phase("load", || load_registry(&registry))?;
+            let block = RangeBlock {
+                header,
+                receipt: Some(receipt),
+                journal: None,
+            };
+            let phase = if execute_only { "execute" } else { "prove" };
+            let outputs = report.phase(phase, || chain.extend(&block, registry, execute_only))?;
+            println!(
+                "Block {} added, blocks {} to {}, state 0x{}",
+                outputs.state.last_block,
+                outputs.state.first_block,
+                outputs.state.last_block,
+                hex::encode(outputs.commitment)
+            );
+        }
+        AccumulatorAction::Show { registry, address } => {
+            let registry = load_registry(&registry)?;
+            let Some(head) = chain.head()? else {
+                println!("Empty chain in {}", dir.display());
+                return Ok(());
+            };
+            println!(
+                "Blocks {} to {} (0x{}), state 0x{}",
+                head.state.first_block,
+                head.state.last_block,
+                hex::encode(head.state.last_block_hash),
+                hex::encode(head.commitment)
+            );
+            for (contract, count) in
+                count_accumulated_logs(&head.state, &registry, address.as_deref())?
+            {
+                println!("{} ({}): {}", contract.address, contract.prime, count);
+            }
+            if address.is_none() {
+                println!("untracked: {}", head.state.untracked_logs);
+            }
+        }
+        AccumulatorAction::Verify => match report.phase("verify", || chain.verify())? {
+            Some(head) => println!(
+                "Chain verified, blocks {} to {}, state 0x{}",
+                head.state.first_block,
+                head.state.last_block,
+                hex::encode(head.commitment)
+            ),
+            None => println!("Empty chain in {}", dir.display()),
+        },
+    }
+
+    Ok(())
+}
+
+pub fn serve(
+    addr: String,
+    registry: &Path,
+    data_dir: PathBuf,
+    queue_capacity: usize,
+    workers: usize,
+    backend: Backend,
+) -> Result<(), HostError> {
+    let server = Server::bind(ServerConfig {
+        addr,
+        registry: load_registry(registry)?,
+        data_dir,
+        queue_capacity,
+        workers,
+        backend,
+        max_body_bytes: DEFAULT_MAX_BODY_BYTES,
+        max_finished_jobs: DEFAULT_MAX_FINISHED_JOBS,
+    })?;
+    if let Some(addr) = server.addr() {
+        println!("Listening on http://{}", addr);
+    }
+
+    // SIGINT cancels the jobs through the server's token, this also stops accepting requests.
+    let server = Arc::new(server);
+    let watched = server.clone();
+    std::thread::spawn(move || {
+        while !shutdown_token().is_cancelled() {
+            std::thread::sleep(Duration::from_millis(200));
+        }
+        watched.shutdown();
+    });
+    server.run();
+
+    Ok(())
+}
+
+pub fn registry(path: &Path, action: RegistryAction) -> Result<(), HostError> {
+    match action {
+        RegistryAction::Init => {
+            if path.exists() {
+                return Err(HostError::AlreadyExists(path.to_path_buf()));
+            }
+            save_registry(path, &Registry::default())?;
+        }
+        RegistryAction::Add { address, prime } => {
+            let registry = load_registry(path)?;
+            let prime = prime.unwrap_or_else(|| registry.next_free_prime());
+
+            let mut entries: Vec<(String, u64)> = registry
+                .contracts
+                .into_iter()
+                .map(|contract| (contract.address, contract.prime))
+                .collect();
+            entries.push((address.clone(), prime));
+
+            save_registry(path, &Registry::new(entries)?)?;
+            println!("{} = {}", address.to_lowercase(), prime);
+        }
+        RegistryAction::Remove { address } => {
+            let mut registry = load_registry(path)?;
+            let len = registry.contracts.len();
+            registry
+                .contracts
+                .retain(|contract| !contract.address.eq_ignore_ascii_case(&address));
+            if registry.contracts.len() == len {
+                return Err(HostError::NotRegistered(address));
+            }
+            save_registry(path, &registry)?;
+        }
+        RegistryAction::List => {
+            for contract in load_registry(path)?.contracts {
+                println!("{} {}", contract.address, contract.prime);
+            }
+        }
+        RegistryAction::Hash => {
+            println!("0x{}", hex::encode(load_registry(path)?.hash()));
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,78 @@
+use serde::Deserialize;
+use std::path::Path;
+
+use crate::backend::ProverConfig;
+use crate::error::HostError;
+use crate::prover::ExecutorLimits;
+
+/**
+ * Host settings read from a TOML file, or JSON by extension. Every section is optional:
+ *
+ * ```toml
+ * [executor]
+ * session_limit = 8589934592
+ * segment_limit_po2 = 19
+ * memory_mb = 16384
+ *
+ * [prover]
+ * backend = "bonsai"
+ *
+ * [prover.bonsai]
+ * url = "https://api.bonsai.xyz"
+ * retries = 5
+ * ```
+ **/
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HostConfig {
+    pub executor: ExecutorLimits,
+    pub prover: ProverConfig,
+}
+
+pub fn load_config(path: &Path) -> Result<HostConfig, HostError> {
+    let content = std::fs::read_to_string(path)?;
+    let config = if path.extension().and_then(|ext| ext.to_str()) == Some("json") {
+        serde_json::from_str(&content)?
+    } else {
+        toml::from_str(&content)?
+    };
+
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::BackendKind;
+
+    #[test]
+    fn test_load_config() {
+        let dir = std::env::temp_dir().join(format!("host-config-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let path = dir.join("host.toml");
+        std::fs::write(&path, "[executor]\nsession_limit = 0\nmemory_mb = 4096\n").unwrap();
+        let config = load_config(&path).unwrap();
+        assert_eq!(config.executor.session_limit, 0);
+        assert_eq!(config.executor.segment_limit_po2, 20);
+        assert_eq!(config.executor.segment_po2().unwrap(), 18);
+
+        let path = dir.join("host.json");
+        std::fs::write(
+            &path,
+            r#"{"executor": {"segment_limit_po2": 21}, "prover": {"backend": "dev-mode"}}"#,
+        )
+        .unwrap();
+        let config = load_config(&path).unwrap();
+        assert_eq!(config.executor.segment_limit_po2, 21);
+        assert_eq!(config.prover.backend, BackendKind::DevMode);
+        assert_eq!(config.prover.bonsai.retries, 3);
+
+        std::fs::write(&path, r#"{"exector": {}}"#).unwrap();
+        assert!(load_config(&path).is_err());
+        std::fs::write(&path, r#"{"executor": {"sesion_limit": 0}}"#).unwrap();
+        assert!(load_config(&path).is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
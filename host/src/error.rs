@@ -0,0 +1,241 @@
+use reth_primitives::H256;
+use std::error::Error;
+use std::fmt;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use cita_trie::TrieError;
+use trie_core::chunk::ChunkError;
+use trie_core::range::RangeError;
+use trie_core::registry::RegistryErrors;
+use verifier::VerifyError;
+
+/**
+ * Errors returned by the host library.
+ **/
+#[derive(Debug)]
+pub enum HostError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    TomlDe(toml::de::Error),
+    TomlSer(toml::ser::Error),
+    Bincode(bincode::Error),
+    /// Receipts or header that can't be turned into guest inputs.
+    InvalidInput(String),
+    Trie(TrieError),
+    UnsupportedTrieNode(&'static str),
+    ReceiptsRootMismatch {
+        block: u64,
+        expected: H256,
+        found: String,
+        receipt_count: usize,
+    },
+    Registry(RegistryErrors),
+    NotRegistered(String),
+    RegistryHashMismatch,
+    /// Outputs with a product tree root of 0, which no product of primes gives.
+    ZeroProductTreeRoot,
+    Http(Box<ureq::Error>),
+    JsonRpc {
+        method: String,
+        error: String,
+    },
+    /// Error from the risc0 executor or prover.
+    Zkvm(anyhow::Error),
+    /// The guest ran for more cycles than the session limit allows.
+    SessionLimitExceeded(anyhow::Error),
+    InvalidLimits(String),
+    InvalidBackend(String),
+    /// A Bonsai request or session that failed, by message.
+    Bonsai(String),
+    /// A proof stopped through its `CancelToken`, by Bonsai session, image ID or server job.
+    ProofCancelled(String),
+    ProofTimeout {
+        session: String,
+        timeout: Duration,
+    },
+    Verify(VerifyError),
+    /// Error reading or writing a receipt or journal file.
+    Artifact(String),
+    JournalMismatch(PathBuf),
+    AlreadyExists(PathBuf),
+    BatchFailed(usize),
+    Server(String),
+    Range(RangeError),
+    /// An accumulator step that doesn't extend the previous one, by block number.
+    BrokenChain(u64),
+    Chunk(ChunkError),
+}
+
+impl Error for HostError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            HostError::Io(err) => Some(err),
+            HostError::Json(err) => Some(err),
+            HostError::TomlDe(err) => Some(err),
+            HostError::TomlSer(err) => Some(err),
+            HostError::Bincode(err) => Some(err),
+            HostError::Trie(err) => Some(err),
+            HostError::Registry(err) => Some(err),
+            HostError::Http(err) => Some(err),
+            HostError::Verify(err) => Some(err),
+            HostError::Range(err) => Some(err),
+            HostError::Chunk(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for HostError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HostError::Io(err) => write!(f, "{}", err),
+            HostError::Json(err) => write!(f, "invalid JSON: {}", err),
+            HostError::TomlDe(err) => write!(f, "invalid TOML: {}", err),
+            HostError::TomlSer(err) => write!(f, "TOML serialization failed: {}", err),
+            HostError::Bincode(err) => write!(f, "invalid inputs file: {}", err),
+            HostError::InvalidInput(err) => write!(f, "{}", err),
+            HostError::Trie(err) => write!(f, "{}", err),
+            HostError::UnsupportedTrieNode(kind) => {
+                write!(f, "unsupported {} node in the receipts trie", kind)
+            }
+            HostError::ReceiptsRootMismatch {
+                block,
+                expected,
+                found,
+                receipt_count,
+            } => write!(
+                f,
+                "receipts root mismatch for block {}: header has {:?}, {} receipts give {}",
+                block, expected, receipt_count, found
+            ),
+            HostError::Registry(err) => write!(f, "{}", err),
+            HostError::NotRegistered(address) => write!(f, "{} is not registered", address),
+            HostError::RegistryHashMismatch => write!(
+                f,
+                "registry hash doesn't match the one committed in the receipt"
+            ),
+            HostError::ZeroProductTreeRoot => {
+                write!(f, "product tree root is 0, no product of primes is")
+            }
+            HostError::Http(err) => write!(f, "{}", err),
+            HostError::JsonRpc { method, error } => write!(f, "{} failed: {}", method, error),
+            HostError::Zkvm(err) => write!(f, "{}", err),
+            HostError::SessionLimitExceeded(err) => write!(
+                f,
+                "{:#}: the guest needs more cycles than the session limit, raise it with \
+                 --session-limit or session_limit in the [executor] config",
+                err
+            ),
+            HostError::InvalidLimits(err) => write!(f, "invalid executor limits: {}", err),
+            HostError::InvalidBackend(err) => write!(f, "invalid prover backend: {}", err),
+            HostError::Bonsai(err) => write!(f, "bonsai: {}", err),
+            HostError::ProofCancelled(proof) => write!(f, "proof {} was cancelled", proof),
+            HostError::ProofTimeout { session, timeout } => write!(
+                f,
+                "proof {} took more than {}s and was stopped, raise proof_timeout_secs in the \
+                 [prover.bonsai] config",
+                session,
+                timeout.as_secs()
+            ),
+            HostError::Verify(err) => write!(f, "{}", err),
+            HostError::Artifact(err) => write!(f, "{}", err),
+            HostError::JournalMismatch(path) => {
+                write!(f, "{} doesn't match the receipt", path.display())
+            }
+            HostError::AlreadyExists(path) => write!(f, "{} already exists", path.display()),
+            HostError::BatchFailed(count) => write!(f, "{} block(s) failed", count),
+            HostError::Server(err) => write!(f, "{}", err),
+            HostError::Range(err) => write!(f, "{}", err),
+            HostError::BrokenChain(block) => write!(
+                f,
+                "accumulator step of block {} doesn't extend the previous state",
+                block
+            ),
+            HostError::Chunk(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl From<std::io::Error> for HostError {
+    fn from(err: std::io::Error) -> Self {
+        HostError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for HostError {
+    fn from(err: serde_json::Error) -> Self {
+        HostError::Json(err)
+    }
+}
+
+impl From<toml::de::Error> for HostError {
+    fn from(err: toml::de::Error) -> Self {
+        HostError::TomlDe(err)
+    }
+}
+
+impl From<toml::ser::Error> for HostError {
+    fn from(err: toml::ser::Error) -> Self {
+        HostError::TomlSer(err)
+    }
+}
+
+impl From<bincode::Error> for HostError {
+    fn from(err: bincode::Error) -> Self {
+        HostError::Bincode(err)
+    }
+}
+
+impl From<TrieError> for HostError {
+    fn from(err: TrieError) -> Self {
+        HostError::Trie(err)
+    }
+}
+
+impl From<RegistryErrors> for HostError {
+    fn from(err: RegistryErrors) -> Self {
+        HostError::Registry(err)
+    }
+}
+
+impl From<ureq::Error> for HostError {
+    fn from(err: ureq::Error) -> Self {
+        HostError::Http(Box::new(err))
+    }
+}
+
+impl From<anyhow::Error> for HostError {
+    fn from(err: anyhow::Error) -> Self {
+        // The executor only reports a reached session limit in its message.
+        if format!("{:#}", err).contains("Session limit exceeded") {
+            return HostError::SessionLimitExceeded(err);
+        }
+        HostError::Zkvm(err)
+    }
+}
+
+impl From<VerifyError> for HostError {
+    fn from(err: VerifyError) -> Self {
+        HostError::Verify(err)
+    }
+}
+
+impl From<RangeError> for HostError {
+    fn from(err: RangeError) -> Self {
+        HostError::Range(err)
+    }
+}
+
+impl From<ChunkError> for HostError {
+    fn from(err: ChunkError) -> Self {
+        HostError::Chunk(err)
+    }
+}
+
+// The verifier's file helpers return boxed errors, which aren't Send.
+impl From<Box<dyn Error>> for HostError {
+    fn from(err: Box<dyn Error>) -> Self {
+        HostError::Artifact(err.to_string())
+    }
+}
@@ -0,0 +1,599 @@
+use reth_primitives::{Address, Bytes, Log, Receipt, TxType, H256};
+use serde::Deserialize;
+use serde_json::Value;
+use std::path::Path;
+
+use trie_core::header::encode_list;
+use trie_core::keccak256;
+
+use crate::error::HostError;
+
+/**
+ * Receipt dumps the host accepts:
+ * - `Reth`: a list of receipts in reth's serde format, like receipts.json,
+ * - `JsonRpc`: node output of `eth_getBlockReceipts`, or of `eth_getTransactionReceipt` for
+ *   each transaction, either bare or wrapped in JSON-RPC responses.
+ **/
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ReceiptsFormat {
+    Reth,
+    JsonRpc,
+}
+
+/**
+ * A receipt of the block with its EIP-2718 transaction type. reth's `TxType` stops at
+ * EIP-1559, so the type byte is kept here and `receipt.tx_type` is only meaningful up to 2.
+ **/
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BlockReceipt {
+    pub tx_type: u8,
+    pub receipt: Receipt,
+}
+
+impl From<Receipt> for BlockReceipt {
+    fn from(receipt: Receipt) -> Self {
+        BlockReceipt {
+            tx_type: receipt.tx_type as u8,
+            receipt,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RpcReceipt {
+    #[serde(rename = "type", default)]
+    pub tx_type: Option<String>,
+    #[serde(default)]
+    pub block_hash: Option<H256>,
+    pub status: Option<String>,
+    pub cumulative_gas_used: String,
+    pub transaction_index: String,
+    pub logs: Vec<RpcLog>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RpcLog {
+    pub address: Address,
+    pub topics: Vec<H256>,
+    pub data: Bytes,
+    #[serde(default)]
+    pub removed: bool,
+}
+
+/**
+ * The block header fields the host checks receipts against.
+ **/
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BlockHeader {
+    pub number: u64,
+    pub hash: H256,
+    pub parent_hash: H256,
+    pub receipts_root: H256,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RpcHeader {
+    number: String,
+    hash: H256,
+    parent_hash: H256,
+    receipts_root: H256,
+}
+
+/**
+ * Load a block as returned by `eth_getBlockByNumber` / `eth_getBlockByHash`,
+ * bare or wrapped in its JSON-RPC response.
+ **/
+pub fn load_header(path: &Path) -> Result<BlockHeader, HostError> {
+    let value: Value = serde_json::from_slice(&std::fs::read(path)?)?;
+    parse_header(value)
+}
+
+pub fn parse_header(value: Value) -> Result<BlockHeader, HostError> {
+    let header: RpcHeader = serde_json::from_value(block_result(value)?)?;
+    Ok(BlockHeader {
+        number: quantity(&header.number)?,
+        hash: header.hash,
+        parent_hash: header.parent_hash,
+        receipts_root: header.receipts_root,
+    })
+}
+
+/**
+ * The block of a JSON-RPC response, or the value itself if it isn't one.
+ **/
+fn block_result(value: Value) -> Result<Value, HostError> {
+    match value {
+        Value::Object(mut response) if response.contains_key("jsonrpc") => {
+            if let Some(error) = response.get("error") {
+                return Err(HostError::InvalidInput(format!(
+                    "JSON-RPC error: {}",
+                    error
+                )));
+            }
+            match response.remove("result") {
+                Some(block @ Value::Object(_)) => Ok(block),
+                _ => Err(invalid("JSON-RPC response has no block result")),
+            }
+        }
+        value => Ok(value),
+    }
+}
+
+/**
+ * Header fields in RLP order. Fields added by later forks come last and are encoded
+ * while present, a block has every field of the forks before its own.
+ **/
+const HEADER_FIELDS: [(&str, bool); 21] = [
+    ("parentHash", false),
+    ("sha3Uncles", false),
+    ("miner", false),
+    ("stateRoot", false),
+    ("transactionsRoot", false),
+    ("receiptsRoot", false),
+    ("logsBloom", false),
+    ("difficulty", true),
+    ("number", true),
+    ("gasLimit", true),
+    ("gasUsed", true),
+    ("timestamp", true),
+    ("extraData", false),
+    ("mixHash", false),
+    ("nonce", false),
+    ("baseFeePerGas", true),
+    ("withdrawalsRoot", false),
+    ("blobGasUsed", true),
+    ("excessBlobGas", true),
+    ("parentBeaconBlockRoot", false),
+    ("requestsHash", false),
+];
+const LEGACY_HEADER_FIELDS: usize = 15;
+
+pub fn load_header_rlp(path: &Path) -> Result<Vec<u8>, HostError> {
+    let value: Value = serde_json::from_slice(&std::fs::read(path)?)?;
+    header_rlp(value)
+}
+
+/**
+ * RLP encode a block returned by `eth_getBlockByNumber` / `eth_getBlockByHash`, as the range
+ * guest takes it. The encoding is checked against the block hash, so a field the encoding
+ * doesn't know of is reported rather than producing a header the guest would refuse.
+ **/
+pub fn header_rlp(value: Value) -> Result<Vec<u8>, HostError> {
+    let block = block_result(value)?;
+    let hash: H256 = serde_json::from_value(block["hash"].clone())?;
+
+    let mut fields = Vec::with_capacity(HEADER_FIELDS.len());
+    for (i, (name, is_quantity)) in HEADER_FIELDS.iter().enumerate() {
+        let Some(field) = block.get(*name).and_then(|field| field.as_str()) else {
+            if i < LEGACY_HEADER_FIELDS {
+                return Err(HostError::InvalidInput(format!("block has no {}", name)));
+            }
+            break;
+        };
+        let digits = field.strip_prefix("0x").unwrap_or(field);
+        let digits = if *is_quantity {
+            let digits = digits.trim_start_matches('0');
+            // Quantities are big endian without leading zero bytes, odd lengths get a 0 nibble.
+            if digits.len() % 2 == 1 {
+                format!("0{}", digits)
+            } else {
+                digits.to_string()
+            }
+        } else {
+            digits.to_string()
+        };
+        fields.push(
+            hex::decode(&digits).map_err(|err| {
+                HostError::InvalidInput(format!("header field {}: {}", name, err))
+            })?,
+        );
+    }
+
+    let rlp = encode_list(&fields);
+    if keccak256(&rlp) != hash.0 {
+        return Err(HostError::InvalidInput(format!(
+            "RLP encoded header doesn't hash to the block hash {:?}, it may have fields this \
+             version doesn't encode",
+            hash
+        )));
+    }
+
+    Ok(rlp)
+}
+
+pub fn load_receipts(path: &Path) -> Result<Vec<BlockReceipt>, HostError> {
+    let value: Value = serde_json::from_slice(&std::fs::read(path)?)?;
+    parse_receipts(value)
+}
+
+pub fn parse_receipts(value: Value) -> Result<Vec<BlockReceipt>, HostError> {
+    parse_block_receipts(value, None)
+}
+
+/**
+ * Parse receipts, checking that JSON-RPC receipts all have the `blockHash` of the same block,
+ * `block_hash` when given. Receipts in reth's format have no block hash to check.
+ **/
+pub fn parse_block_receipts(
+    value: Value,
+    block_hash: Option<H256>,
+) -> Result<Vec<BlockReceipt>, HostError> {
+    let receipts = unwrap_responses(value)?;
+    match detect_format(&receipts)? {
+        ReceiptsFormat::Reth => {
+            let receipts: Vec<Receipt> = serde_json::from_value(receipts)?;
+            Ok(receipts.into_iter().map(BlockReceipt::from).collect())
+        }
+        ReceiptsFormat::JsonRpc => {
+            let receipts: Vec<RpcReceipt> = serde_json::from_value(receipts)?;
+            from_rpc_receipts(receipts, block_hash)
+        }
+    }
+}
+
+/**
+ * Strip JSON-RPC envelopes: a single response holding the block receipts, or a list of
+ * responses holding one receipt each. Bare receipt lists are returned as is.
+ **/
+fn unwrap_responses(value: Value) -> Result<Value, HostError> {
+    match value {
+        Value::Object(mut response) if response.contains_key("jsonrpc") => {
+            if let Some(error) = response.get("error") {
+                return Err(HostError::InvalidInput(format!(
+                    "JSON-RPC error: {}",
+                    error
+                )));
+            }
+            match response.remove("result") {
+                Some(Value::Array(receipts)) => Ok(Value::Array(receipts)),
+                Some(receipt @ Value::Object(_)) => Ok(Value::Array(vec![receipt])),
+                _ => Err(invalid("JSON-RPC response has no receipts result")),
+            }
+        }
+        Value::Array(items) => items
+            .into_iter()
+            .map(|item| match item {
+                Value::Object(mut response) if response.contains_key("jsonrpc") => {
+                    if let Some(error) = response.get("error") {
+                        return Err(HostError::InvalidInput(format!(
+                            "JSON-RPC error for request {}: {}",
+                            response.get("id").unwrap_or(&Value::Null),
+                            error
+                        )));
+                    }
+                    response
+                        .remove("result")
+                        .filter(Value::is_object)
+                        .ok_or_else(|| invalid("JSON-RPC response has no receipt result"))
+                }
+                item => Ok(item),
+            })
+            .collect::<Result<Vec<Value>, HostError>>()
+            .map(Value::Array),
+        _ => Err(invalid(
+            "expected a list of receipts or a JSON-RPC response",
+        )),
+    }
+}
+
+pub fn detect_format(receipts: &Value) -> Result<ReceiptsFormat, HostError> {
+    let first = match receipts.as_array().and_then(|receipts| receipts.first()) {
+        Some(first) => first,
+        // An empty block, both formats give the same receipts.
+        None => return Ok(ReceiptsFormat::Reth),
+    };
+
+    if first.get("cumulative_gas_used").is_some() {
+        Ok(ReceiptsFormat::Reth)
+    } else if first.get("cumulativeGasUsed").is_some() {
+        Ok(ReceiptsFormat::JsonRpc)
+    } else {
+        Err(invalid("unknown receipts format"))
+    }
+}
+
+/**
+ * Convert JSON-RPC receipts, in any order, to the receipts of the block in transaction order.
+ * Every receipt must have the same `blockHash`, `block_hash` if given, so receipts of
+ * different blocks or of a reorged block aren't mixed.
+ **/
+pub fn from_rpc_receipts(
+    mut receipts: Vec<RpcReceipt>,
+    block_hash: Option<H256>,
+) -> Result<Vec<BlockReceipt>, HostError> {
+    let mut expected_hash = block_hash;
+    let mut indexed = Vec::with_capacity(receipts.len());
+    for receipt in receipts.drain(..) {
+        let idx = quantity(&receipt.transaction_index)?;
+        match (receipt.block_hash, expected_hash) {
+            (Some(hash), Some(expected)) if hash != expected => {
+                return Err(HostError::InvalidInput(format!(
+                    "receipt of transaction {} is from block {:?}, expected {:?}",
+                    idx, hash, expected
+                )));
+            }
+            (None, Some(_)) => {
+                return Err(HostError::InvalidInput(format!(
+                    "receipt of transaction {} has no block hash",
+                    idx
+                )));
+            }
+            (hash, _) => expected_hash = expected_hash.or(hash),
+        }
+        indexed.push((idx, receipt));
+    }
+    indexed.sort_by_key(|(idx, _)| *idx);
+
+    indexed
+        .into_iter()
+        .enumerate()
+        .map(|(expected, (idx, receipt))| {
+            if idx != expected as u64 {
+                return Err(HostError::InvalidInput(format!(
+                    "missing receipt for transaction index {}, found {}",
+                    expected, idx
+                )));
+            }
+            from_rpc_receipt(receipt)
+        })
+        .collect()
+}
+
+/**
+ * Transaction types are EIP-2718 type bytes, 0x7f at most so they can't be mistaken for the
+ * first byte of an RLP list. Receipts of every typed transaction are encoded the same way,
+ * `type || rlp(receipt)`, so types reth doesn't know yet are kept as EIP-1559 receipts with
+ * their own type byte.
+ **/
+fn from_rpc_receipt(receipt: RpcReceipt) -> Result<BlockReceipt, HostError> {
+    let tx_type = match receipt.tx_type.as_deref().map(quantity).transpose()? {
+        None => 0,
+        Some(tx_type) if tx_type <= 0x7f => tx_type as u8,
+        Some(other) => {
+            return Err(HostError::InvalidInput(format!(
+                "invalid transaction type {:#x}",
+                other
+            )))
+        }
+    };
+    let reth_type = match tx_type {
+        0 => TxType::Legacy,
+        1 => TxType::EIP2930,
+        _ => TxType::EIP1559,
+    };
+    let success = match receipt.status.as_deref() {
+        Some(status) => quantity(status)? == 1,
+        None => {
+            return Err(invalid(
+                "pre-Byzantium receipts (state root, no status) are not supported",
+            ))
+        }
+    };
+
+    let mut logs = Vec::with_capacity(receipt.logs.len());
+    for log in receipt.logs {
+        if log.removed {
+            return Err(invalid(
+                "receipt contains a removed log, the block was reorged",
+            ));
+        }
+        logs.push(Log {
+            address: log.address,
+            topics: log.topics,
+            data: log.data,
+        });
+    }
+
+    Ok(BlockReceipt {
+        tx_type,
+        receipt: Receipt {
+            tx_type: reth_type,
+            success,
+            cumulative_gas_used: quantity(&receipt.cumulative_gas_used)?,
+            logs,
+        },
+    })
+}
+
+/**
+ * JSON-RPC quantity: 0x prefixed hex without leading zeros.
+ **/
+pub fn quantity(value: &str) -> Result<u64, HostError> {
+    let digits = value.strip_prefix("0x").ok_or_else(|| {
+        HostError::InvalidInput(format!("quantity {} is missing the 0x prefix", value))
+    })?;
+    u64::from_str_radix(digits, 16)
+        .map_err(|err| HostError::InvalidInput(format!("quantity {}: {}", value, err)))
+}
+
+fn invalid(message: &str) -> HostError {
+    HostError::InvalidInput(message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn rpc_receipt(index: &str, address: &str) -> Value {
+        json!({
+            "blockHash": "0x9c2e4f5a0c6e4c3b84b6d8f2f0f1d4f8f6d1c8a5b7e8d9f0a1b2c3d4e5f60718",
+            "blockNumber": "0x10d4f",
+            "contractAddress": null,
+            "cumulativeGasUsed": "0x18082",
+            "effectiveGasPrice": "0x3b9aca00",
+            "from": "0x6b4b968dcecfd3d197ce04dc8925f91930815366",
+            "gasUsed": "0x18082",
+            "logs": [{
+                "address": address,
+                "topics": ["0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef"],
+                "data": "0x00000000000000000000000000000000000000000000000000000000000000ff",
+                "logIndex": "0x0",
+                "removed": false,
+                "transactionIndex": index
+            }],
+            "logsBloom": format!("0x{}", "00".repeat(256)),
+            "status": "0x1",
+            "to": address,
+            "transactionHash": "0x5b5f1d8e6a8e5f9e3b8c4a2d1e0f9c8b7a6d5e4f3c2b1a0f9e8d7c6b5a4f3e2d",
+            "transactionIndex": index,
+            "type": "0x2"
+        })
+    }
+
+    #[test]
+    fn test_detect_format() {
+        let reth: Value =
+            serde_json::from_slice(include_bytes!("../../receipts_small.json")).unwrap();
+        assert_eq!(detect_format(&reth).unwrap(), ReceiptsFormat::Reth);
+
+        let rpc = json!([rpc_receipt(
+            "0x0",
+            "0x4ce5df9033ead87976255a8695592bca3e8cb5cb"
+        )]);
+        assert_eq!(detect_format(&rpc).unwrap(), ReceiptsFormat::JsonRpc);
+    }
+
+    #[test]
+    fn test_block_receipts_response() {
+        let response = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "result": [
+                rpc_receipt("0x0", "0x4ce5df9033ead87976255a8695592bca3e8cb5cb"),
+                rpc_receipt("0x1", "0xf64e49c1d1d2b1cfa570b1da6481dc8dc95cd093"),
+            ]
+        });
+
+        let receipts = parse_receipts(response).unwrap();
+        assert_eq!(receipts.len(), 2);
+        assert_eq!(receipts[0].tx_type, 2);
+        assert_eq!(receipts[0].receipt.tx_type, TxType::EIP1559);
+        assert!(receipts[0].receipt.success);
+        assert_eq!(receipts[0].receipt.cumulative_gas_used, 0x18082);
+        assert_eq!(
+            format!("{:?}", receipts[1].receipt.logs[0].address),
+            "0xf64e49c1d1d2b1cfa570b1da6481dc8dc95cd093"
+        );
+    }
+
+    #[test]
+    fn test_transaction_receipt_responses_are_reordered() {
+        let responses = json!([
+            {"jsonrpc": "2.0", "id": 2, "result": rpc_receipt("0x1", "0xf64e49c1d1d2b1cfa570b1da6481dc8dc95cd093")},
+            {"jsonrpc": "2.0", "id": 1, "result": rpc_receipt("0x0", "0x4ce5df9033ead87976255a8695592bca3e8cb5cb")},
+        ]);
+
+        let receipts = parse_receipts(responses).unwrap();
+        assert_eq!(
+            format!("{:?}", receipts[0].receipt.logs[0].address),
+            "0x4ce5df9033ead87976255a8695592bca3e8cb5cb"
+        );
+
+        let gap = json!([
+            rpc_receipt("0x0", "0x4ce5df9033ead87976255a8695592bca3e8cb5cb"),
+            rpc_receipt("0x2", "0x4ce5df9033ead87976255a8695592bca3e8cb5cb")
+        ]);
+        assert!(parse_receipts(gap).is_err());
+    }
+
+    #[test]
+    fn test_blob_transaction_receipt() {
+        let mut blob = rpc_receipt("0x0", "0x4ce5df9033ead87976255a8695592bca3e8cb5cb");
+        blob["type"] = json!("0x3");
+        blob["blobGasUsed"] = json!("0x20000");
+        blob["blobGasPrice"] = json!("0x1");
+
+        let receipts = parse_receipts(json!([blob])).unwrap();
+        assert_eq!(receipts[0].tx_type, 3);
+        assert!(receipts[0].receipt.success);
+        assert_eq!(receipts[0].receipt.logs.len(), 1);
+
+        blob["type"] = json!("0x7f");
+        assert_eq!(
+            parse_receipts(json!([blob.clone()])).unwrap()[0].tx_type,
+            0x7f
+        );
+        blob["type"] = json!("0x80");
+        assert!(parse_receipts(json!([blob])).is_err());
+    }
+
+    #[test]
+    fn test_receipt_response_error() {
+        let responses = json!([
+            {"jsonrpc": "2.0", "id": 1, "result": rpc_receipt("0x0", "0x4ce5df9033ead87976255a8695592bca3e8cb5cb")},
+            {"jsonrpc": "2.0", "id": 2, "error": {"code": -32000, "message": "header not found"}},
+        ]);
+
+        match parse_receipts(responses) {
+            Err(HostError::InvalidInput(message)) => assert!(message.contains("header not found")),
+            other => panic!("expected the JSON-RPC error, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_receipts_block_hash() {
+        let receipt = rpc_receipt("0x0", "0x4ce5df9033ead87976255a8695592bca3e8cb5cb");
+        let mut other_block = rpc_receipt("0x1", "0x4ce5df9033ead87976255a8695592bca3e8cb5cb");
+        other_block["blockHash"] = json!(format!("0x{}", "11".repeat(32)));
+        assert!(parse_receipts(json!([receipt.clone(), other_block])).is_err());
+
+        let block_hash: H256 = serde_json::from_value(receipt["blockHash"].clone()).unwrap();
+        assert!(parse_block_receipts(json!([receipt.clone()]), Some(block_hash)).is_ok());
+        assert!(parse_block_receipts(json!([receipt]), Some(H256::zero())).is_err());
+    }
+
+    #[test]
+    fn test_genesis_header_rlp() {
+        let zero = format!("0x{}", "00".repeat(32));
+        let genesis = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "result": {
+                "hash": "0xd4e56740f876aef8c010b86a40d5f56745a118d0906a34e69aec8c0db1cb8fa3",
+                "parentHash": zero,
+                "sha3Uncles": "0x1dcc4de8dec75d7aab85b567b6ccd41ad312451b948a7413f0a142fd40d49347",
+                "miner": format!("0x{}", "00".repeat(20)),
+                "stateRoot": "0xd7f8974fb5ac78d9ac099b9ad5018bedc2ce0a72dad1827a1709da30580f0544",
+                "transactionsRoot": "0x56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421",
+                "receiptsRoot": "0x56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421",
+                "logsBloom": format!("0x{}", "00".repeat(256)),
+                "difficulty": "0x400000000",
+                "number": "0x0",
+                "gasLimit": "0x1388",
+                "gasUsed": "0x0",
+                "timestamp": "0x0",
+                "extraData": "0x11bbe8db4e347b4e8c937c1c8370e4b5ed33adb3db69cbdb7a38e1e50b1b82fa",
+                "mixHash": zero,
+                "nonce": "0x0000000000000042"
+            }
+        });
+
+        let rlp = header_rlp(genesis.clone()).unwrap();
+        let header = trie_core::header::decode_header(&rlp).unwrap();
+        assert_eq!(header.number, 0);
+        assert_eq!(
+            hex::encode(header.receipts_root),
+            "56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421"
+        );
+
+        let mut wrong = genesis;
+        wrong["result"]["gasLimit"] = json!("0x1389");
+        assert!(header_rlp(wrong).is_err());
+    }
+
+    #[test]
+    fn test_fixture_header_hash() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/rpc/eth_getBlockByNumber.json");
+        let rlp = load_header_rlp(&path).unwrap();
+        let header = load_header(&path).unwrap();
+        assert_eq!(keccak256(&rlp), header.hash.0);
+        assert_eq!(
+            format!("{:?}", header.hash),
+            "0x87767ff18b0c14098f2c3e1f8f1d7a9862085bf7bdc88110b9f34e8797497eb3"
+        );
+    }
+}
@@ -0,0 +1,26 @@
+//! Host side of the receipts proofs: load a block's receipts and header, build the receipts
+//! trie and the guest inputs, prove or execute the guest, then verify and query its receipts.
+//! Large blocks can be proven in chunks combined by a second guest.
+//! Proven blocks can be aggregated into counts over a block range, or added one at a time to
+//! a chain of accumulator states.
+//! Proofs run locally, in dev mode or on Bonsai, as selected by the prover backend.
+//! The `host` binary is a thin CLI over this crate.
+
+pub mod accumulator;
+pub mod backend;
+pub mod batch;
+pub mod cache;
+pub mod chunk;
+pub mod config;
+pub mod error;
+pub mod input;
+pub mod prover;
+pub mod query;
+pub mod range;
+pub mod registry;
+pub mod report;
+pub mod rpc;
+pub mod server;
+pub mod trie;
+
+pub use error::HostError;
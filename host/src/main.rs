@@ -1,146 +1,451 @@
-use methods::{RECEIPTS_ROOT_AND_PRODUCT_TREE_ELF, RECEIPTS_ROOT_AND_PRODUCT_TREE_ID};
-use risc0_zkvm::{default_prover, serde::from_slice, ExecutorEnv};
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
 
-use hasher::HasherKeccak;
+mod commands;
 
-use reth_primitives::{Receipt, ReceiptWithBloomRef};
-use risc0_zkvm::serde::to_vec;
-use std::sync::Arc;
+use commands::ReceiptsSource;
+use host::backend::{self, BackendKind};
+use host::config::HostConfig;
+use host::error::HostError;
+use host::report::Report;
+use host::rpc::BlockId;
+use host::server::Backend;
+use host::{batch, cache, config, prover};
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::EnvFilter;
+use trie_core::JournalEncoding;
 
-use cita_trie::MemoryDB;
-use cita_trie::{PatriciaTrie, Trie};
-use reth_primitives::bytes::BytesMut;
-use reth_primitives::rpc_utils::rlp::RlpStream;
-use reth_rlp::Encodable;
-use trie_core::{factor_of_n, Inputs, Node, Outputs};
-
-pub fn build_from_receipts(receipts: Vec<Receipt>) -> (Vec<String>, Node) {
-    let mem_db = Arc::new(MemoryDB::new(true));
-    let hasher = Arc::new(HasherKeccak::new());
-
-    let mut trie = PatriciaTrie::new(mem_db.clone(), hasher.clone());
-    let mut key_buf = BytesMut::new();
-    let mut value_buf = BytesMut::new();
-
-    let mut log_addresses: Vec<String> = Vec::new();
-
-    // let mut contract_prime = HashMap::new();
-    // contract_prime.insert("0x0d4a11d5EEaaC28EC3F61d100daF4d40471f1852", 2);
-    // contract_prime.insert("0x1f9840a85d5aF5bf1D1762F925BDADdC4201F984", 3);
-    // contract_prime.insert("0x7a250d5630B4cF539739dF2C5dAcb4c659F2488D", 5);
-    // contract_prime.insert("0x4ce5df9033ead87976255a8695592bca3e8cb5cb", 2);
-    // contract_prime.insert("0xf64e49c1d1d2b1cfa570b1da6481dc8dc95cd093", 3);
-    // contract_prime.insert("0x076a3e1500f3110d8f4445d396a3d7ca6d0ca269", 5);
-
-    for (idx, receipt) in receipts.iter().enumerate() {
-        key_buf.clear();
-        idx.encode(&mut key_buf);
-
-        receipt.logs.iter().for_each(|log| {
-            // if log.address
-            //     == Address::from_str("0x1f9840a85d5aF5bf1D1762F925BDADdC4201F984").unwrap()
-            // {
-            //     println!("log: {:?} {:?}", log, log.address);
-            //     println!(
-            //         "{}",
-            //         format!("{:?}", log.address).to_lowercase()
-            //             == "0x1f9840a85d5af5bf1d1762f925bdaddc4201f984"
-            //     );
-            // }
-
-            let addr = format!("{:?}", log.address);
-            log_addresses.push(addr.to_lowercase());
-        });
+#[derive(Parser)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+    /// Use risc0 dev mode: skip proving and accept fake receipts, for local iteration only.
+    /// Same as --prover dev-mode
+    #[arg(long, global = true, conflicts_with = "prover")]
+    dev_mode: bool,
+    /// Where proofs run: local, dev-mode or bonsai. Overrides the config
+    #[arg(long, global = true)]
+    prover: Option<BackendKind>,
+    /// Write a JSON report with phase timings, cycles, input sizes and outputs
+    #[arg(long, global = true)]
+    report: Option<PathBuf>,
+    /// Host config file, TOML or JSON, with the executor limits in an [executor] section
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+    /// Maximum cycles of a guest execution, 0 for no limit. Overrides the config
+    #[arg(long, global = true)]
+    session_limit: Option<u64>,
+    /// Segment size in powers of 2 cycles, 13 to 24. Overrides the config
+    #[arg(long, global = true)]
+    segment_limit_po2: Option<u32>,
+    /// Memory budget of the prover in MB, lowers the segment size to fit. Overrides the config
+    #[arg(long, global = true)]
+    memory_mb: Option<u64>,
+}
 
-        // println!("leaves: {:?}", product_tree_leaves);
+impl Cli {
+    /**
+     * The config file if any, with the command line flags on top.
+     **/
+    fn host_config(&self) -> Result<HostConfig, HostError> {
+        let mut config = match &self.config {
+            Some(path) => config::load_config(path)?,
+            None => HostConfig::default(),
+        };
+        let limits = &mut config.executor;
+        if let Some(session_limit) = self.session_limit {
+            limits.session_limit = session_limit;
+        }
+        if let Some(segment_limit_po2) = self.segment_limit_po2 {
+            limits.segment_limit_po2 = segment_limit_po2;
+        }
+        if let Some(memory_mb) = self.memory_mb {
+            limits.memory_mb = Some(memory_mb);
+        }
+        if self.dev_mode {
+            config.prover.backend = BackendKind::DevMode;
+        }
+        if let Some(prover) = self.prover {
+            config.prover.backend = prover;
+        }
 
-        value_buf.clear();
-        let bloom_receipt = ReceiptWithBloomRef::from(receipt);
-        bloom_receipt.encode_inner(&mut value_buf, false);
-        trie.insert(key_buf.to_vec(), value_buf.to_vec()).unwrap();
+        Ok(config)
     }
-
-    (log_addresses, encode_trie_rec(trie.root))
 }
 
-fn encode_trie_rec(root: cita_trie::node::Node) -> Node {
-    match root {
-        cita_trie::node::Node::Branch(branch) => {
-            let borrow_branch = branch.borrow();
-            let mut children: [Box<Node>; 16] = Default::default();
-            let mut children_count = 0;
-            for i in 0..16 {
-                let child = borrow_branch.children[i].clone();
-
-                let child = encode_trie_rec(child);
-                match child {
-                    Node::Empty => {}
-                    _ => {
-                        children_count += 1;
-                    }
-                }
-                children[i] = Box::new(child);
-            }
+#[derive(Subcommand)]
+enum Command {
+    /// Build the guest inputs from a receipts file and a registry.
+    /// Receipts can be in reth's format or eth_getBlockReceipts / eth_getTransactionReceipt output
+    BuildInputs {
+        #[arg(long, default_value = "receipts.json", conflicts_with = "rpc_url")]
+        receipts: PathBuf,
+        /// Block header (eth_getBlockByNumber output) to check the receipts root against
+        #[arg(long, conflicts_with = "rpc_url")]
+        header: Option<PathBuf>,
+        /// Fetch the receipts from this JSON-RPC endpoint instead of a file
+        #[arg(long, requires = "block")]
+        rpc_url: Option<String>,
+        /// Block number, hash or tag to fetch
+        #[arg(long, requires = "rpc_url")]
+        block: Option<BlockId>,
+        #[arg(long, default_value = "registry.toml")]
+        registry: PathBuf,
+        #[arg(long, default_value = "inputs.bin")]
+        output: PathBuf,
+        /// Have the guest commit the outputs ABI encoded, for EVM contracts, instead of risc0 serde
+        #[arg(long)]
+        abi_journal: bool,
+    },
+    /// Fetch a block header and its receipts from a JSON-RPC endpoint
+    Fetch {
+        #[arg(long)]
+        rpc_url: String,
+        /// Block number, hash or tag
+        #[arg(long)]
+        block: BlockId,
+        #[arg(long, default_value = "receipts.json")]
+        receipts_output: PathBuf,
+        #[arg(long, default_value = "header.json")]
+        header_output: PathBuf,
+    },
+    /// Run the guest with the executor only and print cycles, segments and outputs
+    Execute {
+        #[arg(long, default_value = "inputs.bin")]
+        inputs: PathBuf,
+        /// Write the journal and its decoded outputs as JSON
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Prove the guest and write the receipt
+    Prove {
+        #[arg(long, default_value = "inputs.bin")]
+        inputs: PathBuf,
+        #[arg(long, default_value = "receipt.bin")]
+        output: PathBuf,
+        /// Reuse the receipts of previous runs on the same inputs and guest from this directory
+        #[arg(long)]
+        cache_dir: Option<PathBuf>,
+        /// Size limit of the cache directory, least recently used receipts are evicted over it
+        #[arg(long, default_value_t = cache::DEFAULT_MAX_BYTES >> 20, requires = "cache_dir")]
+        cache_max_mb: u64,
+        /// Prove the block in chunks combined by a second guest, for blocks too large for one
+        /// execution
+        #[arg(long, conflicts_with = "cache_dir")]
+        chunked: bool,
+        /// Number of chunks, by default from the receipt and log counts of the block
+        #[arg(long, requires = "chunked")]
+        chunks: Option<usize>,
+    },
+    /// Prove many blocks, resuming from the receipts of a previous run
+    Batch {
+        /// Manifest of blocks (TOML or JSON) or directory of inputs files named after their block
+        #[arg(long)]
+        blocks: PathBuf,
+        #[arg(long, default_value = "batch")]
+        output_dir: PathBuf,
+        /// Number of blocks proven in parallel
+        #[arg(long, default_value_t = 1)]
+        jobs: usize,
+    },
+    /// Verify a receipt against the guest image ID
+    Verify {
+        #[arg(long, default_value = "receipt.bin")]
+        receipt: PathBuf,
+        /// Write the journal and its decoded outputs as JSON
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Print the journal digest and the ABI encoded outputs of a receipt or journal file
+    Journal {
+        #[arg(long, default_value = "receipt.bin", conflicts_with = "journal")]
+        receipt: PathBuf,
+        /// Journal file written next to a receipt or by `execute --output`
+        #[arg(long)]
+        journal: Option<PathBuf>,
+    },
+    /// Count the logs of registered contracts in a verified receipt
+    Query {
+        #[arg(long, default_value = "receipt.bin")]
+        receipt: PathBuf,
+        #[arg(long, default_value = "registry.toml")]
+        registry: PathBuf,
+        /// Only print the count for this contract
+        #[arg(long)]
+        address: Option<String>,
+    },
+    /// Aggregate the per-block proofs of consecutive blocks into log counts over the range
+    Range {
+        /// Manifest (TOML or JSON) of the blocks with their hash and receipt or journal file
+        #[arg(long)]
+        blocks: PathBuf,
+        #[arg(long, default_value = "registry.toml")]
+        registry: PathBuf,
+        #[arg(long, default_value = "range.receipt.bin")]
+        output: PathBuf,
+        /// Verify the per-block receipts in the guest instead of on the host, every block
+        /// needs a receipt
+        #[arg(long)]
+        recursive: bool,
+        /// Only run the executor and write the journal, no receipt
+        #[arg(long)]
+        execute_only: bool,
+        /// Only print the count for this contract
+        #[arg(long)]
+        address: Option<String>,
+    },
+    /// Add blocks one at a time to a chain of accumulator states, each proof extending the last
+    Accumulator {
+        /// Directory of the chain's receipts and journals
+        #[arg(long, default_value = "accumulator")]
+        dir: PathBuf,
+        #[command(subcommand)]
+        action: AccumulatorAction,
+    },
+    /// Serve an HTTP API to submit blocks, poll jobs, download receipts and query counts
+    Serve {
+        #[arg(long, default_value = "127.0.0.1:3000")]
+        addr: String,
+        #[arg(long, default_value = "registry.toml")]
+        registry: PathBuf,
+        /// Where receipts and journals of the jobs are written
+        #[arg(long, default_value = "serve")]
+        data_dir: PathBuf,
+        /// Jobs waiting for a worker, further submissions are refused until one is taken
+        #[arg(long, default_value_t = 16)]
+        queue_capacity: usize,
+        #[arg(long, default_value_t = 1)]
+        workers: usize,
+        /// Only run the executor: jobs produce journals and counts but no receipts
+        #[arg(long)]
+        execute_only: bool,
+    },
+    /// Manage the contract to prime registry passed to the guest
+    Registry {
+        #[arg(long, default_value = "registry.toml")]
+        file: PathBuf,
+        #[command(subcommand)]
+        action: RegistryAction,
+    },
+}
 
-            Node::Branch {
-                children,
-                children_count,
-            }
-        }
-        cita_trie::node::Node::Leaf(leaf) => {
-            let borrow_leaf = leaf.borrow();
+#[derive(Debug, Subcommand)]
+pub enum RegistryAction {
+    /// Create an empty registry file
+    Init,
+    /// Register a contract, with the next free prime unless one is given
+    Add {
+        address: String,
+        #[arg(long)]
+        prime: Option<u64>,
+    },
+    /// Unregister a contract
+    Remove { address: String },
+    /// Print the registered contracts and their primes
+    List,
+    /// Print the registry hash committed by the guest
+    Hash,
+}
 
-            let mut stream = RlpStream::new_list(2);
-            stream.append(&borrow_leaf.key.encode_compact());
-            stream.append(&borrow_leaf.value);
+#[derive(Debug, Subcommand)]
+pub enum AccumulatorAction {
+    /// Add a block to the last state, or start the chain with it
+    Extend {
+        /// Block header, as written by `fetch`
+        #[arg(long)]
+        header: PathBuf,
+        /// Receipt of the block's proof, verified by the accumulator guest
+        #[arg(long)]
+        receipt: PathBuf,
+        #[arg(long, default_value = "registry.toml")]
+        registry: PathBuf,
+        /// Only run the executor, the step has a journal but no receipt
+        #[arg(long)]
+        execute_only: bool,
+    },
+    /// Print the counts of the last state
+    Show {
+        #[arg(long, default_value = "registry.toml")]
+        registry: PathBuf,
+        /// Only print the count for this contract
+        #[arg(long)]
+        address: Option<String>,
+    },
+    /// Verify the receipt of every step and that each one extends the previous state
+    Verify,
+}
 
-            let buf = stream.out().to_vec();
-            Node::Leaf(buf)
-        }
-        cita_trie::node::Node::Empty => Node::Empty,
-        _ => {
-            panic!("unexpected node type");
+impl Command {
+    fn name(&self) -> &'static str {
+        match self {
+            Command::BuildInputs { .. } => "build-inputs",
+            Command::Fetch { .. } => "fetch",
+            Command::Execute { .. } => "execute",
+            Command::Prove { .. } => "prove",
+            Command::Batch { .. } => "batch",
+            Command::Verify { .. } => "verify",
+            Command::Journal { .. } => "journal",
+            Command::Query { .. } => "query",
+            Command::Range { .. } => "range",
+            Command::Accumulator { .. } => "accumulator",
+            Command::Serve { .. } => "serve",
+            Command::Registry { .. } => "registry",
         }
     }
 }
 
 fn main() {
-    let receipts_json = std::fs::read("receipts.json").unwrap();
-    let receipts: Vec<Receipt> = serde_json::from_slice(receipts_json.as_slice()).unwrap();
-
-    let time = std::time::Instant::now();
-    let (log_addresses, root) = build_from_receipts(receipts);
-
-    let inputs = Inputs {
-        root,
-        log_addresses,
-    };
-
-    println!("Time building inputs: {:?}", time.elapsed());
-    let env = ExecutorEnv::builder()
-        .add_input(&to_vec(&inputs).expect("Error serializing input"))
-        .build()
-        .expect("Error building environment");
+    // Phase spans are logged when they close, with their duration. Filter with RUST_LOG.
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+        )
+        .with_span_events(FmtSpan::CLOSE)
+        .with_writer(std::io::stderr)
+        .init();
 
-    // Obtain the default prover.
-    let prover = default_prover();
+    let cli = Cli::parse();
+    let setup = cli.host_config().and_then(|config| {
+        if config.prover.backend == BackendKind::DevMode {
+            commands::set_dev_mode();
+        }
+        prover::set_executor_limits(config.executor)?;
+        backend::set_prover_backend(backend::backend_from_config(&config.prover)?)
+    });
+    if let Err(err) = setup {
+        eprintln!("Error: {}", err);
+        std::process::exit(1);
+    }
 
-    // Produce a receipt by proving the specified ELF binary.
-    let receipt = prover
-        .prove_elf(env, RECEIPTS_ROOT_AND_PRODUCT_TREE_ELF)
-        .expect("Error proving ELF");
-    // Verify receipt to confirm that recipients will also be able to verify your receipt
-    receipt
-        .verify(RECEIPTS_ROOT_AND_PRODUCT_TREE_ID)
-        .expect("Error verifying receipt");
+    // The first Ctrl-C cancels the proofs, which fail with `ProofCancelled` and stop their
+    // Bonsai sessions, a second one exits right away.
+    let sigint = ctrlc::set_handler(|| {
+        let token = backend::shutdown_token();
+        if token.is_cancelled() {
+            std::process::exit(130);
+        }
+        eprintln!("Cancelling proofs, press Ctrl-C again to exit");
+        token.cancel();
+    });
+    if let Err(err) = sigint {
+        eprintln!("Error: installing the Ctrl-C handler: {}", err);
+        std::process::exit(1);
+    }
 
-    println!("Elapsed after proof: {:?}", time.elapsed());
+    let mut report = Report::new(cli.command.name());
+    let result = match cli.command {
+        Command::BuildInputs {
+            receipts,
+            header,
+            rpc_url,
+            block,
+            registry,
+            output,
+            abi_journal,
+        } => {
+            let encoding = if abi_journal {
+                JournalEncoding::Abi
+            } else {
+                JournalEncoding::Risc0
+            };
+            let source = match (rpc_url, block) {
+                (Some(url), Some(block)) => ReceiptsSource::Rpc { url, block },
+                _ => ReceiptsSource::File { receipts, header },
+            };
+            commands::build_inputs(source, &registry, &output, encoding, &mut report)
+        }
+        Command::Fetch {
+            rpc_url,
+            block,
+            receipts_output,
+            header_output,
+        } => commands::fetch(&rpc_url, &block, &receipts_output, &header_output),
+        Command::Execute { inputs, output } => {
+            commands::execute(&inputs, output.as_deref(), &mut report)
+        }
+        Command::Prove {
+            inputs,
+            output,
+            cache_dir,
+            cache_max_mb,
+            chunked,
+            chunks,
+        } => {
+            let cache = cache_dir.map(|dir| (dir, cache_max_mb << 20));
+            commands::prove(&inputs, &output, cache, chunked, chunks, &mut report)
+        }
+        Command::Batch {
+            blocks,
+            output_dir,
+            jobs,
+        } => batch::run(&blocks, &output_dir, jobs).and_then(|summary| {
+            println!(
+                "Proved {}, skipped {}, failed {} in {:.1}s",
+                summary.proved, summary.skipped, summary.failed, summary.elapsed_secs
+            );
+            match summary.failed {
+                0 => Ok(()),
+                failed => Err(HostError::BatchFailed(failed)),
+            }
+        }),
+        Command::Verify { receipt, output } => {
+            commands::verify(&receipt, output.as_deref(), &mut report)
+        }
+        Command::Journal { receipt, journal } => match journal {
+            Some(journal) => commands::journal_file(&journal),
+            None => commands::journal_receipt(&receipt),
+        },
+        Command::Query {
+            receipt,
+            registry,
+            address,
+        } => commands::query(&receipt, &registry, address.as_deref(), &mut report),
+        Command::Range {
+            blocks,
+            registry,
+            output,
+            recursive,
+            execute_only,
+            address,
+        } => commands::range(
+            &blocks,
+            &registry,
+            &output,
+            recursive,
+            execute_only,
+            address.as_deref(),
+            &mut report,
+        ),
+        Command::Accumulator { dir, action } => commands::accumulator(&dir, action, &mut report),
+        Command::Serve {
+            addr,
+            registry,
+            data_dir,
+            queue_capacity,
+            workers,
+            execute_only,
+        } => {
+            let backend = if execute_only {
+                Backend::Execute
+            } else {
+                Backend::Prove
+            };
+            commands::serve(addr, &registry, data_dir, queue_capacity, workers, backend)
+        }
+        Command::Registry { file, action } => commands::registry(&file, action),
+    };
 
-    let outputs: Outputs = from_slice(&receipt.journal).expect("Error serializing output");
-    println!("Outputs: {:?}", outputs);
+    if let Err(err) = &result {
+        report.error = Some(err.to_string());
+    }
+    if let Some(path) = cli.report {
+        if let Err(err) = report.save(&path) {
+            eprintln!("Error writing report: {}", err);
+        }
+    }
 
-    let product_tree_root = u128::from_be_bytes(outputs.product_tree_root);
-    println!("2: {}", factor_of_n(product_tree_root, 2));
-    println!("3: {}", factor_of_n(product_tree_root, 3));
-    println!("5: {}", factor_of_n(product_tree_root, 5));
+    if let Err(err) = result {
+        eprintln!("Error: {}", err);
+        std::process::exit(1);
+    }
 }
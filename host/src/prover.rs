@@ -0,0 +1,346 @@
+use methods::{
+    CHUNK_COMBINE_ID, RECEIPTS_ROOT_AND_PRODUCT_TREE_ELF, RECEIPTS_ROOT_AND_PRODUCT_TREE_ID,
+};
+use risc0_zkvm::serde::to_vec;
+use risc0_zkvm::{
+    default_executor, ExecutorEnv, ExecutorEnvBuilder, Receipt as ProofReceipt, SessionInfo,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::Path;
+use std::sync::OnceLock;
+
+use trie_core::{Inputs, JournalEncoding, Outputs, Registry};
+
+use crate::backend::{prover_backend, CancelToken, ProofRequest};
+use crate::chunk::verify_chunked;
+use crate::error::HostError;
+use crate::trie::ReceiptsTrie;
+use verifier::artifacts::{decode_outputs, SavedReceipt};
+use verifier::verify_receipt;
+
+/**
+ * Segment and cycle counts of an execution. `total_cycles` is what proving pays for,
+ * each segment being padded to a power of 2, `user_cycles` only counts guest instructions.
+ **/
+#[derive(Debug, Serialize)]
+pub struct ExecutionStats {
+    pub segments: usize,
+    pub total_cycles: u64,
+    pub user_cycles: u64,
+}
+
+impl ExecutionStats {
+    pub fn from_session(session: &SessionInfo) -> Result<Self, HostError> {
+        Ok(ExecutionStats {
+            segments: session.segments.len(),
+            total_cycles: session
+                .segments
+                .iter()
+                .map(|segment| 1u64 << segment.po2)
+                .sum(),
+            user_cycles: session.cycles(),
+        })
+    }
+}
+
+// Range of segment sizes the prover supports, in powers of 2 cycles.
+const MIN_SEGMENT_PO2: u32 = 13;
+const MAX_SEGMENT_PO2: u32 = 24;
+// Rough proving memory of a 2^20 cycles segment on the CPU prover, it halves with each po2 less.
+const SEGMENT_PO2_20_MB: u64 = 10 * 1024;
+
+static LIMITS: OnceLock<ExecutorLimits> = OnceLock::new();
+
+/**
+ * Limits of guest executions. The defaults leave room for blocks with thousands of receipts:
+ * 2^32 cycles a session and risc0's default segments of 2^20 cycles. A `session_limit` of 0
+ * removes the limit. `memory_mb` is the memory budget of the prover, it lowers the segment
+ * size to what fits in it.
+ **/
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExecutorLimits {
+    pub session_limit: u64,
+    pub segment_limit_po2: u32,
+    pub memory_mb: Option<u64>,
+}
+
+impl Default for ExecutorLimits {
+    fn default() -> Self {
+        ExecutorLimits {
+            session_limit: 1 << 32,
+            segment_limit_po2: 20,
+            memory_mb: None,
+        }
+    }
+}
+
+impl ExecutorLimits {
+    /**
+     * The segment size used: `segment_limit_po2`, lowered to fit the memory budget.
+     **/
+    pub fn segment_po2(&self) -> Result<u32, HostError> {
+        if !(MIN_SEGMENT_PO2..=MAX_SEGMENT_PO2).contains(&self.segment_limit_po2) {
+            return Err(HostError::InvalidLimits(format!(
+                "segment_limit_po2 {} is out of range {}..={}",
+                self.segment_limit_po2, MIN_SEGMENT_PO2, MAX_SEGMENT_PO2
+            )));
+        }
+        let Some(memory_mb) = self.memory_mb else {
+            return Ok(self.segment_limit_po2);
+        };
+
+        (MIN_SEGMENT_PO2..=self.segment_limit_po2)
+            .rev()
+            .find(|po2| segment_memory_mb(*po2) <= memory_mb)
+            .ok_or_else(|| {
+                HostError::InvalidLimits(format!(
+                    "a memory budget of {} MB doesn't fit the smallest segments, 2^{} cycles \
+                     need about {} MB",
+                    memory_mb,
+                    MIN_SEGMENT_PO2,
+                    segment_memory_mb(MIN_SEGMENT_PO2)
+                ))
+            })
+    }
+
+    pub(crate) fn apply(&self, builder: &mut ExecutorEnvBuilder) -> Result<(), HostError> {
+        builder
+            .session_limit(Some(self.session_limit).filter(|limit| *limit != 0))
+            .segment_limit_po2(self.segment_po2()?);
+
+        Ok(())
+    }
+}
+
+fn segment_memory_mb(po2: u32) -> u64 {
+    if po2 >= 20 {
+        SEGMENT_PO2_20_MB << (po2 - 20)
+    } else {
+        SEGMENT_PO2_20_MB >> (20 - po2)
+    }
+}
+
+/**
+ * Set the limits of every guest execution of the process, once, before the first one.
+ * Executions use the default limits otherwise.
+ **/
+pub fn set_executor_limits(limits: ExecutorLimits) -> Result<(), HostError> {
+    limits.segment_po2()?;
+    LIMITS
+        .set(limits)
+        .map_err(|_| HostError::InvalidLimits("limits are already set".to_string()))
+}
+
+pub fn executor_limits() -> ExecutorLimits {
+    *LIMITS.get_or_init(ExecutorLimits::default)
+}
+
+pub fn inputs_from_trie(
+    trie: ReceiptsTrie,
+    registry: Registry,
+    journal_encoding: JournalEncoding,
+) -> Inputs {
+    Inputs {
+        root: trie.root,
+        log_addresses: trie.log_addresses,
+        registry,
+        journal_encoding,
+    }
+}
+
+pub fn read_inputs(path: &Path) -> Result<Inputs, HostError> {
+    Ok(bincode::deserialize(&std::fs::read(path)?)?)
+}
+
+pub fn write_inputs(path: &Path, inputs: &Inputs) -> Result<usize, HostError> {
+    let bytes = bincode::serialize(inputs)?;
+    std::fs::write(path, &bytes)?;
+
+    Ok(bytes.len())
+}
+
+/**
+ * Serialize the inputs for a guest, as the words it reads.
+ **/
+pub fn guest_input<T: Serialize>(inputs: &T) -> Result<Vec<u32>, HostError> {
+    Ok(to_vec(inputs).map_err(anyhow::Error::from)?)
+}
+
+/**
+ * Serialize the inputs for a guest, also returning their size in bytes.
+ **/
+pub fn executor_env<T: Serialize>(inputs: &T) -> Result<(ExecutorEnv<'static>, usize), HostError> {
+    let words = guest_input(inputs)?;
+    let mut builder = ExecutorEnv::builder();
+    executor_limits().apply(&mut builder)?;
+    let env = builder.write_slice(&words).build()?;
+
+    Ok((env, words.len() * 4))
+}
+
+/**
+ * Run the guest with the executor only, no proof is generated.
+ **/
+pub fn execute_env(env: ExecutorEnv<'static>) -> Result<SessionInfo, HostError> {
+    execute_elf(env, RECEIPTS_ROOT_AND_PRODUCT_TREE_ELF)
+}
+
+pub fn execute_elf(env: ExecutorEnv<'static>, elf: &[u8]) -> Result<SessionInfo, HostError> {
+    Ok(default_executor().execute(env, elf)?)
+}
+
+/**
+ * Prove the guest on `inputs`. The receipt is not verified, callers do it as a separate step.
+ **/
+pub fn prove_inputs(inputs: &Inputs, cancel: &CancelToken) -> Result<ProofReceipt, HostError> {
+    prove_elf(
+        &guest_input(inputs)?,
+        RECEIPTS_ROOT_AND_PRODUCT_TREE_ELF,
+        RECEIPTS_ROOT_AND_PRODUCT_TREE_ID,
+        cancel,
+    )
+}
+
+/**
+ * Prove a guest on serialized inputs with the prover backend of the process. Command line
+ * proofs pass `shutdown_token()`, so SIGINT cancels them.
+ **/
+pub fn prove_elf(
+    input: &[u32],
+    elf: &[u8],
+    image_id: [u32; 8],
+    cancel: &CancelToken,
+) -> Result<ProofReceipt, HostError> {
+    prover_backend().prove(&ProofRequest {
+        elf,
+        image_id,
+        input,
+        assumptions: &[],
+        succinct: false,
+        cancel,
+    })
+}
+
+/**
+ * Prove a guest that verifies other receipts with `env::verify`. Recursion resolves the
+ * guest's assumptions with succinct receipts, and the receipt returned is succinct too, so
+ * its size doesn't grow with the receipts it verifies. The local backends compress the
+ * receipts first. Bonsai can't, it only takes receipts that are already succinct and refuses
+ * others before uploading anything.
+ **/
+pub fn prove_composition<T: Serialize>(
+    inputs: &T,
+    assumptions: &[ProofReceipt],
+    elf: &[u8],
+    image_id: [u32; 8],
+    cancel: &CancelToken,
+) -> Result<ProofReceipt, HostError> {
+    prover_backend().prove(&ProofRequest {
+        elf,
+        image_id,
+        input: &guest_input(inputs)?,
+        assumptions,
+        succinct: true,
+        cancel,
+    })
+}
+
+/**
+ * Decode the outputs committed by the guest, in either journal encoding.
+ **/
+pub fn decode_journal(journal: &[u8]) -> Result<Outputs, HostError> {
+    Ok(decode_outputs(journal)?)
+}
+
+/**
+ * SHA-256 of the journal, the digest the receipt commits to and an on-chain verifier checks.
+ **/
+pub fn journal_digest(journal: &[u8]) -> [u8; 32] {
+    Sha256::digest(journal).into()
+}
+
+/**
+ * Verify a per-block receipt, proven in one piece or in chunks, and decode its outputs.
+ **/
+pub fn verify_saved(saved: &SavedReceipt) -> Result<Outputs, HostError> {
+    if saved.image_id == CHUNK_COMBINE_ID {
+        return verify_chunked(saved);
+    }
+
+    Ok(verify_receipt(saved, &RECEIPTS_ROOT_AND_PRODUCT_TREE_ID)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_segment_po2() {
+        let mut limits = ExecutorLimits::default();
+        assert_eq!(limits.segment_po2().unwrap(), 20);
+
+        // 2^18 cycles segments need about 2.5 GB.
+        limits.memory_mb = Some(3 * 1024);
+        assert_eq!(limits.segment_po2().unwrap(), 18);
+        limits.memory_mb = Some(64 * 1024);
+        assert_eq!(limits.segment_po2().unwrap(), 20);
+
+        limits.memory_mb = Some(16);
+        assert!(matches!(
+            limits.segment_po2(),
+            Err(HostError::InvalidLimits(_))
+        ));
+        limits.memory_mb = None;
+        limits.segment_limit_po2 = 25;
+        assert!(matches!(
+            limits.segment_po2(),
+            Err(HostError::InvalidLimits(_))
+        ));
+    }
+
+    #[test]
+    fn test_session_limit_error() {
+        let err = anyhow::anyhow!("Session limit exceeded: 4194304 >= 4194304")
+            .context("execution failed");
+        let err = HostError::from(err);
+        assert!(matches!(err, HostError::SessionLimitExceeded(_)));
+        assert!(err.to_string().contains("--session-limit"));
+        assert!(matches!(
+            HostError::from(anyhow::anyhow!("guest panicked")),
+            HostError::Zkvm(_)
+        ));
+    }
+
+    #[test]
+    fn test_limits_config() {
+        let limits: ExecutorLimits = toml::from_str("segment_limit_po2 = 19").unwrap();
+        assert_eq!(limits.segment_limit_po2, 19);
+        assert_eq!(
+            limits.session_limit,
+            ExecutorLimits::default().session_limit
+        );
+
+        // A misspelled limit is an error rather than the default.
+        assert!(toml::from_str::<ExecutorLimits>("sesion_limit = 0").is_err());
+    }
+
+    #[test]
+    fn test_abi_journal_digest() {
+        let outputs = Outputs {
+            root: std::array::from_fn(|i| i as u8),
+            product_tree_hash: [0xaa; 32],
+            product_tree_root: (64u128 * 81 * 25).to_be_bytes(),
+            registry_hash: std::array::from_fn(|i| 32 + i as u8),
+            untracked_logs: 3,
+        };
+        let journal = outputs.abi_encode();
+
+        assert_eq!(decode_journal(&journal).unwrap(), outputs);
+        assert_eq!(
+            hex::encode(journal_digest(&journal)),
+            "c1a4c8471348056509271928027c22f5782c86d53eb2501ded16942125fe0c16"
+        );
+    }
+}
@@ -0,0 +1,118 @@
+use trie_core::accumulator::AccumulatorState;
+use trie_core::range::RangeOutputs;
+use trie_core::{factor_of_n, Contract, Outputs, Registry};
+
+use crate::error::HostError;
+
+/**
+ * Number of logs of each registered contract in verified outputs, or of `address` only.
+ * The registry must be the one committed in the outputs, and the root can't be 0.
+ **/
+pub fn count_logs<'a>(
+    outputs: &Outputs,
+    registry: &'a Registry,
+    address: Option<&str>,
+) -> Result<Vec<(&'a Contract, u8)>, HostError> {
+    if outputs.registry_hash != registry.hash() {
+        return Err(HostError::RegistryHashMismatch);
+    }
+
+    let product_tree_root = u128::from_be_bytes(outputs.product_tree_root);
+    if product_tree_root == 0 {
+        return Err(HostError::ZeroProductTreeRoot);
+    }
+    let counts: Vec<_> = registry
+        .contracts
+        .iter()
+        .filter(|contract| address.map_or(true, |a| contract.address.eq_ignore_ascii_case(a)))
+        .map(|contract| {
+            let count = factor_of_n(product_tree_root, contract.prime as u128);
+            (contract, count)
+        })
+        .collect();
+
+    match address {
+        Some(address) if counts.is_empty() => Err(HostError::NotRegistered(address.to_string())),
+        _ => Ok(counts),
+    }
+}
+
+/**
+ * Number of logs of each registered contract over a verified range, or of `address` only.
+ **/
+pub fn count_range_logs<'a>(
+    outputs: &RangeOutputs,
+    registry: &'a Registry,
+    address: Option<&str>,
+) -> Result<Vec<(&'a Contract, u32)>, HostError> {
+    select_counts(&outputs.registry_hash, &outputs.counts, registry, address)
+}
+
+/**
+ * Number of logs of each registered contract since the first block of an accumulator chain.
+ **/
+pub fn count_accumulated_logs<'a>(
+    state: &AccumulatorState,
+    registry: &'a Registry,
+    address: Option<&str>,
+) -> Result<Vec<(&'a Contract, u32)>, HostError> {
+    select_counts(&state.registry_hash, &state.counts, registry, address)
+}
+
+/**
+ * Pair counts committed in registry order with their contracts.
+ **/
+fn select_counts<'a>(
+    registry_hash: &[u8; 32],
+    counts: &[u32],
+    registry: &'a Registry,
+    address: Option<&str>,
+) -> Result<Vec<(&'a Contract, u32)>, HostError> {
+    if *registry_hash != registry.hash() {
+        return Err(HostError::RegistryHashMismatch);
+    }
+
+    let counts: Vec<_> = registry
+        .contracts
+        .iter()
+        .zip(counts)
+        .filter(|(contract, _)| address.map_or(true, |a| contract.address.eq_ignore_ascii_case(a)))
+        .map(|(contract, count)| (contract, *count))
+        .collect();
+
+    match address {
+        Some(address) if counts.is_empty() => Err(HostError::NotRegistered(address.to_string())),
+        _ => Ok(counts),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_count_logs() {
+        let registry = Registry::new(vec![
+            ("0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2".to_string(), 2),
+            ("0xd6a347c998109ac87ccb323f78960c91bbe911cd".to_string(), 3),
+        ])
+        .unwrap();
+        let mut outputs = Outputs {
+            root: [0; 32],
+            product_tree_hash: [0; 32],
+            product_tree_root: 12u128.to_be_bytes(),
+            registry_hash: registry.hash(),
+            untracked_logs: 0,
+        };
+
+        let counts = count_logs(&outputs, &registry, None).unwrap();
+        let counts: Vec<u8> = counts.into_iter().map(|(_, count)| count).collect();
+        assert_eq!(counts, vec![2, 1]);
+
+        outputs.product_tree_root = [0; 16];
+        assert!(matches!(
+            count_logs(&outputs, &registry, None),
+            Err(HostError::ZeroProductTreeRoot)
+        ));
+    }
+}
@@ -0,0 +1,533 @@
+use methods::{
+    CHUNK_COMBINE_ID, RANGE_AGGREGATION_ELF, RANGE_AGGREGATION_ID, RANGE_COMPOSITION_ELF,
+    RANGE_COMPOSITION_ID, RECEIPTS_ROOT_AND_PRODUCT_TREE_ID,
+};
+use risc0_zkvm::serde::from_slice;
+use risc0_zkvm::{Receipt as ProofReceipt, SessionInfo};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use trie_core::range::{
+    aggregate, BlockSummary, ComposedRangeInputs, ComposedRangeOutputs, ProvenBlock, RangeInputs,
+    RangeOutputs,
+};
+use trie_core::Registry;
+use verifier::artifacts::{image_id_hex, load_journal, load_receipt, to_hex};
+use verifier::VerifyError;
+
+use crate::backend::shutdown_token;
+use crate::error::HostError;
+use crate::input::load_header_rlp;
+use crate::prover::{
+    execute_elf, executor_env, guest_input, prove_composition, prove_elf, verify_saved,
+};
+
+const RANGE_JOURNAL_VERSION: u32 = 1;
+
+/**
+ * Blocks of a range, in order. Each block gives its header, as written by `fetch`, and either
+ * the receipt of its per-block proof, verified before use, or a journal file, whose outputs
+ * are trusted. Paths are relative to the manifest.
+ **/
+#[derive(Debug, Deserialize)]
+pub struct RangeManifest {
+    pub blocks: Vec<RangeBlock>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct RangeBlock {
+    pub header: PathBuf,
+    pub receipt: Option<PathBuf>,
+    pub journal: Option<PathBuf>,
+}
+
+/**
+ * Human readable view of `RangeOutputs`, like `OutputsJson` for a single block.
+ **/
+#[derive(Debug, Deserialize, Serialize)]
+pub struct RangeOutputsJson {
+    pub first_block: u64,
+    pub last_block: u64,
+    pub first_block_hash: String,
+    pub last_block_hash: String,
+    pub blocks_hash: String,
+    pub range_tree_hash: String,
+    pub registry_hash: String,
+    pub counts: Vec<u32>,
+    pub untracked_logs: u64,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct RangeJournalFile {
+    pub version: u32,
+    pub image_id: String,
+    pub journal: String,
+    pub outputs: RangeOutputsJson,
+}
+
+impl From<&RangeOutputs> for RangeOutputsJson {
+    fn from(outputs: &RangeOutputs) -> Self {
+        RangeOutputsJson {
+            first_block: outputs.first_block,
+            last_block: outputs.last_block,
+            first_block_hash: to_hex(&outputs.first_block_hash),
+            last_block_hash: to_hex(&outputs.last_block_hash),
+            blocks_hash: to_hex(&outputs.blocks_hash),
+            range_tree_hash: to_hex(&outputs.range_tree_hash),
+            registry_hash: to_hex(&outputs.registry_hash),
+            counts: outputs.counts.clone(),
+            untracked_logs: outputs.untracked_logs,
+        }
+    }
+}
+
+/**
+ * Read a range manifest, TOML or JSON by extension.
+ **/
+pub fn load_manifest(path: &Path) -> Result<Vec<RangeBlock>, HostError> {
+    let content = std::fs::read_to_string(path)?;
+    let manifest: RangeManifest = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("json") => serde_json::from_str(&content)?,
+        _ => toml::from_str(&content)?,
+    };
+
+    let base = path.parent().unwrap_or_else(|| Path::new("."));
+    Ok(manifest
+        .blocks
+        .into_iter()
+        .map(|block| RangeBlock {
+            header: base.join(block.header),
+            receipt: block.receipt.map(|receipt| base.join(receipt)),
+            journal: block.journal.map(|journal| base.join(journal)),
+        })
+        .collect())
+}
+
+/**
+ * RLP encoded header of a block and the outputs of its per-block proof, from its verified
+ * receipt or its journal file.
+ **/
+pub fn block_summary(block: &RangeBlock) -> Result<BlockSummary, HostError> {
+    let outputs = match (&block.receipt, &block.journal) {
+        (Some(receipt), None) => verify_saved(&load_receipt(receipt)?)?,
+        (None, Some(journal)) => {
+            let (file, outputs) = load_journal(journal)?;
+            let image_id = image_id_hex(&RECEIPTS_ROOT_AND_PRODUCT_TREE_ID);
+            if file.image_id != image_id && file.image_id != image_id_hex(&CHUNK_COMBINE_ID) {
+                return Err(VerifyError::ImageIdMismatch {
+                    expected: image_id,
+                    found: file.image_id,
+                }
+                .into());
+            }
+            outputs
+        }
+        _ => {
+            return Err(HostError::InvalidInput(format!(
+                "block with header {}: expected exactly one of receipt or journal",
+                block.header.display()
+            )))
+        }
+    };
+
+    Ok(BlockSummary {
+        header: load_header_rlp(&block.header)?,
+        outputs,
+    })
+}
+
+/**
+ * Build the aggregation inputs of the blocks in a manifest. The range is aggregated once on
+ * the host so that a broken chain or a registry mismatch is reported before the guest runs.
+ **/
+pub fn range_inputs(manifest: &Path, registry: Registry) -> Result<RangeInputs, HostError> {
+    let blocks = load_manifest(manifest)?
+        .iter()
+        .map(block_summary)
+        .collect::<Result<Vec<_>, _>>()?;
+    let inputs = RangeInputs { blocks, registry };
+    aggregate(&inputs)?;
+
+    Ok(inputs)
+}
+
+/**
+ * A block to verify in a guest: its summary, from the receipt verified on the host, the
+ * header and journal the guest verifies, and the receipt that resolves the guest's
+ * `env::verify`. A journal file can't be verified in a guest, the block needs a receipt of
+ * the per-block guest.
+ **/
+pub fn proven_block(
+    block: &RangeBlock,
+) -> Result<(BlockSummary, ProvenBlock, ProofReceipt), HostError> {
+    let receipt = match (&block.receipt, &block.journal) {
+        (Some(receipt), None) => load_receipt(receipt)?,
+        _ => {
+            return Err(HostError::InvalidInput(format!(
+                "block with header {}: verifying it in a guest needs a receipt",
+                block.header.display()
+            )))
+        }
+    };
+    // The guests verify the receipts against the per-block guest only.
+    if receipt.image_id != RECEIPTS_ROOT_AND_PRODUCT_TREE_ID {
+        return Err(VerifyError::ImageIdMismatch {
+            expected: image_id_hex(&RECEIPTS_ROOT_AND_PRODUCT_TREE_ID),
+            found: image_id_hex(&receipt.image_id),
+        }
+        .into());
+    }
+    let header = load_header_rlp(&block.header)?;
+    let summary = BlockSummary {
+        header: header.clone(),
+        outputs: verify_saved(&receipt)?,
+    };
+    let proven = ProvenBlock {
+        header,
+        journal: receipt.receipt.journal.bytes.clone(),
+    };
+
+    Ok((summary, proven, receipt.receipt))
+}
+
+/**
+ * Build the composition inputs of the blocks in a manifest and the receipts the guest
+ * verifies. Every block needs a receipt, a journal file can't be verified in the guest.
+ * The receipts are verified and the range aggregated on the host first, like `range_inputs`.
+ **/
+pub fn composed_range_inputs(
+    manifest: &Path,
+    registry: Registry,
+) -> Result<(ComposedRangeInputs, Vec<ProofReceipt>), HostError> {
+    let mut summaries = Vec::new();
+    let mut blocks = Vec::new();
+    let mut receipts = Vec::new();
+    for block in load_manifest(manifest)? {
+        let (summary, proven, receipt) = proven_block(&block)?;
+        summaries.push(summary);
+        blocks.push(proven);
+        receipts.push(receipt);
+    }
+    let inputs = RangeInputs {
+        blocks: summaries,
+        registry,
+    };
+    aggregate(&inputs)?;
+
+    Ok((
+        ComposedRangeInputs {
+            block_image_id: RECEIPTS_ROOT_AND_PRODUCT_TREE_ID,
+            blocks,
+            registry: inputs.registry,
+        },
+        receipts,
+    ))
+}
+
+pub fn decode_range_journal(journal: &[u8]) -> Result<RangeOutputs, HostError> {
+    from_slice(journal).map_err(|err| VerifyError::InvalidJournal(err.to_string()).into())
+}
+
+/**
+ * Decode the journal of the composition guest and check the blocks were verified against
+ * the per-block guest.
+ **/
+pub fn decode_composed_range_journal(journal: &[u8]) -> Result<RangeOutputs, HostError> {
+    let outputs: ComposedRangeOutputs =
+        from_slice(journal).map_err(|err| VerifyError::InvalidJournal(err.to_string()))?;
+    if outputs.block_image_id != RECEIPTS_ROOT_AND_PRODUCT_TREE_ID {
+        return Err(VerifyError::ImageIdMismatch {
+            expected: image_id_hex(&RECEIPTS_ROOT_AND_PRODUCT_TREE_ID),
+            found: image_id_hex(&outputs.block_image_id),
+        }
+        .into());
+    }
+
+    Ok(outputs.range)
+}
+
+/**
+ * Run the aggregation guest with the executor only, no proof is generated.
+ **/
+pub fn execute_range(inputs: &RangeInputs) -> Result<(SessionInfo, RangeOutputs), HostError> {
+    let session = execute_elf(executor_env(inputs)?.0, RANGE_AGGREGATION_ELF)?;
+    let outputs = decode_range_journal(&session.journal.bytes)?;
+
+    Ok((session, outputs))
+}
+
+/**
+ * Prove the aggregation guest and verify the receipt.
+ **/
+pub fn prove_range(inputs: &RangeInputs) -> Result<(ProofReceipt, RangeOutputs), HostError> {
+    let receipt = prove_elf(
+        &guest_input(inputs)?,
+        RANGE_AGGREGATION_ELF,
+        RANGE_AGGREGATION_ID,
+        shutdown_token(),
+    )?;
+    receipt
+        .verify(RANGE_AGGREGATION_ID)
+        .map_err(|err| VerifyError::InvalidProof(err.to_string()))?;
+    let outputs = decode_range_journal(&receipt.journal.bytes)?;
+
+    Ok((receipt, outputs))
+}
+
+/**
+ * Run the composition guest with the executor only. The executor records the `env::verify`
+ * calls as assumptions without checking them, the receipts are only needed to prove.
+ **/
+pub fn execute_composed_range(
+    inputs: &ComposedRangeInputs,
+) -> Result<(SessionInfo, RangeOutputs), HostError> {
+    let session = execute_elf(executor_env(inputs)?.0, RANGE_COMPOSITION_ELF)?;
+    let outputs = decode_composed_range_journal(&session.journal.bytes)?;
+
+    Ok((session, outputs))
+}
+
+/**
+ * Prove the composition guest with the per-block receipts it verifies and verify the receipt.
+ **/
+pub fn prove_composed_range(
+    inputs: &ComposedRangeInputs,
+    receipts: &[ProofReceipt],
+) -> Result<(ProofReceipt, RangeOutputs), HostError> {
+    let receipt = prove_composition(
+        inputs,
+        receipts,
+        RANGE_COMPOSITION_ELF,
+        RANGE_COMPOSITION_ID,
+        shutdown_token(),
+    )?;
+    receipt
+        .verify(RANGE_COMPOSITION_ID)
+        .map_err(|err| VerifyError::InvalidProof(err.to_string()))?;
+    let outputs = decode_composed_range_journal(&receipt.journal.bytes)?;
+
+    Ok((receipt, outputs))
+}
+
+/**
+ * A range to aggregate with either guest: the aggregation guest, which trusts the block
+ * outputs verified by the host, or the composition guest, which verifies the block receipts.
+ **/
+pub enum RangeJob {
+    Aggregate(RangeInputs),
+    Compose(ComposedRangeInputs, Vec<ProofReceipt>),
+}
+
+impl RangeJob {
+    pub fn load(manifest: &Path, registry: Registry, recursive: bool) -> Result<Self, HostError> {
+        Ok(if recursive {
+            let (inputs, receipts) = composed_range_inputs(manifest, registry)?;
+            RangeJob::Compose(inputs, receipts)
+        } else {
+            RangeJob::Aggregate(range_inputs(manifest, registry)?)
+        })
+    }
+
+    pub fn image_id(&self) -> [u32; 8] {
+        match self {
+            RangeJob::Aggregate(_) => RANGE_AGGREGATION_ID,
+            RangeJob::Compose(..) => RANGE_COMPOSITION_ID,
+        }
+    }
+
+    pub fn execute(&self) -> Result<(SessionInfo, RangeOutputs), HostError> {
+        match self {
+            RangeJob::Aggregate(inputs) => execute_range(inputs),
+            RangeJob::Compose(inputs, _) => execute_composed_range(inputs),
+        }
+    }
+
+    pub fn prove(&self) -> Result<(ProofReceipt, RangeOutputs), HostError> {
+        match self {
+            RangeJob::Aggregate(inputs) => prove_range(inputs),
+            RangeJob::Compose(inputs, receipts) => prove_composed_range(inputs, receipts),
+        }
+    }
+}
+
+pub fn save_range_journal(
+    path: &Path,
+    image_id: &[u32; 8],
+    journal: &[u8],
+    outputs: &RangeOutputs,
+) -> Result<(), HostError> {
+    let file = RangeJournalFile {
+        version: RANGE_JOURNAL_VERSION,
+        image_id: image_id_hex(image_id),
+        journal: to_hex(journal),
+        outputs: outputs.into(),
+    };
+    std::fs::write(path, serde_json::to_string_pretty(&file)?)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use risc0_zkvm::serde::to_vec;
+    use serde_json::{json, Value};
+    use trie_core::header::encode_list;
+    use trie_core::keccak256;
+    use trie_core::range::RangeError;
+    use trie_core::Outputs;
+    use verifier::artifacts::save_journal;
+
+    /**
+     * `eth_getBlockByNumber` output of a pre-London block, with its hash.
+     **/
+    fn header_json(
+        parent_hash: [u8; 32],
+        number: u64,
+        receipts_root: [u8; 32],
+    ) -> (Value, [u8; 32]) {
+        let fields = vec![
+            parent_hash.to_vec(),
+            vec![0x22; 32],
+            vec![0x33; 20],
+            vec![0x44; 32],
+            vec![0x55; 32],
+            receipts_root.to_vec(),
+            vec![0; 256],
+            vec![],
+            vec![number as u8],
+            vec![0x01, 0xc9, 0xc3, 0x80],
+            vec![],
+            vec![0x64, 0xf1, 0xc2, 0xa3],
+            vec![],
+            vec![0x77; 32],
+            vec![0; 8],
+        ];
+        let hash = keccak256(&encode_list(&fields));
+        let header = json!({
+            "hash": to_hex(&hash),
+            "parentHash": to_hex(&parent_hash),
+            "sha3Uncles": to_hex(&[0x22; 32]),
+            "miner": to_hex(&[0x33; 20]),
+            "stateRoot": to_hex(&[0x44; 32]),
+            "transactionsRoot": to_hex(&[0x55; 32]),
+            "receiptsRoot": to_hex(&receipts_root),
+            "logsBloom": to_hex(&[0; 256]),
+            "difficulty": "0x0",
+            "number": format!("{:#x}", number),
+            "gasLimit": "0x1c9c380",
+            "gasUsed": "0x0",
+            "timestamp": "0x64f1c2a3",
+            "extraData": "0x",
+            "mixHash": to_hex(&[0x77; 32]),
+            "nonce": "0x0000000000000000",
+        });
+        (header, hash)
+    }
+
+    #[test]
+    fn test_range_inputs_from_journals() {
+        let dir = std::env::temp_dir().join(format!("range-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let registry = Registry::new(vec![
+            ("0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2".to_string(), 2),
+            ("0xd6a347c998109ac87ccb323f78960c91bbe911cd".to_string(), 3),
+        ])
+        .unwrap();
+
+        let mut manifest = String::new();
+        let mut parent_hash = [0xff; 32];
+        for (number, product_tree_root) in [(10u64, 12u128), (11, 3), (12, 2)] {
+            let outputs = Outputs {
+                root: [number as u8; 32],
+                product_tree_hash: [0; 32],
+                product_tree_root: product_tree_root.to_be_bytes(),
+                registry_hash: registry.hash(),
+                untracked_logs: 0,
+            };
+            let journal = format!("{}.journal.json", number);
+            save_journal(
+                &dir.join(&journal),
+                &outputs.abi_encode(),
+                &outputs,
+                &RECEIPTS_ROOT_AND_PRODUCT_TREE_ID,
+            )
+            .unwrap();
+
+            let (header, hash) = header_json(parent_hash, number, outputs.root);
+            parent_hash = hash;
+            let header_file = format!("{}.header.json", number);
+            std::fs::write(dir.join(&header_file), header.to_string()).unwrap();
+
+            manifest.push_str(&format!(
+                "[[blocks]]\nheader = \"{}\"\njournal = \"{}\"\n",
+                header_file, journal
+            ));
+        }
+
+        let path = dir.join("range.toml");
+        std::fs::write(&path, &manifest).unwrap();
+        let inputs = range_inputs(&path, registry.clone()).unwrap();
+        assert_eq!(inputs.blocks.len(), 3);
+        let outputs = aggregate(&inputs).unwrap();
+        assert_eq!((outputs.first_block, outputs.last_block), (10, 12));
+        assert_eq!(outputs.last_block_hash, parent_hash);
+        assert_eq!(outputs.counts, vec![3, 2]);
+
+        // Journals can't be verified in the guest.
+        assert!(matches!(
+            composed_range_inputs(&path, registry.clone()),
+            Err(HostError::InvalidInput(_))
+        ));
+
+        // Block 11 is left out: 12 doesn't follow 10.
+        let gap = manifest.replace(
+            "[[blocks]]\nheader = \"11.header.json\"\njournal = \"11.journal.json\"\n",
+            "",
+        );
+        std::fs::write(&path, &gap).unwrap();
+        match range_inputs(&path, registry) {
+            Err(HostError::Range(RangeError::NotConsecutive { expected, found })) => {
+                assert_eq!((expected, found), (11, 12))
+            }
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_decode_composed_range_journal() {
+        let range = RangeOutputs {
+            first_block: 10,
+            last_block: 12,
+            first_block_hash: [0x10; 32],
+            last_block_hash: [0x12; 32],
+            blocks_hash: [0xbb; 32],
+            range_tree_hash: [0xcc; 32],
+            registry_hash: [0xdd; 32],
+            counts: vec![3, 2],
+            untracked_logs: 1,
+        };
+        let journal = |block_image_id| -> Vec<u8> {
+            to_vec(&ComposedRangeOutputs {
+                block_image_id,
+                range: range.clone(),
+            })
+            .unwrap()
+            .iter()
+            .flat_map(|word| word.to_le_bytes())
+            .collect()
+        };
+
+        assert_eq!(
+            decode_composed_range_journal(&journal(RECEIPTS_ROOT_AND_PRODUCT_TREE_ID)).unwrap(),
+            range
+        );
+        // Blocks verified against another guest.
+        assert!(matches!(
+            decode_composed_range_journal(&journal([0; 8])),
+            Err(HostError::Verify(VerifyError::ImageIdMismatch { .. }))
+        ));
+    }
+}
@@ -0,0 +1,89 @@
+use serde::de::{MapAccess, Visitor};
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::path::Path;
+
+use trie_core::Registry;
+
+use crate::error::HostError;
+
+/**
+ * On disk registry, TOML or JSON depending on the file extension:
+ * a `contracts` table mapping addresses to primes.
+ * Entries are kept in file order, with duplicates, so validation can report them.
+ **/
+#[derive(Debug, Deserialize, Serialize)]
+pub struct RegistryFile {
+    #[serde(deserialize_with = "entries", serialize_with = "serialize_entries")]
+    pub contracts: Vec<(String, u64)>,
+}
+
+fn entries<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<(String, u64)>, D::Error> {
+    struct EntriesVisitor;
+
+    impl<'de> Visitor<'de> for EntriesVisitor {
+        type Value = Vec<(String, u64)>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "a map of addresses to primes")
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+            let mut entries = Vec::new();
+            while let Some(entry) = map.next_entry()? {
+                entries.push(entry);
+            }
+            Ok(entries)
+        }
+    }
+
+    deserializer.deserialize_map(EntriesVisitor)
+}
+
+fn serialize_entries<S: Serializer>(
+    entries: &[(String, u64)],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let mut map = serializer.serialize_map(Some(entries.len()))?;
+    for (address, prime) in entries {
+        map.serialize_entry(address, prime)?;
+    }
+    map.end()
+}
+
+fn is_json(path: &Path) -> bool {
+    path.extension().and_then(|ext| ext.to_str()) == Some("json")
+}
+
+pub fn load_registry(path: &Path) -> Result<Registry, HostError> {
+    let content = std::fs::read_to_string(path)?;
+    let file: RegistryFile = if is_json(path) {
+        serde_json::from_str(&content)?
+    } else {
+        toml::from_str(&content)?
+    };
+
+    let registry = Registry::new(file.contracts)?;
+
+    Ok(registry)
+}
+
+pub fn save_registry(path: &Path, registry: &Registry) -> Result<(), HostError> {
+    let file = RegistryFile {
+        contracts: registry
+            .contracts
+            .iter()
+            .map(|contract| (contract.address.clone(), contract.prime))
+            .collect(),
+    };
+
+    let content = if is_json(path) {
+        serde_json::to_string_pretty(&file)?
+    } else {
+        toml::to_string(&file)?
+    };
+    std::fs::write(path, content)?;
+
+    Ok(())
+}
@@ -0,0 +1,65 @@
+use serde::Serialize;
+use std::path::Path;
+use std::time::Instant;
+
+use verifier::artifacts::OutputsJson;
+
+use crate::error::HostError;
+use crate::prover::ExecutionStats;
+
+#[derive(Debug, Serialize)]
+pub struct PhaseTiming {
+    pub name: &'static str,
+    pub duration_secs: f64,
+}
+
+/**
+ * Machine readable summary of a run, written as JSON with `--report` for benchmarking
+ * dashboards. Fields a command doesn't produce are left out.
+ **/
+#[derive(Debug, Default, Serialize)]
+pub struct Report {
+    pub command: &'static str,
+    pub phases: Vec<PhaseTiming>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub receipt_count: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub log_count: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub input_bytes: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub execution: Option<ExecutionStats>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub outputs: Option<OutputsJson>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Report {
+    pub fn new(command: &'static str) -> Self {
+        Report {
+            command,
+            ..Default::default()
+        }
+    }
+
+    /**
+     * Run `f` inside a `phase` tracing span and record how long it took.
+     **/
+    pub fn phase<T>(&mut self, name: &'static str, f: impl FnOnce() -> T) -> T {
+        let span = tracing::info_span!("phase", phase = name);
+        let time = Instant::now();
+        let result = span.in_scope(f);
+
+        self.phases.push(PhaseTiming {
+            name,
+            duration_secs: time.elapsed().as_secs_f64(),
+        });
+        result
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), HostError> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,225 @@
+use serde_json::{json, Value};
+use std::cell::Cell;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::error::HostError;
+use crate::input::{parse_block_receipts, parse_header, BlockHeader, BlockReceipt};
+
+/**
+ * Block selector for JSON-RPC calls: a number (decimal or 0x hex), a 32 byte hash or a tag
+ * such as `latest` or `finalized`.
+ **/
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum BlockId {
+    Number(u64),
+    Hash(String),
+    Tag(String),
+}
+
+impl FromStr for BlockId {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.strip_prefix("0x") {
+            Some(digits) if digits.len() == 64 => {
+                hex::decode(digits)
+                    .map_err(|err| format!("invalid block hash {}: {}", value, err))?;
+                Ok(BlockId::Hash(value.to_lowercase()))
+            }
+            Some(digits) => u64::from_str_radix(digits, 16)
+                .map(BlockId::Number)
+                .map_err(|err| format!("invalid block number {}: {}", value, err)),
+            None => match value {
+                "latest" | "safe" | "finalized" | "earliest" | "pending" => {
+                    Ok(BlockId::Tag(value.to_string()))
+                }
+                _ => value
+                    .parse()
+                    .map(BlockId::Number)
+                    .map_err(|err| format!("invalid block {}: {}", value, err)),
+            },
+        }
+    }
+}
+
+impl BlockId {
+    fn param(&self) -> Value {
+        match self {
+            BlockId::Number(number) => json!(format!("{:#x}", number)),
+            BlockId::Hash(hash) => json!(hash),
+            BlockId::Tag(tag) => json!(tag),
+        }
+    }
+}
+
+/**
+ * A block fetched from a node: the parsed header and receipts, and the raw JSON-RPC
+ * results so they can be saved and used offline.
+ **/
+pub struct BlockData {
+    pub header: BlockHeader,
+    pub receipts: Vec<BlockReceipt>,
+    pub raw_header: Value,
+    pub raw_receipts: Value,
+}
+
+pub struct RpcClient {
+    url: String,
+    agent: ureq::Agent,
+    next_id: Cell<u64>,
+}
+
+impl RpcClient {
+    pub fn new(url: &str) -> Self {
+        RpcClient {
+            url: url.to_string(),
+            agent: ureq::AgentBuilder::new()
+                .timeout(Duration::from_secs(60))
+                .build(),
+            next_id: Cell::new(1),
+        }
+    }
+
+    /**
+     * Send a JSON-RPC request and return its `result`, JSON-RPC errors are returned as errors.
+     **/
+    pub fn call(&self, method: &str, params: Value) -> Result<Value, HostError> {
+        let id = self.next_id.get();
+        self.next_id.set(id + 1);
+
+        let mut response: Value = self
+            .agent
+            .post(&self.url)
+            .send_json(json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "method": method,
+                "params": params,
+            }))?
+            .into_json()?;
+
+        if let Some(error) = response.get("error") {
+            return Err(HostError::JsonRpc {
+                method: method.to_string(),
+                error: error.to_string(),
+            });
+        }
+        match response.get_mut("result").map(Value::take) {
+            Some(Value::Null) | None => Err(HostError::JsonRpc {
+                method: method.to_string(),
+                error: "no result".to_string(),
+            }),
+            Some(result) => Ok(result),
+        }
+    }
+
+    /**
+     * Fetch the header with `eth_getBlockByNumber` (or `eth_getBlockByHash`), then the receipts
+     * of that exact block by hash with `eth_getBlockReceipts`, so a tag can't move in between.
+     * Each receipt must have the block's hash.
+     **/
+    pub fn fetch_block(&self, block: &BlockId) -> Result<BlockData, HostError> {
+        let raw_header = match block {
+            BlockId::Hash(_) => self.call("eth_getBlockByHash", json!([block.param(), false]))?,
+            _ => self.call("eth_getBlockByNumber", json!([block.param(), false]))?,
+        };
+        let header = parse_header(raw_header.clone())?;
+
+        let hash = format!("{:?}", header.hash);
+        let raw_receipts = self.call("eth_getBlockReceipts", json!([hash]))?;
+        let receipts = parse_block_receipts(raw_receipts.clone(), Some(header.hash))?;
+
+        Ok(BlockData {
+            header,
+            receipts,
+            raw_header,
+            raw_receipts,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reth_primitives::Receipt;
+    use std::thread;
+
+    const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/rpc");
+
+    /**
+     * Serve the recorded response of each method from the fixtures directory.
+     **/
+    fn mock_node() -> String {
+        let server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", server.server_addr().to_ip().unwrap());
+
+        thread::spawn(move || {
+            for mut request in server.incoming_requests() {
+                let call: Value = serde_json::from_reader(request.as_reader()).unwrap();
+                let fixture = match call["method"].as_str().unwrap() {
+                    "eth_getBlockByHash" => "eth_getBlockByNumber",
+                    method => method,
+                };
+
+                let body = match std::fs::read(format!("{}/{}.json", FIXTURES, fixture)) {
+                    Ok(bytes) => {
+                        let mut response: Value = serde_json::from_slice(&bytes).unwrap();
+                        response["id"] = call["id"].clone();
+                        response
+                    }
+                    Err(_) => json!({
+                        "jsonrpc": "2.0",
+                        "id": call["id"],
+                        "error": {"code": -32601, "message": "the method does not exist"}
+                    }),
+                };
+                request
+                    .respond(tiny_http::Response::from_string(body.to_string()))
+                    .unwrap();
+            }
+        });
+
+        url
+    }
+
+    #[test]
+    fn test_parse_block_id() {
+        assert_eq!("18000000".parse(), Ok(BlockId::Number(18_000_000)));
+        assert_eq!("0x112a880".parse(), Ok(BlockId::Number(18_000_000)));
+        assert_eq!("latest".parse(), Ok(BlockId::Tag("latest".to_string())));
+        assert!(matches!(
+            format!("0x{}", "aB".repeat(32)).parse(),
+            Ok(BlockId::Hash(_))
+        ));
+        assert!("0x12zz".parse::<BlockId>().is_err());
+        assert!("head".parse::<BlockId>().is_err());
+    }
+
+    #[test]
+    fn test_fetch_block() {
+        let client = RpcClient::new(&mock_node());
+
+        let block = client.fetch_block(&BlockId::Number(18_000_000)).unwrap();
+        assert_eq!(block.header.number, 18_000_000);
+        assert_eq!(
+            format!("{:?}", block.header.receipts_root),
+            "0xe0845b75f95ea931b3450c50600a24b0ace8b10b3d9853fd8cd5bdb88174cf45"
+        );
+        let recorded: Vec<Receipt> =
+            serde_json::from_slice(include_bytes!("../../receipts_small.json")).unwrap();
+        let recorded: Vec<BlockReceipt> = recorded.into_iter().map(BlockReceipt::from).collect();
+        assert_eq!(block.receipts, recorded);
+
+        let hash = format!("{:?}", block.header.hash);
+        let by_hash = client.fetch_block(&hash.parse().unwrap()).unwrap();
+        assert_eq!(by_hash.header, block.header);
+    }
+
+    #[test]
+    fn test_rpc_error() {
+        let client = RpcClient::new(&mock_node());
+        let err = client.call("eth_chainId", json!([])).unwrap_err();
+        assert!(err.to_string().contains("the method does not exist"));
+    }
+}
@@ -0,0 +1,584 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::Read;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+
+use methods::RECEIPTS_ROOT_AND_PRODUCT_TREE_ID;
+use tiny_http::{Header, Method, Request, Response};
+use trie_core::{Inputs, JournalEncoding, Outputs, Registry};
+use verifier::artifacts::{journal_path, save_journal, save_receipt, OutputsJson, SavedReceipt};
+
+use crate::backend::{shutdown_token, CancelToken};
+use crate::error::HostError;
+use crate::input::{parse_header, parse_receipts};
+use crate::prover::{
+    decode_journal, execute_env, executor_env, inputs_from_trie, prove_inputs, verify_saved,
+};
+use crate::query::count_logs;
+use crate::trie::{build_from_receipts, check_receipts_root};
+
+/**
+ * What the workers run on submitted blocks: `Prove` produces receipts, `Execute` only runs
+ * the executor and produces the journal, for testing and quick counts.
+ **/
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Backend {
+    Prove,
+    Execute,
+}
+
+/// Largest `POST /jobs` body accepted by default, larger submissions get a 413.
+pub const DEFAULT_MAX_BODY_BYTES: u64 = 256 << 20;
+/// Finished jobs kept by default, older ones are forgotten and their files removed.
+pub const DEFAULT_MAX_FINISHED_JOBS: usize = 1024;
+
+pub struct ServerConfig {
+    pub addr: String,
+    pub registry: Registry,
+    pub data_dir: PathBuf,
+    /// Jobs waiting for a worker, submissions are refused with 503 beyond it.
+    pub queue_capacity: usize,
+    pub workers: usize,
+    pub backend: Backend,
+    pub max_body_bytes: u64,
+    pub max_finished_jobs: usize,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Done,
+    Failed,
+}
+
+/**
+ * Body of `POST /jobs`: receipts in any format `build-inputs` accepts and optionally the
+ * block header, to check the receipts root against before queueing.
+ **/
+#[derive(Debug, Deserialize)]
+pub struct SubmitRequest {
+    pub receipts: Value,
+    #[serde(default)]
+    pub header: Option<Value>,
+    /// Commit the outputs ABI encoded, for EVM contracts.
+    #[serde(default)]
+    pub abi_journal: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct JobInfo {
+    pub id: u64,
+    pub status: JobStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub outputs: Option<OutputsJson>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ContractCount {
+    pub address: String,
+    pub prime: u64,
+    pub count: u8,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct QueryResult {
+    pub counts: Vec<ContractCount>,
+    pub untracked_logs: u32,
+}
+
+struct Job {
+    status: JobStatus,
+    outputs: Option<Outputs>,
+    error: Option<String>,
+}
+
+struct Shared {
+    registry: Registry,
+    data_dir: PathBuf,
+    backend: Backend,
+    jobs: Mutex<HashMap<u64, Job>>,
+    next_id: AtomicU64,
+    max_body_bytes: u64,
+    max_finished_jobs: usize,
+    /// Parent of the tokens of the jobs, cancelled by `Server::shutdown` and SIGINT.
+    shutdown: CancelToken,
+}
+
+/**
+ * HTTP front of the host library:
+ * `POST /jobs` queues a block and returns its id, `GET /jobs/{id}` its status and outputs,
+ * `GET /jobs/{id}/receipt` and `GET /jobs/{id}/journal` the proof files, and
+ * `GET /jobs/{id}/query?address=0x...` the log counts of the registered contracts.
+ **/
+pub struct Server {
+    http: tiny_http::Server,
+    queue: SyncSender<(u64, SubmitRequest)>,
+    shared: Arc<Shared>,
+}
+
+type HttpResponse = Response<std::io::Cursor<Vec<u8>>>;
+
+impl Server {
+    /**
+     * Bind the address and start the workers. Requests are served by `run`.
+     **/
+    pub fn bind(config: ServerConfig) -> Result<Self, HostError> {
+        std::fs::create_dir_all(&config.data_dir)?;
+        let http = tiny_http::Server::http(&config.addr)
+            .map_err(|err| HostError::Server(format!("binding {}: {}", config.addr, err)))?;
+
+        let shared = Arc::new(Shared {
+            registry: config.registry,
+            next_id: AtomicU64::new(first_id(&config.data_dir)?),
+            data_dir: config.data_dir,
+            backend: config.backend,
+            jobs: Mutex::new(HashMap::new()),
+            max_body_bytes: config.max_body_bytes,
+            max_finished_jobs: config.max_finished_jobs.max(1),
+            shutdown: shutdown_token().child(),
+        });
+        let (queue, jobs) = sync_channel(config.queue_capacity);
+        let jobs = Arc::new(Mutex::new(jobs));
+        for _ in 0..config.workers.max(1) {
+            let shared = shared.clone();
+            let jobs = jobs.clone();
+            std::thread::spawn(move || worker(&shared, &jobs));
+        }
+
+        Ok(Server {
+            http,
+            queue,
+            shared,
+        })
+    }
+
+    pub fn addr(&self) -> Option<SocketAddr> {
+        self.http.server_addr().to_ip()
+    }
+
+    /**
+     * Serve requests until the process exits or `shutdown` is called.
+     **/
+    pub fn run(&self) {
+        for mut request in self.http.incoming_requests() {
+            let response = self.handle(&mut request);
+            if let Err(err) = request.respond(response) {
+                tracing::warn!(%err, "failed to send response");
+            }
+        }
+    }
+
+    /**
+     * Cancel the running and queued jobs, which fail with `ProofCancelled`, and return from
+     * `run`.
+     **/
+    pub fn shutdown(&self) {
+        tracing::info!("shutting down");
+        self.shared.shutdown.cancel();
+        self.http.unblock();
+    }
+
+    fn handle(&self, request: &mut Request) -> HttpResponse {
+        let url = request.url().to_string();
+        let (path, query) = url.split_once('?').unwrap_or((url.as_str(), ""));
+        let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+
+        let id = match segments.get(1).map(|id| id.parse::<u64>()) {
+            Some(Ok(id)) => Some(id),
+            Some(Err(_)) => return error_response(400, "invalid job id"),
+            None => None,
+        };
+        let method = request.method().clone();
+        match (&method, segments.as_slice(), id) {
+            (Method::Post, ["jobs"], None) => self.submit(request),
+            (Method::Get, ["jobs", _], Some(id)) => self.status(id),
+            (Method::Get, ["jobs", _, "receipt"], Some(id)) => self.receipt(id),
+            (Method::Get, ["jobs", _, "journal"], Some(id)) => self.journal(id),
+            (Method::Get, ["jobs", _, "query"], Some(id)) => {
+                let address = query
+                    .split('&')
+                    .find_map(|param| param.strip_prefix("address="))
+                    .map(|address| percent_decode(address).ok_or(()))
+                    .transpose();
+                match address {
+                    Ok(address) => self.query(id, address.as_deref()),
+                    Err(()) => error_response(400, "invalid percent encoding in address"),
+                }
+            }
+            _ => error_response(404, "not found"),
+        }
+    }
+
+    fn submit(&self, request: &mut Request) -> HttpResponse {
+        let max_body = self.shared.max_body_bytes;
+        let too_large = format!("request body is larger than {} bytes", max_body);
+        if request
+            .body_length()
+            .is_some_and(|length| length as u64 > max_body)
+        {
+            return error_response(413, &too_large);
+        }
+        let mut body = Vec::new();
+        if let Err(err) = request
+            .as_reader()
+            .take(max_body + 1)
+            .read_to_end(&mut body)
+        {
+            return error_response(400, &err.to_string());
+        }
+        if body.len() as u64 > max_body {
+            return error_response(413, &too_large);
+        }
+        // The receipts trie is built by the worker, only the JSON is checked here.
+        let submission: SubmitRequest = match serde_json::from_slice(&body) {
+            Ok(submission) => submission,
+            Err(err) => return error_response(400, &err.to_string()),
+        };
+
+        let id = match self.shared.next_id() {
+            Ok(id) => id,
+            Err(err) => return error_response(500, &err.to_string()),
+        };
+        self.shared.jobs().insert(
+            id,
+            Job {
+                status: JobStatus::Queued,
+                outputs: None,
+                error: None,
+            },
+        );
+        match self.queue.try_send((id, submission)) {
+            Ok(()) => {
+                tracing::info!(id, "job queued");
+                json_response(202, &json!({ "id": id, "status": JobStatus::Queued }))
+            }
+            Err(err) => {
+                self.shared.jobs().remove(&id);
+                let message = match err {
+                    TrySendError::Full(_) => "job queue is full, retry later",
+                    TrySendError::Disconnected(_) => "no worker is running",
+                };
+                error_response(503, message)
+            }
+        }
+    }
+
+    fn status(&self, id: u64) -> HttpResponse {
+        match self.shared.jobs().get(&id) {
+            Some(job) => json_response(
+                200,
+                &JobInfo {
+                    id,
+                    status: job.status,
+                    outputs: job.outputs.as_ref().map(OutputsJson::from),
+                    error: job.error.clone(),
+                },
+            ),
+            None => error_response(404, "unknown job"),
+        }
+    }
+
+    fn receipt(&self, id: u64) -> HttpResponse {
+        if let Err(response) = self.done(id) {
+            return response;
+        }
+        if self.shared.backend == Backend::Execute {
+            return error_response(
+                404,
+                "the server runs the executor only, there is no receipt",
+            );
+        }
+        file_response(&self.shared.receipt_path(id), "application/octet-stream")
+    }
+
+    fn journal(&self, id: u64) -> HttpResponse {
+        if let Err(response) = self.done(id) {
+            return response;
+        }
+        file_response(
+            &journal_path(&self.shared.receipt_path(id)),
+            "application/json",
+        )
+    }
+
+    fn query(&self, id: u64, address: Option<&str>) -> HttpResponse {
+        let outputs = match self.done(id) {
+            Ok(outputs) => outputs,
+            Err(response) => return response,
+        };
+
+        match count_logs(&outputs, &self.shared.registry, address) {
+            Ok(counts) => json_response(
+                200,
+                &QueryResult {
+                    counts: counts
+                        .into_iter()
+                        .map(|(contract, count)| ContractCount {
+                            address: contract.address.clone(),
+                            prime: contract.prime,
+                            count,
+                        })
+                        .collect(),
+                    untracked_logs: outputs.untracked_logs,
+                },
+            ),
+            Err(err @ HostError::NotRegistered(_)) => error_response(404, &err.to_string()),
+            Err(err) => error_response(500, &err.to_string()),
+        }
+    }
+
+    /**
+     * Outputs of a finished job, or the response to send when it isn't.
+     **/
+    fn done(&self, id: u64) -> Result<Outputs, HttpResponse> {
+        match self.shared.jobs().get(&id) {
+            None => Err(error_response(404, "unknown job")),
+            Some(Job {
+                outputs: Some(outputs),
+                ..
+            }) => Ok(outputs.clone()),
+            Some(Job {
+                status: JobStatus::Failed,
+                error,
+                ..
+            }) => Err(error_response(
+                409,
+                &format!("job failed: {}", error.as_deref().unwrap_or_default()),
+            )),
+            Some(_) => Err(error_response(409, "job is not done yet")),
+        }
+    }
+}
+
+impl Shared {
+    fn jobs(&self) -> std::sync::MutexGuard<'_, HashMap<u64, Job>> {
+        // A worker panicking mid update leaves no broken invariant in the map.
+        self.jobs
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn receipt_path(&self, id: u64) -> PathBuf {
+        self.data_dir.join(format!("{}.receipt.bin", id))
+    }
+
+    /**
+     * Take the next job id and record it in the data directory, so a restarted server
+     * doesn't hand out the ids of jobs whose files are still there.
+     **/
+    fn next_id(&self) -> Result<u64, HostError> {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        std::fs::write(self.data_dir.join(NEXT_ID_FILE), (id + 1).to_string())?;
+        Ok(id)
+    }
+
+    /**
+     * Build the guest inputs of a submission, checking the receipts root when a header is given.
+     **/
+    fn inputs(&self, request: SubmitRequest) -> Result<Inputs, HostError> {
+        let receipts = parse_receipts(request.receipts)?;
+        let trie = build_from_receipts(&receipts)?;
+        if let Some(header) = request.header {
+            check_receipts_root(&parse_header(header)?, &trie)?;
+        }
+
+        let encoding = if request.abi_journal {
+            JournalEncoding::Abi
+        } else {
+            JournalEncoding::Risc0
+        };
+        Ok(inputs_from_trie(trie, self.registry.clone(), encoding))
+    }
+
+    /**
+     * Forget the oldest finished jobs beyond `max_finished_jobs` and remove their files.
+     **/
+    fn prune(&self) {
+        let mut jobs = self.jobs();
+        let mut finished: Vec<u64> = jobs
+            .iter()
+            .filter(|(_, job)| matches!(job.status, JobStatus::Done | JobStatus::Failed))
+            .map(|(id, _)| *id)
+            .collect();
+        if finished.len() <= self.max_finished_jobs {
+            return;
+        }
+
+        finished.sort_unstable();
+        for id in &finished[..finished.len() - self.max_finished_jobs] {
+            tracing::info!(id, "forgetting finished job");
+            jobs.remove(id);
+            let path = self.receipt_path(*id);
+            let _ = std::fs::remove_file(journal_path(&path));
+            let _ = std::fs::remove_file(path);
+        }
+    }
+
+    fn set_status(&self, id: u64, status: JobStatus) {
+        if let Some(job) = self.jobs().get_mut(&id) {
+            job.status = status;
+        }
+    }
+
+    /**
+     * Run a job with the configured backend, writing the receipt and journal files.
+     **/
+    fn run_job(
+        &self,
+        id: u64,
+        request: SubmitRequest,
+        cancel: &CancelToken,
+    ) -> Result<Outputs, HostError> {
+        if cancel.is_cancelled() {
+            return Err(HostError::ProofCancelled(format!("job {}", id)));
+        }
+        let inputs = &self.inputs(request)?;
+        let receipt_path = self.receipt_path(id);
+        match self.backend {
+            Backend::Execute => {
+                let session = execute_env(executor_env(inputs)?.0)?;
+                let outputs = decode_journal(&session.journal.bytes)?;
+                save_journal(
+                    &journal_path(&receipt_path),
+                    &session.journal.bytes,
+                    &outputs,
+                    &RECEIPTS_ROOT_AND_PRODUCT_TREE_ID,
+                )?;
+                Ok(outputs)
+            }
+            Backend::Prove => {
+                let saved = SavedReceipt {
+                    image_id: RECEIPTS_ROOT_AND_PRODUCT_TREE_ID,
+                    receipt: prove_inputs(inputs, cancel)?,
+                };
+                let outputs = verify_saved(&saved)?;
+                save_receipt(&receipt_path, &saved.receipt, &saved.image_id)?;
+                Ok(outputs)
+            }
+        }
+    }
+}
+
+fn worker(shared: &Shared, jobs: &Mutex<Receiver<(u64, SubmitRequest)>>) {
+    loop {
+        let next = jobs
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .recv();
+        let Ok((id, request)) = next else {
+            return;
+        };
+
+        shared.set_status(id, JobStatus::Running);
+        // The job's own token, cancelled with the server's.
+        let result = shared.run_job(id, request, &shared.shutdown.child());
+        tracing::info!(id, ok = result.is_ok(), "job finished");
+
+        if let Some(job) = shared.jobs().get_mut(&id) {
+            match result {
+                Ok(outputs) => {
+                    job.status = JobStatus::Done;
+                    job.outputs = Some(outputs);
+                }
+                Err(err) => {
+                    job.status = JobStatus::Failed;
+                    job.error = Some(err.to_string());
+                }
+            }
+        }
+        shared.prune();
+    }
+}
+
+const NEXT_ID_FILE: &str = "next_id";
+
+/**
+ * The first job id of a server on `data_dir`: the one recorded by the last server, or the
+ * one after the largest job file for directories written before ids were recorded.
+ **/
+fn first_id(data_dir: &Path) -> Result<u64, HostError> {
+    if let Ok(recorded) = std::fs::read_to_string(data_dir.join(NEXT_ID_FILE)) {
+        return recorded.trim().parse().map_err(|_| {
+            HostError::Server(format!("invalid {} file: {:?}", NEXT_ID_FILE, recorded))
+        });
+    }
+
+    let mut last = 0;
+    for entry in std::fs::read_dir(data_dir)? {
+        let name = entry?.file_name();
+        let id = name
+            .to_str()
+            .and_then(|name| name.split('.').next())
+            .and_then(|id| id.parse::<u64>().ok());
+        last = last.max(id.unwrap_or(0));
+    }
+    Ok(last + 1)
+}
+
+/**
+ * Decode a percent-encoded query string value, `None` on a malformed escape.
+ **/
+fn percent_decode(value: &str) -> Option<String> {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' => {
+                let hex = bytes.get(i + 1..i + 3)?;
+                if !hex.iter().all(u8::is_ascii_hexdigit) {
+                    return None;
+                }
+                let hex = std::str::from_utf8(hex).ok()?;
+                decoded.push(u8::from_str_radix(hex, 16).ok()?);
+                i += 3;
+            }
+            b'+' => {
+                decoded.push(b' ');
+                i += 1;
+            }
+            byte => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+fn content_type(value: &str) -> Header {
+    Header::from_bytes(&b"Content-Type"[..], value.as_bytes()).expect("valid header")
+}
+
+fn json_response<T: Serialize>(status: u16, body: &T) -> HttpResponse {
+    match serde_json::to_vec(body) {
+        Ok(body) => Response::from_data(body)
+            .with_status_code(status)
+            .with_header(content_type("application/json")),
+        Err(err) => error_response(500, &err.to_string()),
+    }
+}
+
+fn error_response(status: u16, message: &str) -> HttpResponse {
+    Response::from_data(json!({ "error": message }).to_string().into_bytes())
+        .with_status_code(status)
+        .with_header(content_type("application/json"))
+}
+
+fn file_response(path: &std::path::Path, mime: &str) -> HttpResponse {
+    match std::fs::read(path) {
+        Ok(bytes) => Response::from_data(bytes)
+            .with_status_code(200)
+            .with_header(content_type(mime)),
+        Err(err) => error_response(500, &err.to_string()),
+    }
+}
@@ -0,0 +1,229 @@
+use hasher::HasherKeccak;
+
+use reth_primitives::ReceiptWithBloomRef;
+use std::sync::Arc;
+
+use cita_trie::MemoryDB;
+use cita_trie::{PatriciaTrie, Trie};
+use reth_primitives::bytes::BytesMut;
+use reth_primitives::rpc_utils::rlp::RlpStream;
+use reth_rlp::Encodable;
+use trie_core::Node;
+
+use crate::error::HostError;
+use crate::input::{BlockHeader, BlockReceipt};
+
+/**
+ * The receipts trie of a block: the log addresses, the trie encoded for the guest and the
+ * receipts root computed by the trie itself, to check against the header.
+ **/
+#[derive(Debug)]
+pub struct ReceiptsTrie {
+    pub log_addresses: Vec<String>,
+    pub root: Node,
+    pub receipts_root: Vec<u8>,
+    pub receipt_count: usize,
+}
+
+pub fn build_from_receipts(receipts: &[BlockReceipt]) -> Result<ReceiptsTrie, HostError> {
+    let mem_db = Arc::new(MemoryDB::new(true));
+    let hasher = Arc::new(HasherKeccak::new());
+
+    let mut trie = PatriciaTrie::new(mem_db.clone(), hasher.clone());
+    let mut key_buf = BytesMut::new();
+    let mut value_buf = BytesMut::new();
+
+    let mut log_addresses: Vec<String> = Vec::new();
+
+    for (idx, receipt) in receipts.iter().enumerate() {
+        key_buf.clear();
+        idx.encode(&mut key_buf);
+
+        receipt.receipt.logs.iter().for_each(|log| {
+            let addr = format!("{:?}", log.address);
+            log_addresses.push(addr.to_lowercase());
+        });
+
+        value_buf.clear();
+        encode_receipt(receipt, &mut value_buf);
+        trie.insert(key_buf.to_vec(), value_buf.to_vec())?;
+    }
+
+    // Encode before committing, root() replaces the in-memory nodes with ones read from the db.
+    let root = encode_trie_rec(trie.root.clone())?;
+    let receipts_root = trie.root()?;
+
+    Ok(ReceiptsTrie {
+        log_addresses,
+        root,
+        receipts_root,
+        receipt_count: receipts.len(),
+    })
+}
+
+/**
+ * The trie value of a receipt: `rlp(receipt)` for legacy transactions and
+ * `type || rlp(receipt)` for typed ones. reth only knows types up to EIP-1559, later types
+ * are encoded as EIP-1559 receipts and given their own type byte.
+ **/
+fn encode_receipt(receipt: &BlockReceipt, out: &mut BytesMut) {
+    let start = out.len();
+    ReceiptWithBloomRef::from(&receipt.receipt).encode_inner(out, false);
+    if receipt.tx_type > 2 {
+        out[start] = receipt.tx_type;
+    }
+}
+
+fn encode_trie_rec(root: cita_trie::node::Node) -> Result<Node, HostError> {
+    match root {
+        cita_trie::node::Node::Branch(branch) => {
+            let borrow_branch = branch.borrow();
+            let mut children: [Box<Node>; 16] = Default::default();
+            let mut children_count = 0;
+            for i in 0..16 {
+                let child = borrow_branch.children[i].clone();
+
+                let child = encode_trie_rec(child)?;
+                match child {
+                    Node::Empty => {}
+                    _ => {
+                        children_count += 1;
+                    }
+                }
+                children[i] = Box::new(child);
+            }
+
+            Ok(Node::Branch {
+                children,
+                children_count,
+            })
+        }
+        cita_trie::node::Node::Leaf(leaf) => {
+            let borrow_leaf = leaf.borrow();
+
+            let mut stream = RlpStream::new_list(2);
+            stream.append(&borrow_leaf.key.encode_compact());
+            stream.append(&borrow_leaf.value);
+
+            let buf = stream.out().to_vec();
+            Ok(Node::Leaf(buf))
+        }
+        cita_trie::node::Node::Extension(extension) => {
+            let borrow_extension = extension.borrow();
+
+            Ok(Node::Extension {
+                nibbles: borrow_extension.prefix.get_data().to_vec(),
+                child: Box::new(encode_trie_rec(borrow_extension.node.clone())?),
+            })
+        }
+        cita_trie::node::Node::Empty => Ok(Node::Empty),
+        cita_trie::node::Node::Hash(_) => Err(HostError::UnsupportedTrieNode("hash")),
+    }
+}
+
+/**
+ * Check the receipts root computed from the receipts against the one in the block header,
+ * so a wrong or incomplete receipts file is refused before any proving time is spent.
+ **/
+pub fn check_receipts_root(header: &BlockHeader, trie: &ReceiptsTrie) -> Result<(), HostError> {
+    if header.receipts_root.as_bytes() != trie.receipts_root.as_slice() {
+        return Err(HostError::ReceiptsRootMismatch {
+            block: header.number,
+            expected: header.receipts_root,
+            found: format!("0x{}", hex::encode(&trie.receipts_root)),
+            receipt_count: trie.receipt_count,
+        });
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::{load_header, load_receipts};
+    use trie_core::chunk::{chunk_outputs, combine, node_hash, split_inputs};
+    use trie_core::{Inputs, JournalEncoding, Registry};
+    use std::path::Path;
+
+    const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/rpc");
+
+    #[test]
+    fn test_check_receipts_root() {
+        let header = load_header(&Path::new(FIXTURES).join("eth_getBlockByNumber.json")).unwrap();
+        let mut receipts =
+            load_receipts(&Path::new(FIXTURES).join("eth_getBlockReceipts.json")).unwrap();
+
+        let trie = build_from_receipts(&receipts).unwrap();
+        assert!(check_receipts_root(&header, &trie).is_ok());
+
+        receipts.pop();
+        let trie = build_from_receipts(&receipts).unwrap();
+        let err = check_receipts_root(&header, &trie).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "receipts root mismatch for block 18000000: header has {:?}, 2 receipts give 0x{}",
+                header.receipts_root,
+                hex::encode(&trie.receipts_root)
+            )
+        );
+    }
+
+    #[test]
+    fn test_extension_nodes() {
+        let receipts =
+            load_receipts(&Path::new(FIXTURES).join("eth_getBlockReceipts.json")).unwrap();
+        let receipts: Vec<_> = receipts.iter().cycle().take(300).cloned().collect();
+
+        // Past 129 receipts the keys share nibbles, the guest hashes the extension nodes too.
+        let trie = build_from_receipts(&receipts).unwrap();
+        assert!(matches!(
+            &trie.root,
+            Node::Branch { children, .. } if matches!(
+                &*children[8],
+                Node::Branch { children, .. } if matches!(*children[2], Node::Extension { .. })
+            )
+        ));
+        assert_eq!(node_hash(&trie.root).unwrap().to_vec(), trie.receipts_root);
+
+        let inputs = Inputs {
+            root: trie.root,
+            log_addresses: trie.log_addresses,
+            registry: Registry::default(),
+            journal_encoding: JournalEncoding::Risc0,
+        };
+        let (top, chunks) = split_inputs(&inputs, 8);
+        let outputs: Vec<_> = chunks.iter().map(|c| chunk_outputs(c).unwrap()).collect();
+        assert_eq!(
+            combine(&top, &outputs).unwrap().root.to_vec(),
+            trie.receipts_root
+        );
+    }
+
+    #[test]
+    fn test_encode_typed_receipt() {
+        let receipts =
+            load_receipts(&Path::new(FIXTURES).join("eth_getBlockReceipts.json")).unwrap();
+        let mut receipt = receipts
+            .into_iter()
+            .find(|receipt| receipt.tx_type == 2)
+            .unwrap();
+
+        let mut eip1559 = BytesMut::new();
+        encode_receipt(&receipt, &mut eip1559);
+        assert_eq!(eip1559[0], 2);
+
+        receipt.tx_type = 3;
+        let mut blob = BytesMut::new();
+        encode_receipt(&receipt, &mut blob);
+        assert_eq!(blob[0], 3);
+        assert_eq!(blob[1..], eip1559[1..]);
+
+        receipt.tx_type = 0;
+        receipt.receipt.tx_type = reth_primitives::TxType::Legacy;
+        let mut legacy = BytesMut::new();
+        encode_receipt(&receipt, &mut legacy);
+        assert_eq!(legacy[..], eip1559[1..]);
+    }
+}
@@ -0,0 +1,279 @@
+use risc0_zkvm::{FakeReceipt, InnerReceipt, Receipt, ReceiptClaim};
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tiny_http::{Header, Method, Response, Server};
+
+use host::backend::{BonsaiBackend, BonsaiConfig, CancelToken, ProofRequest, ProverBackend};
+use host::error::HostError;
+
+const API_KEY: &str = "test-key";
+const IMAGE_ID: [u32; 8] = [7; 8];
+const ELF: &[u8] = b"not really an elf";
+const INPUT: &[u32] = &[1, 2, 0xdeadbeef];
+
+/**
+ * How the mock sessions end.
+ **/
+#[derive(Clone, Copy)]
+enum Session {
+    /// Runs for two polls then succeeds.
+    Succeeds,
+    /// Never finishes.
+    Runs,
+    Fails(&'static str),
+    /// Isn't created, the creation request gets a 503.
+    Unavailable,
+}
+
+/**
+ * What the mock received, by route.
+ **/
+#[derive(Default)]
+struct Received {
+    uploads: Vec<(String, Vec<u8>)>,
+    session: Option<Value>,
+    input_requests: usize,
+    creates: usize,
+    stopped: bool,
+}
+
+/**
+ * A Bonsai API answering like the real one, with presigned upload URLs pointing back at
+ * itself. The first input upload request fails with a 503 to exercise retries.
+ **/
+fn start(session: Session) -> (String, Arc<Mutex<Received>>) {
+    let server = Server::http("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", server.server_addr().to_ip().unwrap());
+    let received = Arc::new(Mutex::new(Received::default()));
+
+    let base = url.clone();
+    let state = received.clone();
+    let polls = AtomicUsize::new(0);
+    thread::spawn(move || {
+        for mut request in server.incoming_requests() {
+            let authorized = request
+                .headers()
+                .iter()
+                .any(|header| header.field.equiv("x-api-key") && header.value == API_KEY);
+            if !authorized {
+                request.respond(Response::empty(401)).unwrap();
+                continue;
+            }
+
+            let mut body = Vec::new();
+            request.as_reader().read_to_end(&mut body).unwrap();
+            let mut received = state.lock().unwrap();
+            let path = request.url().to_string();
+            let reply = |value: Value| {
+                Response::from_string(value.to_string())
+                    .with_header(Header::from_bytes("Content-Type", "application/json").unwrap())
+            };
+
+            let response = match (request.method(), path.as_str()) {
+                (Method::Get, route) if route.starts_with("/images/upload/") => reply(json!({
+                    "url": format!("{}/put/image", base),
+                })),
+                (Method::Get, "/inputs/upload") => {
+                    received.input_requests += 1;
+                    if received.input_requests == 1 {
+                        reply(json!({})).with_status_code(503)
+                    } else {
+                        reply(json!({
+                            "url": format!("{}/put/input", base),
+                            "uuid": "input-1",
+                        }))
+                    }
+                }
+                (Method::Get, "/receipts/upload") => reply(json!({
+                    "url": format!("{}/put/receipt", base),
+                    "uuid": "receipt-1",
+                })),
+                (Method::Put, route) => {
+                    received.uploads.push((route.to_string(), body));
+                    reply(json!({}))
+                }
+                (Method::Post, "/sessions/create") => {
+                    received.creates += 1;
+                    if matches!(session, Session::Unavailable) {
+                        reply(json!({})).with_status_code(503)
+                    } else {
+                        received.session = Some(serde_json::from_slice(&body).unwrap());
+                        reply(json!({ "uuid": "session-1" }))
+                    }
+                }
+                (Method::Get, "/sessions/status/session-1") => {
+                    let poll = polls.fetch_add(1, Ordering::SeqCst);
+                    match session {
+                        Session::Succeeds if poll >= 2 => reply(json!({
+                            "status": "SUCCEEDED",
+                            "receipt_url": format!("{}/receipt", base),
+                        })),
+                        Session::Fails(error) => reply(json!({
+                            "status": "FAILED",
+                            "error_msg": error,
+                        })),
+                        _ => reply(json!({ "status": "RUNNING" })),
+                    }
+                }
+                (Method::Get, "/sessions/stop/session-1") => {
+                    received.stopped = true;
+                    reply(json!({}))
+                }
+                (Method::Get, "/receipt") => Response::from_data(receipt()),
+                _ => reply(json!({})).with_status_code(404),
+            };
+            drop(received);
+            request.respond(response).unwrap();
+        }
+    });
+
+    (url, received)
+}
+
+fn journal() -> Vec<u8> {
+    vec![0x11, 0x22, 0x33, 0x44]
+}
+
+fn receipt() -> Vec<u8> {
+    let claim = ReceiptClaim::ok(IMAGE_ID, journal());
+    let receipt = Receipt::new(InnerReceipt::Fake(FakeReceipt::new(claim)), journal());
+    bincode::serialize(&receipt).unwrap()
+}
+
+fn backend(url: &str, proof_timeout_secs: u64) -> BonsaiBackend {
+    BonsaiBackend::new(&BonsaiConfig {
+        url: Some(url.to_string()),
+        api_key: Some(API_KEY.to_string()),
+        proof_timeout_secs,
+        poll_interval_ms: 10,
+        retry_delay_ms: 10,
+        ..BonsaiConfig::default()
+    })
+    .unwrap()
+}
+
+fn request(cancel: &CancelToken) -> ProofRequest<'_> {
+    ProofRequest {
+        elf: ELF,
+        image_id: IMAGE_ID,
+        input: INPUT,
+        assumptions: &[],
+        succinct: false,
+        cancel,
+    }
+}
+
+#[test]
+fn test_bonsai_prove() {
+    let (url, received) = start(Session::Succeeds);
+
+    let receipt = backend(&url, 60)
+        .prove(&request(&CancelToken::new()))
+        .unwrap();
+    assert_eq!(receipt.journal.bytes, journal());
+
+    let received = received.lock().unwrap();
+    let input: Vec<u8> = INPUT.iter().flat_map(|word| word.to_le_bytes()).collect();
+    assert_eq!(
+        received.uploads,
+        vec![
+            ("/put/image".to_string(), ELF.to_vec()),
+            ("/put/input".to_string(), input),
+        ]
+    );
+    // The 503 was retried.
+    assert_eq!(received.input_requests, 2);
+    assert_eq!(received.creates, 1);
+    let session = received.session.as_ref().unwrap();
+    assert_eq!(session["img"], json!("07000000".repeat(8)));
+    assert_eq!(session["input"], json!("input-1"));
+    assert_eq!(session["exec_cycle_limit"], json!(1u64 << 32));
+    assert!(!received.stopped);
+}
+
+#[test]
+fn test_bonsai_cancel() {
+    let (url, received) = start(Session::Runs);
+    let cancel = CancelToken::new();
+    let canceller = cancel.clone();
+    thread::spawn(move || {
+        thread::sleep(Duration::from_millis(200));
+        canceller.cancel();
+    });
+    assert!(matches!(
+        backend(&url, 60).prove(&request(&cancel)),
+        Err(HostError::ProofCancelled(session)) if session == "session-1"
+    ));
+    assert!(received.lock().unwrap().stopped);
+
+    // Cancelling a parent cancels its children, and nothing is uploaded for them.
+    let (url, received) = start(Session::Succeeds);
+    let parent = CancelToken::new();
+    let child = parent.child();
+    parent.cancel();
+    assert!(matches!(
+        backend(&url, 60).prove(&request(&child)),
+        Err(HostError::ProofCancelled(_))
+    ));
+    let received = received.lock().unwrap();
+    assert!(received.uploads.is_empty());
+    assert_eq!(received.creates, 0);
+}
+
+#[test]
+fn test_bonsai_create_not_retried() {
+    let (url, received) = start(Session::Unavailable);
+
+    // The session may have been started, another request could start a second one.
+    assert!(matches!(
+        backend(&url, 60).prove(&request(&CancelToken::new())),
+        Err(HostError::Http(_))
+    ));
+    assert_eq!(received.lock().unwrap().creates, 1);
+}
+
+#[test]
+fn test_bonsai_timeout() {
+    let (url, received) = start(Session::Runs);
+
+    assert!(matches!(
+        backend(&url, 0).prove(&request(&CancelToken::new())),
+        Err(HostError::ProofTimeout { .. })
+    ));
+    assert!(received.lock().unwrap().stopped);
+}
+
+#[test]
+fn test_bonsai_session_failed() {
+    let (url, _) = start(Session::Fails("Session limit exceeded: 100 >= 100"));
+    assert!(matches!(
+        backend(&url, 60).prove(&request(&CancelToken::new())),
+        Err(HostError::SessionLimitExceeded(_))
+    ));
+
+    let (url, _) = start(Session::Fails("guest panicked"));
+    match backend(&url, 60).prove(&request(&CancelToken::new())) {
+        Err(HostError::Bonsai(err)) => assert!(err.contains("guest panicked")),
+        other => panic!("expected a bonsai error, got {:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn test_bonsai_unauthorized() {
+    let (url, _) = start(Session::Succeeds);
+    let backend = BonsaiBackend::new(&BonsaiConfig {
+        url: Some(url),
+        api_key: Some("wrong".to_string()),
+        ..BonsaiConfig::default()
+    })
+    .unwrap();
+
+    // Client errors aren't retried.
+    assert!(matches!(
+        backend.prove(&request(&CancelToken::new())),
+        Err(HostError::Http(_))
+    ));
+}
@@ -0,0 +1,76 @@
+use risc0_zkvm::serde::to_vec;
+use risc0_zkvm::{FakeReceipt, InnerReceipt, Receipt, ReceiptClaim};
+use std::fs::File;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use host::cache::ProofCache;
+use methods::RECEIPTS_ROOT_AND_PRODUCT_TREE_ID;
+use trie_core::Outputs;
+use verifier::artifacts::journal_path;
+
+/**
+ * A fake receipt of the block guest, which verifies in dev mode.
+ **/
+fn fake_receipt() -> Receipt {
+    let outputs = Outputs {
+        root: [1; 32],
+        product_tree_hash: [2; 32],
+        product_tree_root: 6u128.to_be_bytes(),
+        registry_hash: [3; 32],
+        untracked_logs: 0,
+    };
+    let journal: Vec<u8> = to_vec(&outputs)
+        .unwrap()
+        .iter()
+        .flat_map(|word| word.to_le_bytes())
+        .collect();
+    let claim = ReceiptClaim::ok(RECEIPTS_ROOT_AND_PRODUCT_TREE_ID, journal.clone());
+    Receipt::new(InnerReceipt::Fake(FakeReceipt::new(claim)), journal)
+}
+
+fn entry_size(path: &Path) -> u64 {
+    std::fs::metadata(path).unwrap().len() + std::fs::metadata(journal_path(path)).unwrap().len()
+}
+
+fn set_age(path: &Path, age_secs: u64) {
+    File::options()
+        .write(true)
+        .open(path)
+        .unwrap()
+        .set_modified(SystemTime::now() - Duration::from_secs(age_secs))
+        .unwrap();
+}
+
+/**
+ * The only test of this binary: fake receipts need `RISC0_DEV_MODE`, which is set for the
+ * whole process, as with `--dev-mode`.
+ **/
+#[test]
+fn test_evict_least_recently_used() {
+    std::env::set_var("RISC0_DEV_MODE", "1");
+    let dir = std::env::temp_dir().join(format!("proof-cache-lru-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let receipt = fake_receipt();
+
+    // Room for two entries.
+    let sizing = ProofCache::new(&dir.join("sizing"), u64::MAX).unwrap();
+    sizing.put("sizing", &receipt).unwrap();
+    let size = entry_size(&sizing.receipt_path("sizing"));
+    let cache = ProofCache::new(&dir.join("cache"), 2 * size + size / 2).unwrap();
+
+    cache.put("old", &receipt).unwrap();
+    set_age(&cache.receipt_path("old"), 30);
+    cache.put("recent", &receipt).unwrap();
+    set_age(&cache.receipt_path("recent"), 20);
+
+    // Reading the oldest entry makes it the most recently used.
+    assert!(cache.get("old").is_some());
+    cache.put("new", &receipt).unwrap();
+
+    assert!(cache.receipt_path("old").exists());
+    assert!(!cache.receipt_path("recent").exists());
+    assert!(cache.receipt_path("new").exists());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
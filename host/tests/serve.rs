@@ -0,0 +1,232 @@
+use serde_json::{json, Value};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use host::server::{
+    Backend, JobInfo, JobStatus, QueryResult, Server, ServerConfig, DEFAULT_MAX_BODY_BYTES,
+    DEFAULT_MAX_FINISHED_JOBS,
+};
+use trie_core::Registry;
+
+const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/rpc");
+
+fn fixture(name: &str) -> Value {
+    let bytes = std::fs::read(format!("{}/{}.json", FIXTURES, name)).unwrap();
+    serde_json::from_slice(&bytes).unwrap()
+}
+
+/**
+ * Config of a server running the executor only on a free port.
+ **/
+fn config(name: &str) -> ServerConfig {
+    let registry = Registry::new(vec![
+        ("0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2".to_string(), 2),
+        ("0xd6a347c998109ac87ccb323f78960c91bbe911cd".to_string(), 3),
+        ("0x3feb4fea5132695542f8ede5076ac43296d17c6d".to_string(), 5),
+    ])
+    .unwrap();
+    let data_dir = std::env::temp_dir().join(format!("serve-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&data_dir);
+
+    ServerConfig {
+        addr: "127.0.0.1:0".to_string(),
+        registry,
+        data_dir,
+        queue_capacity: 4,
+        workers: 1,
+        backend: Backend::Execute,
+        max_body_bytes: DEFAULT_MAX_BODY_BYTES,
+        max_finished_jobs: DEFAULT_MAX_FINISHED_JOBS,
+    }
+}
+
+/**
+ * Start a server and return its URL.
+ **/
+fn serve(config: ServerConfig) -> String {
+    let server = Server::bind(config).unwrap();
+    let url = format!("http://{}", server.addr().unwrap());
+    thread::spawn(move || server.run());
+
+    url
+}
+
+fn start(name: &str) -> String {
+    serve(config(name))
+}
+
+/**
+ * Status code and JSON body of a response, error statuses included.
+ **/
+fn send(result: Result<ureq::Response, ureq::Error>) -> (u16, Value) {
+    let response = match result {
+        Ok(response) => response,
+        Err(ureq::Error::Status(_, response)) => response,
+        Err(err) => panic!("request failed: {}", err),
+    };
+    (response.status(), response.into_json().unwrap())
+}
+
+fn wait_done(url: &str, id: u64) -> JobInfo {
+    let start = Instant::now();
+    loop {
+        let (status, body) = send(ureq::get(&format!("{}/jobs/{}", url, id)).call());
+        assert_eq!(status, 200);
+        let job: JobInfo = serde_json::from_value(body).unwrap();
+        if job.status == JobStatus::Done || job.status == JobStatus::Failed {
+            return job;
+        }
+        assert!(start.elapsed() < Duration::from_secs(300), "job timed out");
+        thread::sleep(Duration::from_millis(200));
+    }
+}
+
+#[test]
+fn test_submit_and_query() {
+    let url = start("query");
+    let header = fixture("eth_getBlockByNumber");
+
+    let (status, body) = send(ureq::post(&format!("{}/jobs", url)).send_json(json!({
+        "receipts": fixture("eth_getBlockReceipts"),
+        "header": header,
+    })));
+    assert_eq!(status, 202);
+    let id = body["id"].as_u64().unwrap();
+
+    let job = wait_done(&url, id);
+    assert_eq!(job.status, JobStatus::Done, "{:?}", job.error);
+    let outputs = job.outputs.unwrap();
+    assert_eq!(
+        outputs.root,
+        header["result"]["receiptsRoot"].as_str().unwrap()
+    );
+    assert_eq!(outputs.product_tree_root, (64 * 81 * 25).to_string());
+    assert_eq!(outputs.untracked_logs, 3);
+
+    let (status, body) = send(ureq::get(&format!("{}/jobs/{}/query", url, id)).call());
+    assert_eq!(status, 200);
+    let result: QueryResult = serde_json::from_value(body).unwrap();
+    let counts: Vec<u8> = result.counts.iter().map(|count| count.count).collect();
+    // Registry order: 0x3feb.. (5), 0xc02a.. (2), 0xd6a3.. (3).
+    assert_eq!(counts, vec![2, 6, 4]);
+    assert_eq!(result.untracked_logs, 3);
+
+    let (status, body) = send(
+        ureq::get(&format!("{}/jobs/{}/query", url, id))
+            .query("address", "0xd6a347c998109ac87ccb323f78960c91bbe911cd")
+            .call(),
+    );
+    assert_eq!(status, 200);
+    assert_eq!(body["counts"][0]["count"], 4);
+
+    // Query values are percent-decoded.
+    let (status, body) = send(
+        ureq::get(&format!(
+            "{}/jobs/{}/query?address=%30xd6a347c998109ac87ccb323f78960c91bbe911cd",
+            url, id
+        ))
+        .call(),
+    );
+    assert_eq!(status, 200);
+    assert_eq!(body["counts"][0]["count"], 4);
+    let (status, _) = send(ureq::get(&format!("{}/jobs/{}/query?address=%zz", url, id)).call());
+    assert_eq!(status, 400);
+
+    let (status, body) = send(ureq::get(&format!("{}/jobs/{}/journal", url, id)).call());
+    assert_eq!(status, 200);
+    assert_eq!(body["outputs"]["root"], outputs.root);
+
+    // The executor backend produces no receipt.
+    let (status, _) = send(ureq::get(&format!("{}/jobs/{}/receipt", url, id)).call());
+    assert_eq!(status, 404);
+}
+
+/**
+ * Submit receipts missing their last one, which fail the receipts root check.
+ **/
+fn submit_mismatch(url: &str) -> u64 {
+    let mut receipts = fixture("eth_getBlockReceipts");
+    receipts["result"].as_array_mut().unwrap().pop();
+    let (status, body) = send(ureq::post(&format!("{}/jobs", url)).send_json(json!({
+        "receipts": receipts,
+        "header": fixture("eth_getBlockByNumber"),
+    })));
+    assert_eq!(status, 202);
+    body["id"].as_u64().unwrap()
+}
+
+#[test]
+fn test_rejected_submissions() {
+    let url = start("rejected");
+
+    // The receipts root is checked by the worker, the job fails.
+    let job = wait_done(&url, submit_mismatch(&url));
+    assert_eq!(job.status, JobStatus::Failed);
+    assert!(job.error.unwrap().starts_with("receipts root mismatch"));
+
+    let (status, _) = send(ureq::post(&format!("{}/jobs", url)).send_string("not json"));
+    assert_eq!(status, 400);
+
+    let mut small = config("rejected-small");
+    small.max_body_bytes = 1024;
+    let small = serve(small);
+    let (status, body) = send(ureq::post(&format!("{}/jobs", small)).send_json(json!({
+        "receipts": fixture("eth_getBlockReceipts"),
+    })));
+    assert_eq!(status, 413);
+    assert!(body["error"]
+        .as_str()
+        .unwrap()
+        .contains("larger than 1024 bytes"));
+    // Without a Content-Length, the body is read up to the limit.
+    let chunked = std::io::Cursor::new(vec![b' '; 4096]);
+    let (status, _) = send(ureq::post(&format!("{}/jobs", small)).send(chunked));
+    assert_eq!(status, 413);
+
+    let (status, _) = send(ureq::get(&format!("{}/jobs/404", url)).call());
+    assert_eq!(status, 404);
+}
+
+#[test]
+fn test_finished_jobs_are_pruned() {
+    let mut config = config("pruned");
+    config.max_finished_jobs = 1;
+    let url = serve(config);
+
+    let first = submit_mismatch(&url);
+    wait_done(&url, first);
+    let second = submit_mismatch(&url);
+    wait_done(&url, second);
+
+    let (status, _) = send(ureq::get(&format!("{}/jobs/{}", url, first)).call());
+    assert_eq!(status, 404);
+}
+
+#[test]
+fn test_ids_continue_after_restart() {
+    let before = config("restart");
+    let data_dir = before.data_dir.clone();
+    let url = serve(before);
+    let first = submit_mismatch(&url);
+    wait_done(&url, first);
+
+    // A new server on the same directory doesn't hand out the ids again.
+    let mut after = config("restarted");
+    after.data_dir = data_dir;
+    let url = serve(after);
+    assert!(submit_mismatch(&url) > first);
+}
+
+#[test]
+fn test_shutdown() {
+    let server = std::sync::Arc::new(Server::bind(config("shutdown")).unwrap());
+    let (done, stopped) = std::sync::mpsc::channel();
+    let running = server.clone();
+    thread::spawn(move || {
+        running.run();
+        done.send(()).unwrap();
+    });
+
+    server.shutdown();
+    stopped.recv_timeout(Duration::from_secs(10)).unwrap();
+}
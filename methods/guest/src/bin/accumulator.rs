@@ -0,0 +1,55 @@
+#![no_main]
+use risc0_zkvm::guest::env;
+use risc0_zkvm::serde::from_slice;
+
+risc0_zkvm::guest::entry!(main);
+
+use trie_core::accumulator::{extend, AccumulatorInputs, ProvenAccumulatorInputs};
+use trie_core::journal::ABI_JOURNAL_LEN;
+use trie_core::range::BlockSummary;
+use trie_core::Outputs;
+
+/**
+ * Decode the outputs committed by the per-block guest, in either journal encoding.
+ **/
+fn decode_outputs(journal: &[u8]) -> Outputs {
+    if journal.len() == ABI_JOURNAL_LEN {
+        return Outputs::abi_decode(journal).expect("invalid ABI encoded journal");
+    }
+
+    from_slice(journal).expect("invalid journal")
+}
+
+/**
+ * Extend the accumulator state of a previous step with the outputs of a per-block proof.
+ * Both receipts are verified with `env::verify`, the block's against `block_image_id` and
+ * the previous step's against `accumulator_image_id`, and the prover resolves them with the
+ * receipts the host adds to the environment. The IDs are committed for the verifier to check.
+ **/
+pub fn main() {
+    let inputs: ProvenAccumulatorInputs = env::read();
+
+    env::verify(inputs.block_image_id, inputs.block.journal.as_slice()).unwrap();
+    let previous = inputs.previous.map(|journal| {
+        env::verify(inputs.accumulator_image_id, journal.as_slice()).unwrap();
+        from_slice(&journal).expect("invalid accumulator journal")
+    });
+
+    let outputs = match extend(&AccumulatorInputs {
+        block_image_id: inputs.block_image_id,
+        accumulator_image_id: inputs.accumulator_image_id,
+        previous,
+        block: BlockSummary {
+            outputs: decode_outputs(&inputs.block.journal),
+            header: inputs.block.header,
+        },
+        registry: inputs.registry,
+    }) {
+        Ok(outputs) => outputs,
+        Err(err) => panic!("{}", err),
+    };
+
+    println!("outputs: {:?}", outputs);
+
+    env::commit(&outputs);
+}
@@ -0,0 +1,21 @@
+#![no_main]
+use risc0_zkvm::guest::env;
+
+risc0_zkvm::guest::entry!(main);
+
+use trie_core::chunk::{chunk_outputs, ChunkInputs};
+
+/**
+ * Hash some subtries of a block's receipts trie and build the product subtree over a slice
+ * of its logs, for blocks too large to prove in one execution.
+ **/
+pub fn main() {
+    let inputs: ChunkInputs = env::read();
+
+    let outputs = match chunk_outputs(&inputs) {
+        Ok(outputs) => outputs,
+        Err(err) => panic!("{}", err),
+    };
+
+    env::commit(&outputs);
+}
@@ -0,0 +1,34 @@
+#![no_main]
+use risc0_zkvm::guest::env;
+use risc0_zkvm::serde::from_slice;
+
+risc0_zkvm::guest::entry!(main);
+
+use trie_core::chunk::{combine, ChunkOutputs, ChunkedOutputs, CombineInputs};
+
+/**
+ * Verify the chunk proofs of a block and combine their outputs into the receipts root and
+ * product tree of the whole block, the outputs a single proof of the block commits.
+ **/
+pub fn main() {
+    let inputs: CombineInputs = env::read();
+
+    let mut chunks = Vec::with_capacity(inputs.chunks.len());
+    for journal in &inputs.chunks {
+        env::verify(inputs.chunk_image_id, journal.as_slice()).unwrap();
+        let chunk: ChunkOutputs = from_slice(journal).expect("invalid chunk journal");
+        chunks.push(chunk);
+    }
+
+    let outputs = match combine(&inputs.top, &chunks) {
+        Ok(outputs) => outputs,
+        Err(err) => panic!("{}", err),
+    };
+
+    println!("outputs: {:?}", outputs);
+
+    env::commit(&ChunkedOutputs {
+        outputs,
+        chunk_image_id: inputs.chunk_image_id,
+    });
+}
@@ -0,0 +1,23 @@
+#![no_main]
+use risc0_zkvm::guest::env;
+
+risc0_zkvm::guest::entry!(main);
+
+use trie_core::range::{aggregate, RangeInputs};
+
+/**
+ * Aggregate the outputs of consecutive per-block proofs into counts over the range.
+ * The per-block proofs are verified by the host before the outputs are passed in.
+ **/
+pub fn main() {
+    let inputs: RangeInputs = env::read();
+
+    let outputs = match aggregate(&inputs) {
+        Ok(outputs) => outputs,
+        Err(err) => panic!("{}", err),
+    };
+
+    println!("outputs: {:?}", outputs);
+
+    env::commit(&outputs);
+}
@@ -0,0 +1,55 @@
+#![no_main]
+use risc0_zkvm::guest::env;
+use risc0_zkvm::serde::from_slice;
+
+risc0_zkvm::guest::entry!(main);
+
+use trie_core::journal::ABI_JOURNAL_LEN;
+use trie_core::range::{
+    aggregate, BlockSummary, ComposedRangeInputs, ComposedRangeOutputs, RangeInputs,
+};
+use trie_core::Outputs;
+
+/**
+ * Decode the outputs committed by the per-block guest, in either journal encoding.
+ **/
+fn decode_outputs(journal: &[u8]) -> Outputs {
+    if journal.len() == ABI_JOURNAL_LEN {
+        return Outputs::abi_decode(journal).expect("invalid ABI encoded journal");
+    }
+
+    from_slice(journal).expect("invalid journal")
+}
+
+/**
+ * Verify the per-block proofs of consecutive blocks and aggregate their outputs into counts
+ * over the range. Each `env::verify` adds the block's receipt as an assumption, which the
+ * prover resolves with the receipt the host adds to the environment.
+ **/
+pub fn main() {
+    let inputs: ComposedRangeInputs = env::read();
+
+    let mut blocks = Vec::with_capacity(inputs.blocks.len());
+    for block in inputs.blocks {
+        env::verify(inputs.block_image_id, block.journal.as_slice()).unwrap();
+        blocks.push(BlockSummary {
+            outputs: decode_outputs(&block.journal),
+            header: block.header,
+        });
+    }
+
+    let range = match aggregate(&RangeInputs {
+        blocks,
+        registry: inputs.registry,
+    }) {
+        Ok(range) => range,
+        Err(err) => panic!("{}", err),
+    };
+
+    println!("outputs: {:?}", range);
+
+    env::commit(&ComposedRangeOutputs {
+        block_image_id: inputs.block_image_id,
+        range,
+    });
+}
@@ -0,0 +1,254 @@
+use risc0_zkvm::serde::{from_slice, to_vec};
+use risc0_zkvm::Receipt;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use trie_core::chunk::ChunkedOutputs;
+use trie_core::journal::ABI_JOURNAL_LEN;
+use trie_core::Outputs;
+
+const RECEIPT_MAGIC: &[u8; 4] = b"R0PT";
+const RECEIPT_VERSION: u32 = 2;
+const JOURNAL_VERSION: u32 = 1;
+
+/**
+ * A receipt loaded from disk with the image ID it was saved for.
+ **/
+pub struct SavedReceipt {
+    pub image_id: [u32; 8],
+    pub receipt: Receipt,
+}
+
+/**
+ * Human readable view of `Outputs`, hashes as 0x prefixed hex and the product tree root
+ * as a decimal string since JSON numbers can't hold a u128.
+ **/
+#[derive(Debug, Deserialize, Serialize)]
+pub struct OutputsJson {
+    pub root: String,
+    pub product_tree_hash: String,
+    pub product_tree_root: String,
+    pub registry_hash: String,
+    pub untracked_logs: u32,
+}
+
+/**
+ * Journal file written next to the receipt: the raw journal, its decoded outputs and the
+ * image ID, readable without risc0.
+ **/
+#[derive(Debug, Deserialize, Serialize)]
+pub struct JournalFile {
+    pub version: u32,
+    pub image_id: String,
+    pub journal: String,
+    pub outputs: OutputsJson,
+}
+
+impl From<&Outputs> for OutputsJson {
+    fn from(outputs: &Outputs) -> Self {
+        OutputsJson {
+            root: to_hex(&outputs.root),
+            product_tree_hash: to_hex(&outputs.product_tree_hash),
+            product_tree_root: u128::from_be_bytes(outputs.product_tree_root).to_string(),
+            registry_hash: to_hex(&outputs.registry_hash),
+            untracked_logs: outputs.untracked_logs,
+        }
+    }
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    format!("0x{}", hex::encode(bytes))
+}
+
+/**
+ * Image IDs are hex encoded as the little endian bytes of their words, like risc0 digests.
+ **/
+pub fn image_id_hex(image_id: &[u32; 8]) -> String {
+    let bytes: Vec<u8> = image_id
+        .iter()
+        .flat_map(|word| word.to_le_bytes())
+        .collect();
+    to_hex(&bytes)
+}
+
+pub fn parse_image_id(hex_id: &str) -> Result<[u32; 8], Box<dyn Error>> {
+    let bytes = parse_hex::<32>(hex_id)?;
+
+    let mut image_id = [0u32; 8];
+    for (word, chunk) in image_id.iter_mut().zip(bytes.chunks(4)) {
+        *word = u32::from_le_bytes(chunk.try_into()?);
+    }
+    Ok(image_id)
+}
+
+pub fn parse_hex<const N: usize>(value: &str) -> Result<[u8; N], Box<dyn Error>> {
+    let bytes = hex::decode(value.trim_start_matches("0x"))?;
+    if bytes.len() != N {
+        return Err(format!("expected {} bytes, found {} in {}", N, bytes.len(), value).into());
+    }
+
+    let mut out = [0u8; N];
+    out.copy_from_slice(&bytes);
+    Ok(out)
+}
+
+/**
+ * Decode the outputs from a journal in either encoding the guest commits, told apart by length.
+ * The journal must hold the outputs only, a journal that continues after them, like that of
+ * the combine guest, is refused.
+ **/
+pub fn decode_outputs(journal: &[u8]) -> Result<Outputs, Box<dyn Error>> {
+    if journal.len() == ABI_JOURNAL_LEN {
+        return Outputs::abi_decode(journal).ok_or_else(|| "invalid ABI encoded journal".into());
+    }
+
+    decode_exact(journal)
+}
+
+/**
+ * Decode the journal of the combine guest, the outputs followed by the chunk image ID.
+ **/
+pub fn decode_chunked(journal: &[u8]) -> Result<ChunkedOutputs, Box<dyn Error>> {
+    decode_exact(journal)
+}
+
+/**
+ * Decode a risc0 serde journal, refusing words past the value: `from_slice` ignores them.
+ **/
+fn decode_exact<T: serde::de::DeserializeOwned + Serialize>(
+    journal: &[u8],
+) -> Result<T, Box<dyn Error>> {
+    let value: T = from_slice(journal)?;
+    let len = to_vec(&value)?.len() * 4;
+    if journal.len() != len {
+        return Err(format!(
+            "journal has {} bytes after its {} bytes of outputs",
+            journal.len().saturating_sub(len),
+            len
+        )
+        .into());
+    }
+
+    Ok(value)
+}
+
+/**
+ * Outputs of a journal file: plain outputs, or those committed by the combine guest. Journal
+ * files aren't proofs, checking the chunk image ID is left to the verifier of the receipt.
+ **/
+fn journal_outputs(journal: &[u8]) -> Result<Outputs, Box<dyn Error>> {
+    decode_outputs(journal).or_else(|err| match decode_chunked(journal) {
+        Ok(chunked) => Ok(chunked.outputs),
+        Err(_) => Err(err),
+    })
+}
+
+/**
+ * Path of the journal file matching a receipt path, `receipt.bin` -> `receipt.journal.json`.
+ **/
+pub fn journal_path(receipt_path: &Path) -> PathBuf {
+    receipt_path.with_extension("journal.json")
+}
+
+/**
+ * Write the receipt file and the journal file of its `Outputs` next to it.
+ **/
+pub fn save_receipt(
+    path: &Path,
+    receipt: &Receipt,
+    image_id: &[u32; 8],
+) -> Result<(), Box<dyn Error>> {
+    write_receipt(path, receipt, image_id)?;
+
+    let outputs = journal_outputs(&receipt.journal.bytes)?;
+    save_journal(
+        &journal_path(path),
+        &receipt.journal.bytes,
+        &outputs,
+        image_id,
+    )
+}
+
+/**
+ * Receipt file layout: `magic | version (u32 LE) | image ID (8 u32 LE) | bincode receipt`.
+ * The version is bumped whenever the layout or the risc0 receipt encoding changes.
+ * Only the receipt file is written, for guests whose journal isn't `Outputs`.
+ **/
+pub fn write_receipt(
+    path: &Path,
+    receipt: &Receipt,
+    image_id: &[u32; 8],
+) -> Result<(), Box<dyn Error>> {
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(RECEIPT_MAGIC);
+    bytes.extend_from_slice(&RECEIPT_VERSION.to_le_bytes());
+    for word in image_id {
+        bytes.extend_from_slice(&word.to_le_bytes());
+    }
+    bytes.extend_from_slice(&bincode::serialize(receipt)?);
+    // Write then rename so an interrupted write never leaves a truncated receipt behind.
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, bytes)?;
+    std::fs::rename(tmp, path)?;
+
+    Ok(())
+}
+
+pub fn load_receipt(path: &Path) -> Result<SavedReceipt, Box<dyn Error>> {
+    let bytes = std::fs::read(path)?;
+    let header_len = RECEIPT_MAGIC.len() + 4 + 32;
+    if bytes.len() < header_len || &bytes[..4] != RECEIPT_MAGIC {
+        return Err(format!("{} is not a receipt file", path.display()).into());
+    }
+
+    let version = u32::from_le_bytes(bytes[4..8].try_into()?);
+    if version != RECEIPT_VERSION {
+        return Err(format!(
+            "unsupported receipt file version {}, expected {}",
+            version, RECEIPT_VERSION
+        )
+        .into());
+    }
+
+    let mut image_id = [0u32; 8];
+    for (word, chunk) in image_id.iter_mut().zip(bytes[8..header_len].chunks(4)) {
+        *word = u32::from_le_bytes(chunk.try_into()?);
+    }
+    let receipt = bincode::deserialize(&bytes[header_len..])?;
+
+    Ok(SavedReceipt { image_id, receipt })
+}
+
+pub fn save_journal(
+    path: &Path,
+    journal: &[u8],
+    outputs: &Outputs,
+    image_id: &[u32; 8],
+) -> Result<(), Box<dyn Error>> {
+    let file = JournalFile {
+        version: JOURNAL_VERSION,
+        image_id: image_id_hex(image_id),
+        journal: to_hex(journal),
+        outputs: outputs.into(),
+    };
+    std::fs::write(path, serde_json::to_string_pretty(&file)?)?;
+
+    Ok(())
+}
+
+/**
+ * Load a journal file, decoding the outputs from the raw journal rather than trusting
+ * the JSON view.
+ **/
+pub fn load_journal(path: &Path) -> Result<(JournalFile, Outputs), Box<dyn Error>> {
+    let file: JournalFile = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+    if file.version != JOURNAL_VERSION {
+        return Err(format!("unsupported journal file version {}", file.version).into());
+    }
+
+    let journal = hex::decode(file.journal.trim_start_matches("0x"))?;
+    let outputs = journal_outputs(&journal)?;
+
+    Ok((file, outputs))
+}
@@ -0,0 +1,134 @@
+use std::error::Error;
+use std::fmt;
+
+use trie_core::Outputs;
+
+pub mod artifacts;
+
+use artifacts::{decode_chunked, decode_outputs, image_id_hex, to_hex, SavedReceipt};
+
+#[derive(Debug)]
+pub enum VerifyError {
+    ImageIdMismatch { expected: String, found: String },
+    InvalidProof(String),
+    InvalidJournal(String),
+    ReceiptsRootMismatch { expected: String, found: String },
+    RegistryHashMismatch { expected: String, found: String },
+}
+
+impl Error for VerifyError {}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VerifyError::ImageIdMismatch { expected, found } => write!(
+                f,
+                "receipt was saved for image ID {}, expected {}",
+                found, expected
+            ),
+            VerifyError::InvalidProof(err) => write!(f, "invalid proof: {}", err),
+            VerifyError::InvalidJournal(err) => write!(f, "invalid journal: {}", err),
+            VerifyError::ReceiptsRootMismatch { expected, found } => write!(
+                f,
+                "receipts root mismatch: expected {}, found {}",
+                expected, found
+            ),
+            VerifyError::RegistryHashMismatch { expected, found } => write!(
+                f,
+                "registry hash mismatch: expected {}, found {}",
+                expected, found
+            ),
+        }
+    }
+}
+
+/**
+ * Values a verifier may pin on top of the proof itself.
+ **/
+#[derive(Debug, Default)]
+pub struct Expected {
+    pub receipts_root: Option<[u8; 32]>,
+    pub registry_hash: Option<[u8; 32]>,
+}
+
+/**
+ * Check a saved receipt was produced for `image_id`, verify the proof and decode its outputs.
+ * Receipts of the combine guest go through `verify_chunked_receipt`, their journal isn't
+ * plain outputs.
+ **/
+pub fn verify_receipt(saved: &SavedReceipt, image_id: &[u32; 8]) -> Result<Outputs, VerifyError> {
+    verify_proof(saved, image_id)?;
+
+    decode_outputs(&saved.receipt.journal.bytes)
+        .map_err(|err| VerifyError::InvalidJournal(err.to_string()))
+}
+
+/**
+ * Verify a receipt of the combine guest `image_id`. The combine guest verifies the chunk
+ * receipts against the chunk image ID it is given, so the one it commits must be the chunk
+ * guest `chunk_image_id` the verifier trusts.
+ **/
+pub fn verify_chunked_receipt(
+    saved: &SavedReceipt,
+    image_id: &[u32; 8],
+    chunk_image_id: &[u32; 8],
+) -> Result<Outputs, VerifyError> {
+    verify_proof(saved, image_id)?;
+
+    decode_chunked_journal(&saved.receipt.journal.bytes, chunk_image_id)
+}
+
+/**
+ * Decode the journal of the combine guest and check it verified its chunks against
+ * `chunk_image_id`.
+ **/
+pub fn decode_chunked_journal(
+    journal: &[u8],
+    chunk_image_id: &[u32; 8],
+) -> Result<Outputs, VerifyError> {
+    let chunked =
+        decode_chunked(journal).map_err(|err| VerifyError::InvalidJournal(err.to_string()))?;
+    if &chunked.chunk_image_id != chunk_image_id {
+        return Err(VerifyError::ImageIdMismatch {
+            expected: image_id_hex(chunk_image_id),
+            found: image_id_hex(&chunked.chunk_image_id),
+        });
+    }
+
+    Ok(chunked.outputs)
+}
+
+fn verify_proof(saved: &SavedReceipt, image_id: &[u32; 8]) -> Result<(), VerifyError> {
+    if &saved.image_id != image_id {
+        return Err(VerifyError::ImageIdMismatch {
+            expected: image_id_hex(image_id),
+            found: image_id_hex(&saved.image_id),
+        });
+    }
+
+    saved
+        .receipt
+        .verify(*image_id)
+        .map_err(|err| VerifyError::InvalidProof(err.to_string()))
+}
+
+pub fn check_expected(outputs: &Outputs, expected: &Expected) -> Result<(), VerifyError> {
+    if let Some(receipts_root) = expected.receipts_root {
+        if outputs.root != receipts_root {
+            return Err(VerifyError::ReceiptsRootMismatch {
+                expected: to_hex(&receipts_root),
+                found: to_hex(&outputs.root),
+            });
+        }
+    }
+    if let Some(registry_hash) = expected.registry_hash {
+        if outputs.registry_hash != registry_hash {
+            return Err(VerifyError::RegistryHashMismatch {
+                expected: to_hex(&registry_hash),
+                found: to_hex(&outputs.registry_hash),
+            });
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,65 @@
+use clap::Parser;
+use std::error::Error;
+use std::path::PathBuf;
+
+use verifier::artifacts::{load_receipt, parse_hex, parse_image_id, save_journal};
+use verifier::{check_expected, verify_chunked_receipt, verify_receipt, Expected};
+
+/// Verify a saved product tree receipt, exits with a non-zero code on any mismatch
+#[derive(Parser)]
+struct Cli {
+    #[arg(long, default_value = "receipt.bin")]
+    receipt: PathBuf,
+    /// Expected image ID of the guest, hex encoded
+    #[arg(long)]
+    image_id: String,
+    /// Expected image ID of the chunk guest, hex encoded, for receipts of the combine guest
+    /// (`prove --chunked`). Their journal must commit it
+    #[arg(long)]
+    chunk_image_id: Option<String>,
+    /// Expected receipts root, usually the block header's `receiptsRoot`
+    #[arg(long)]
+    receipts_root: Option<String>,
+    /// Expected registry hash, as printed by `host registry hash`
+    #[arg(long)]
+    registry_hash: Option<String>,
+    /// Write the journal and its decoded outputs as JSON
+    #[arg(long)]
+    output: Option<PathBuf>,
+}
+
+fn run(cli: Cli) -> Result<(), Box<dyn Error>> {
+    let image_id = parse_image_id(&cli.image_id)?;
+    let expected = Expected {
+        receipts_root: cli.receipts_root.as_deref().map(parse_hex).transpose()?,
+        registry_hash: cli.registry_hash.as_deref().map(parse_hex).transpose()?,
+    };
+
+    let saved = load_receipt(&cli.receipt)?;
+    let outputs = match cli
+        .chunk_image_id
+        .as_deref()
+        .map(parse_image_id)
+        .transpose()?
+    {
+        Some(chunk_image_id) => verify_chunked_receipt(&saved, &image_id, &chunk_image_id)?,
+        None => verify_receipt(&saved, &image_id)?,
+    };
+    check_expected(&outputs, &expected)?;
+
+    println!("Receipt verified");
+    println!("Outputs: {:?}", outputs);
+    if let Some(output) = cli.output {
+        save_journal(&output, &saved.receipt.journal.bytes, &outputs, &image_id)?;
+        println!("Journal written to {}", output.display());
+    }
+
+    Ok(())
+}
+
+fn main() {
+    if let Err(err) = run(Cli::parse()) {
+        eprintln!("Error: {}", err);
+        std::process::exit(1);
+    }
+}